@@ -10,6 +10,8 @@ extern crate lazy_static;
 extern crate spin;
 extern crate linked_list_allocator;
 
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
 use spin::Mutex;
 use linked_list_allocator::Heap;
 
@@ -21,14 +23,24 @@ lazy_static! {
         Mutex::new(unsafe { Heap::new(HEAP_START, HEAP_SIZE) });
 }
 
+static USED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Bytes currently handed out, not counting allocator overhead.
+pub fn used() -> usize {
+    USED.load(Ordering::Relaxed)
+}
+
 #[no_mangle]
 pub extern fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
-    HEAP.lock().allocate_first_fit(size, align).expect("out of memory")
+    let ptr = HEAP.lock().allocate_first_fit(size, align).expect("out of memory");
+    USED.fetch_add(size, Ordering::Relaxed);
+    ptr
 }
 
 #[no_mangle]
 pub extern fn __rust_deallocate(ptr: *mut u8, size: usize, align: usize) {
     unsafe { HEAP.lock().deallocate(ptr, size, align) };
+    USED.fetch_sub(size, Ordering::Relaxed);
 }
 
 #[no_mangle]
@@ -0,0 +1,206 @@
+// Locates the ACPI system description tables so that drivers can look up
+// the ones they need by signature. The AML namespace is not interpreted.
+
+use core::cmp::max;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use collections::vec::Vec;
+use multiboot2::BootInformation;
+use spin::Mutex;
+
+use boot;
+use mem;
+use mem::paging::{PhysicalAddress, NO_EXECUTE};
+
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+
+// the BIOS read-only area that is searched when the bootloader did not pass
+// the RSDP along
+const BIOS_AREA_START: PhysicalAddress = 0xe0000;
+const BIOS_AREA_END: PhysicalAddress = 0x100000;
+
+const MAX_TABLE_SIZE: usize = 1 << 20;
+
+// the MADT's interrupt controller structures follow the local APIC address
+// and flags
+const MADT_ENTRIES: usize = HEADER_SIZE + 8;
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_LOCAL_X2APIC: u8 = 9;
+const MADT_ENABLED: u32 = 1 << 0;
+
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // the fields below only exist from revision 2 on
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+const RSDP_V1_SIZE: usize = 20;
+
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+pub const HEADER_SIZE: usize = 36;
+
+lazy_static! {
+    static ref TABLES: Mutex<Vec<&'static [u8]>> = Mutex::new(Vec::new());
+}
+
+// Kept apart from the tables so that it can be read without taking a lock.
+static CPU_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub fn init(boot_info: &BootInformation) {
+    assert_has_not_been_called!();
+
+    let rsdp = match find_rsdp(boot_info) {
+        Some(rsdp) => rsdp,
+        None => {
+            warn!("acpi: no RSDP found");
+            return;
+        }
+    };
+
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address as usize, 8)
+    } else {
+        (rsdp.rsdt_address as usize, 4)
+    };
+    let root = match map_table(root) {
+        Some(root) => root,
+        None => {
+            error!("acpi: root table at {:#x} is invalid", root);
+            return;
+        }
+    };
+
+    let mut tables = TABLES.lock();
+    for entry in root[HEADER_SIZE..].chunks(entry_size) {
+        if entry.len() != entry_size {
+            break;
+        }
+        let address = entry.iter().rev().fold(0, |address, &byte| address << 8 | byte as usize);
+        match map_table(address) {
+            Some(table) => tables.push(table),
+            None => warn!("acpi: skipping invalid table at {:#x}", address),
+        }
+    }
+
+    if let Some(madt) = tables.iter().find(|table| &table[..4] == b"APIC") {
+        CPU_COUNT.store(count_cpus(madt), Ordering::Relaxed);
+    }
+}
+
+// The number of usable processors, 1 when the firmware does not say.
+pub fn cpu_count() -> usize {
+    max(1, CPU_COUNT.load(Ordering::Relaxed))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes[offset..offset + 4].iter().rev().fold(0, |value, &byte| value << 8 | byte as u32)
+}
+
+// Counts the enabled processors among the MADT's local APIC structures.
+fn count_cpus(madt: &[u8]) -> usize {
+    let mut count = 0;
+    let mut offset = MADT_ENTRIES;
+    while offset + 2 <= madt.len() {
+        let (typ, len) = (madt[offset], madt[offset + 1] as usize);
+        if len < 2 || offset + len > madt.len() {
+            break;
+        }
+        let entry = &madt[offset..offset + len];
+        let flags = match typ {
+            MADT_LOCAL_APIC if len >= 8 => u32_at(entry, 4),
+            MADT_LOCAL_X2APIC if len >= 12 => u32_at(entry, 8),
+            _ => 0,
+        };
+        if flags & MADT_ENABLED != 0 {
+            count += 1;
+        }
+        offset += len;
+    }
+    count
+}
+
+// The whole table, header included, or None if no table has the signature.
+pub fn find(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    TABLES.lock().iter().find(|table| &table[..4] == signature).cloned()
+}
+
+pub fn header(table: &'static [u8]) -> &'static SdtHeader {
+    assert!(table.len() >= HEADER_SIZE);
+    unsafe { &*(table.as_ptr() as *const SdtHeader) }
+}
+
+pub fn signatures() -> Vec<[u8; 4]> {
+    TABLES.lock().iter().map(|table| header(table).signature).collect()
+}
+
+fn find_rsdp(boot_info: &BootInformation) -> Option<&'static Rsdp> {
+    // the bootloader copies the RSDP into the multiboot information
+    let tag = boot::tags(boot_info).find(|tag| tag.typ == TAG_ACPI_NEW || tag.typ == TAG_ACPI_OLD);
+    if let Some(tag) = tag {
+        let address = tag as *const boot::Tag as usize + ::core::mem::size_of::<boot::Tag>();
+        if let Some(rsdp) = validate_rsdp(address) {
+            return Some(rsdp);
+        }
+    }
+
+    mem::with_controller(|mc| mc.identity_map(BIOS_AREA_START, BIOS_AREA_END, NO_EXECUTE));
+    (0..(BIOS_AREA_END - BIOS_AREA_START) / 16)
+        .map(|index| BIOS_AREA_START + index * 16)
+        .filter_map(validate_rsdp)
+        .next()
+}
+
+fn validate_rsdp(address: PhysicalAddress) -> Option<&'static Rsdp> {
+    let rsdp = unsafe { &*(address as *const Rsdp) };
+    if &rsdp.signature != b"RSD PTR " || !checksum(address, RSDP_V1_SIZE) {
+        return None;
+    }
+    if rsdp.revision >= 2 && !checksum(address, rsdp.length as usize) {
+        return None;
+    }
+    Some(rsdp)
+}
+
+// Maps the table at `address` and returns it once its checksum is verified.
+fn map_table(address: PhysicalAddress) -> Option<&'static [u8]> {
+    if address == 0 {
+        return None;
+    }
+    mem::with_controller(|mc| mc.identity_map(address, address + HEADER_SIZE, NO_EXECUTE));
+    let length = unsafe { (*(address as *const SdtHeader)).length as usize };
+    if length < HEADER_SIZE || length > MAX_TABLE_SIZE {
+        return None;
+    }
+    mem::with_controller(|mc| mc.identity_map(address, address + length, NO_EXECUTE));
+    if !checksum(address, length) {
+        return None;
+    }
+    Some(unsafe { slice::from_raw_parts(address as *const u8, length) })
+}
+
+fn checksum(address: PhysicalAddress, length: usize) -> bool {
+    let bytes = unsafe { slice::from_raw_parts(address as *const u8, length) };
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
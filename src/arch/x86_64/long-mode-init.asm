@@ -11,6 +11,8 @@ bits 64
 ;   call into rust
 long_mode_start:
    call mezzo64
+   ; a null frame pointer ends backtraces
+   xor rbp, rbp
    call __main__
    call os_exit
    hlt
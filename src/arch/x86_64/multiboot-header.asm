@@ -13,6 +13,17 @@ header_start:
 
    ; optional tags...
 
+   ; framebuffer: ask for a linear 1024x768 32-bit mode, the kernel falls
+   ; back to text mode if the boot loader cannot provide one
+   align 8, db 0
+   dw 5    ; type
+   dw 1    ; flags: optional
+   dd 20   ; size
+   dd 1024 ; width
+   dd 768  ; height
+   dd 32   ; depth
+
+   align 8, db 0
    dw 0x0  ; tags terminator
    dw 0x0  ;
    dd 0x8  ;
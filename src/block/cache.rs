@@ -0,0 +1,322 @@
+// A write-back cache in front of every block device. Blocks are grouped into
+// page sized lines keyed by (device, line number), each line held in a frame
+// of its own mapped into a stretch of address space set aside for the cache.
+// Lines are evicted least recently used first, and are given back to the
+// frame allocator when memory runs low.
+
+use core::cmp::min;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use collections::btree_map::BTreeMap;
+use collections::vec::Vec;
+use spin::Mutex;
+
+use mem::{self, PAGE_SIZE};
+use mem::paging::{self, Page, VirtualAddress};
+use super::{check_range, BlockDevice, Result};
+
+// lines live at 320 GiB, above the DMA area
+const CACHE_AREA_START: VirtualAddress = 0o000_500_000_000_0000;
+const MAX_LINES: usize = 1024;
+
+// lines read beyond a miss that continues a sequential run
+const READ_AHEAD: u64 = 8;
+
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref CACHE: Mutex<Cache> = Mutex::new(Cache {
+        lines: Vec::new(),
+        index: BTreeMap::new(),
+        clock: 0,
+    });
+}
+
+pub fn init() {
+    mem::register_shrinker(shrink);
+}
+
+// The number of lines holding data and how many of those are dirty.
+pub fn stats() -> (usize, usize) {
+    let cache = CACHE.lock();
+    let dirty = cache.lines.iter().filter(|line| line.dirty).count();
+    (cache.index.len(), dirty)
+}
+
+struct Line {
+    page: Page,
+    mapped: bool,
+    key: Option<(usize, u64)>,
+    device: Option<Arc<BlockDevice>>,
+    dirty: bool,
+    last_used: u64,
+}
+
+impl Line {
+    fn data(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.page.start() as *mut u8, PAGE_SIZE) }
+    }
+
+    fn write_back(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let (Some((_, line)), Some(device)) = (self.key, self.device.clone()) {
+            let len = line_len(&*device, line);
+            device.write(line_blocks(&*device, line).0, &self.data()[..len])?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn blocks_per_line(device: &BlockDevice) -> u64 {
+    (PAGE_SIZE / device.block_size()) as u64
+}
+
+// The first block of `line` and how many blocks it holds, which is less than
+// a full line at the end of the device.
+fn line_blocks(device: &BlockDevice, line: u64) -> (u64, u64) {
+    let first = line * blocks_per_line(device);
+    (first, min(blocks_per_line(device), device.block_count() - first))
+}
+
+fn line_len(device: &BlockDevice, line: u64) -> usize {
+    line_blocks(device, line).1 as usize * device.block_size()
+}
+
+struct Cache {
+    lines: Vec<Line>,
+    index: BTreeMap<(usize, u64), usize>,
+    clock: u64,
+}
+
+impl Cache {
+    fn touch(&mut self, index: usize) {
+        self.clock += 1;
+        self.lines[index].last_used = self.clock;
+    }
+
+    fn least_recently_used(&self) -> Option<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|&(_, line)| line.mapped)
+            .min_by_key(|&(_, line)| line.last_used)
+            .map(|(index, _)| index)
+    }
+
+    // Drops what a line holds, writing it back first if it is dirty.
+    fn evict(&mut self, index: usize) -> Result<()> {
+        self.lines[index].write_back()?;
+        if let Some(key) = self.lines[index].key.take() {
+            self.index.remove(&key);
+        }
+        self.lines[index].device = None;
+        self.lines[index].last_used = 0;
+        Ok(())
+    }
+
+    // Finds a line to hold new data: a fresh frame while memory allows, the
+    // least recently used line otherwise.
+    fn allocate(&mut self) -> Result<usize> {
+        if mem::free_frames() <= mem::LOW_WATERMARK {
+            if let Some(index) = self.least_recently_used() {
+                self.evict(index)?;
+                return Ok(index);
+            }
+        }
+
+        let unmapped = self.lines.iter().position(|line| !line.mapped);
+        let index = match unmapped {
+            Some(index) => index,
+            None if self.lines.len() < MAX_LINES => {
+                let page = Page::containing(CACHE_AREA_START) + self.lines.len();
+                self.lines.push(Line {
+                    page: page,
+                    mapped: false,
+                    key: None,
+                    device: None,
+                    dirty: false,
+                    last_used: 0,
+                });
+                self.lines.len() - 1
+            }
+            None => {
+                let index = self.least_recently_used().expect("block cache has no lines");
+                self.evict(index)?;
+                return Ok(index);
+            }
+        };
+
+        let page = self.lines[index].page;
+        mem::with_controller(|mc| mc.map(page, paging::WRITABLE | paging::NO_EXECUTE));
+        self.lines[index].mapped = true;
+        Ok(index)
+    }
+
+    fn insert(&mut self, index: usize, device: &CachedDevice, line: u64) {
+        let key = (device.id, line);
+        self.lines[index].key = Some(key);
+        self.lines[index].device = Some(device.device.clone());
+        self.lines[index].dirty = false;
+        self.touch(index);
+        self.index.insert(key, index);
+    }
+
+    // Returns the line holding `line` of `device` and whether it missed. On a
+    // miss the data is read in only if `fill` is set.
+    fn get(&mut self, device: &CachedDevice, line: u64, fill: bool) -> Result<(usize, bool)> {
+        if let Some(&index) = self.index.get(&(device.id, line)) {
+            self.touch(index);
+            return Ok((index, false));
+        }
+
+        let index = self.allocate()?;
+        if fill {
+            let (block, _) = line_blocks(&*device.device, line);
+            let len = line_len(&*device.device, line);
+            device.device.read(block, &mut self.lines[index].data()[..len])?;
+        }
+        self.insert(index, device, line);
+        Ok((index, true))
+    }
+
+    // Reads up to READ_AHEAD lines starting at `line` that are not cached
+    // yet, returning the line after the last one read.
+    fn read_ahead(&mut self, device: &CachedDevice, line: u64) -> u64 {
+        let lines = (device.device.block_count() + blocks_per_line(&*device.device) - 1) /
+                    blocks_per_line(&*device.device);
+        let end = min(line + READ_AHEAD, lines);
+        for next in line..end {
+            if self.index.contains_key(&(device.id, next)) || self.get(device, next, true).is_err() {
+                return next;
+            }
+        }
+        end
+    }
+
+    // Writes back the dirty lines of the device with `id`, or of every
+    // device. The first error is returned once all lines were tried.
+    fn write_back(&mut self, id: Option<usize>) -> Result<()> {
+        let mut result = Ok(());
+        for line in self.lines.iter_mut().filter(|line| line.dirty) {
+            if id.map_or(true, |id| line.key.map_or(false, |(device, _)| device == id)) {
+                let written = line.write_back();
+                if result.is_ok() {
+                    result = written;
+                }
+            }
+        }
+        result
+    }
+}
+
+// Gives frames back to the allocator under memory pressure. Nothing is freed
+// while the cache is in use, as that is what is allocating.
+fn shrink(wanted: usize) -> usize {
+    let mut cache = match CACHE.try_lock() {
+        Some(cache) => cache,
+        None => return 0,
+    };
+    let mut freed = 0;
+    while freed < wanted {
+        let index = match cache.least_recently_used() {
+            Some(index) => index,
+            None => break,
+        };
+        if cache.evict(index).is_err() {
+            break;
+        }
+        let page = cache.lines[index].page;
+        mem::with_controller(|mc| mc.unmap(page));
+        cache.lines[index].mapped = false;
+        freed += 1;
+    }
+    freed
+}
+
+// A block device whose reads and writes go through the cache. Writes only
+// reach the device on flush, on eviction or when the cache shrinks.
+pub struct CachedDevice {
+    id: usize,
+    device: Arc<BlockDevice>,
+    // the line a miss has to be at to continue a sequential run
+    next_miss: AtomicUsize,
+}
+
+impl CachedDevice {
+    pub fn new(device: Arc<BlockDevice>) -> CachedDevice {
+        assert!(device.block_size() <= PAGE_SIZE && PAGE_SIZE % device.block_size() == 0,
+                "block size does not fit the cache");
+        CachedDevice {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            device: device,
+            next_miss: AtomicUsize::new(0),
+        }
+    }
+
+    // Splits a transfer of `len` bytes at `block` into per-line pieces of
+    // (line number, offset within the line, length, bytes done).
+    fn pieces(&self, block: u64, len: usize) -> Vec<(u64, usize, usize, usize)> {
+        let block_size = self.device.block_size();
+        let per_line = blocks_per_line(&*self.device);
+        let mut pieces = Vec::new();
+        let mut done = 0;
+        while done < len {
+            let position = block + (done / block_size) as u64;
+            let within = (position % per_line) as usize * block_size;
+            let count = min(PAGE_SIZE - within, len - done);
+            pieces.push((position / per_line, within, count, done));
+            done += count;
+        }
+        pieces
+    }
+}
+
+impl BlockDevice for CachedDevice {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read(&self, block: u64, buf: &mut [u8]) -> Result<()> {
+        check_range(self, block, buf.len())?;
+        let mut cache = CACHE.lock();
+        for (line, within, count, done) in self.pieces(block, buf.len()) {
+            let (index, missed) = cache.get(self, line, true)?;
+            buf[done..done + count]
+                .copy_from_slice(&cache.lines[index].data()[within..within + count]);
+
+            if missed && self.next_miss.swap(line as usize + 1, Ordering::Relaxed) == line as usize {
+                let end = cache.read_ahead(self, line + 1);
+                self.next_miss.store(end as usize, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, block: u64, buf: &[u8]) -> Result<()> {
+        check_range(self, block, buf.len())?;
+        let mut cache = CACHE.lock();
+        for (line, within, count, done) in self.pieces(block, buf.len()) {
+            // a line that is overwritten completely need not be read first
+            let whole = within == 0 && count == line_len(&*self.device, line);
+            let (index, _) = cache.get(self, line, !whole)?;
+            let cached = &mut cache.lines[index];
+            cached.data()[within..within + count].copy_from_slice(&buf[done..done + count]);
+            cached.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        CACHE.lock().write_back(Some(self.id))?;
+        self.device.flush()
+    }
+}
@@ -0,0 +1,186 @@
+// Block devices: disks that are read and written in whole blocks. Drivers
+// register their devices here, filesystems look them up by name, and every
+// device also appears under /dev for byte-oriented access. Both go through
+// the block cache.
+
+pub mod cache;
+
+use core::cmp::min;
+use core::result;
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use spin::Mutex;
+
+use fs::{self, devfs, FileType};
+use fs::devfs::CharDevice;
+use self::cache::CachedDevice;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    OutOfRange,
+    Misaligned,
+    ReadOnly,
+    Timeout,
+    Device,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl From<Error> for fs::Error {
+    fn from(error: Error) -> fs::Error {
+        match error {
+            Error::OutOfRange | Error::Misaligned => fs::Error::InvalidArgument,
+            Error::ReadOnly => fs::Error::ReadOnly,
+            Error::Timeout | Error::Device => fs::Error::Io,
+        }
+    }
+}
+
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+
+    // `buf` must hold a whole number of blocks, starting at `block`.
+    fn read(&self, block: u64, buf: &mut [u8]) -> Result<()>;
+    fn write(&self, block: u64, buf: &[u8]) -> Result<()>;
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Checks a transfer of `len` bytes at `block`, returning its length in blocks.
+pub fn check_range(device: &BlockDevice, block: u64, len: usize) -> Result<u64> {
+    if len % device.block_size() != 0 {
+        return Err(Error::Misaligned);
+    }
+    let count = (len / device.block_size()) as u64;
+    match block.checked_add(count) {
+        Some(end) if end <= device.block_count() => Ok(count),
+        _ => Err(Error::OutOfRange),
+    }
+}
+
+struct Entry {
+    name: String,
+    device: Arc<BlockDevice>,
+}
+
+lazy_static! {
+    static ref DEVICES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+}
+
+pub fn register(name: &str, device: Arc<BlockDevice>) {
+    let device: Arc<BlockDevice> = Arc::new(CachedDevice::new(device));
+    {
+        let mut devices = DEVICES.lock();
+        assert!(devices.iter().all(|entry| entry.name != name), "block device registered twice");
+        devices.push(Entry {
+            name: name.to_string(),
+            device: device.clone(),
+        });
+    }
+    devfs::register_node(name, FileType::BlockDevice, Arc::new(DeviceFile(device)));
+}
+
+pub fn get(name: &str) -> Option<Arc<BlockDevice>> {
+    DEVICES.lock().iter().find(|entry| entry.name == name).map(|entry| entry.device.clone())
+}
+
+pub fn devices() -> Vec<(String, Arc<BlockDevice>)> {
+    DEVICES.lock().iter().map(|entry| (entry.name.clone(), entry.device.clone())).collect()
+}
+
+// Writes every dirty cached block back and flushes the devices.
+pub fn sync() -> Result<()> {
+    let mut result = Ok(());
+    for (_, device) in devices() {
+        let flushed = device.flush();
+        if result.is_ok() {
+            result = flushed;
+        }
+    }
+    result
+}
+
+// Reads `buf.len()` bytes at byte `offset`, for callers that do not work in
+// whole blocks. Partial blocks at either end go through a bounce buffer.
+pub fn read_bytes(device: &BlockDevice, offset: u64, buf: &mut [u8]) -> Result<()> {
+    let block_size = device.block_size();
+    let mut bounce = Vec::new();
+    let mut done = 0;
+    while done < buf.len() {
+        let position = offset + done as u64;
+        let block = position / block_size as u64;
+        let within = (position % block_size as u64) as usize;
+        let remaining = buf.len() - done;
+        if within == 0 && remaining >= block_size {
+            let count = remaining / block_size * block_size;
+            device.read(block, &mut buf[done..done + count])?;
+            done += count;
+        } else {
+            let count = min(block_size - within, remaining);
+            bounce.resize(block_size, 0);
+            device.read(block, &mut bounce)?;
+            buf[done..done + count].copy_from_slice(&bounce[within..within + count]);
+            done += count;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_bytes(device: &BlockDevice, offset: u64, buf: &[u8]) -> Result<()> {
+    let block_size = device.block_size();
+    let mut bounce = Vec::new();
+    let mut done = 0;
+    while done < buf.len() {
+        let position = offset + done as u64;
+        let block = position / block_size as u64;
+        let within = (position % block_size as u64) as usize;
+        let remaining = buf.len() - done;
+        if within == 0 && remaining >= block_size {
+            let count = remaining / block_size * block_size;
+            device.write(block, &buf[done..done + count])?;
+            done += count;
+        } else {
+            let count = min(block_size - within, remaining);
+            bounce.resize(block_size, 0);
+            device.read(block, &mut bounce)?;
+            bounce[within..within + count].copy_from_slice(&buf[done..done + count]);
+            device.write(block, &bounce)?;
+            done += count;
+        }
+    }
+    Ok(())
+}
+
+// Byte-granular access to a block device for its /dev node.
+struct DeviceFile(Arc<BlockDevice>);
+
+impl DeviceFile {
+    // The part of a `len` byte transfer at `offset` that lies on the device.
+    fn clamp(&self, offset: u64, len: usize) -> usize {
+        let size = self.size();
+        if offset >= size { 0 } else { min(len as u64, size - offset) as usize }
+    }
+}
+
+impl CharDevice for DeviceFile {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        let len = self.clamp(offset, buf.len());
+        read_bytes(&*self.0, offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        let len = self.clamp(offset, buf.len());
+        write_bytes(&*self.0, offset, &buf[..len])?;
+        Ok(len)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.block_count() * self.0.block_size() as u64
+    }
+}
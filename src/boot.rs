@@ -0,0 +1,272 @@
+// Access to the multiboot2 tags that the multiboot2 crate does not parse.
+
+use core::{slice, str};
+
+use collections::vec::Vec;
+use multiboot2::BootInformation;
+use spin::Mutex;
+
+use elf::{self, SectionHeader};
+use mem::paging::PhysicalAddress;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_MODULE: u32 = 3;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_ELF_SECTIONS: u32 = 9;
+
+#[repr(C)]
+pub struct Tag {
+    pub typ: u32,
+    pub size: u32,
+}
+
+pub struct TagIter {
+    current: *const Tag,
+}
+
+impl Iterator for TagIter {
+    type Item = &'static Tag;
+
+    fn next(&mut self) -> Option<&'static Tag> {
+        let tag = unsafe { &*self.current };
+        if tag.typ == TAG_END {
+            return None;
+        }
+        let next = self.current as usize + ((tag.size as usize + 7) & !7);
+        self.current = next as *const Tag;
+        Some(tag)
+    }
+}
+
+pub fn tags(boot_info: &BootInformation) -> TagIter {
+    // the tags follow the total_size and reserved fields
+    TagIter { current: (boot_info.start_address() + 8) as *const Tag }
+}
+
+// The string that follows a tag's header, up to its null terminator.
+fn tag_string(tag: &'static Tag, header_size: usize) -> &'static str {
+    unsafe {
+        let start = (tag as *const Tag as *const u8).offset(header_size as isize);
+        let max_len = tag.size as usize - header_size;
+        let bytes = slice::from_raw_parts(start, max_len);
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(max_len);
+        str::from_utf8(&bytes[..len]).unwrap_or("")
+    }
+}
+
+#[repr(C)]
+struct ModuleTag {
+    typ: u32,
+    size: u32,
+    mod_start: u32,
+    mod_end: u32,
+    // followed by a null terminated command line
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    start: PhysicalAddress,
+    end: PhysicalAddress,
+    cmdline: &'static str,
+}
+
+impl Module {
+    fn from_tag(tag: &'static Tag) -> Module {
+        let module = unsafe { &*(tag as *const Tag as *const ModuleTag) };
+        Module {
+            start: module.mod_start as usize,
+            end: module.mod_end as usize,
+            cmdline: tag_string(tag, ::core::mem::size_of::<ModuleTag>()),
+        }
+    }
+
+    pub fn start_address(&self) -> PhysicalAddress {
+        self.start
+    }
+
+    pub fn end_address(&self) -> PhysicalAddress {
+        self.end
+    }
+
+    pub fn cmdline(&self) -> &'static str {
+        self.cmdline
+    }
+
+    // The last word of the command line, without any leading path, so that
+    // both `module2 /boot/initrd.tar` and `module2 /boot/x.tar initrd` work.
+    pub fn name(&self) -> &'static str {
+        let word = self.cmdline.split_whitespace().last().unwrap_or("");
+        word.rsplit('/').next().unwrap_or(word)
+    }
+
+    pub fn is_named(&self, name: &str) -> bool {
+        self.cmdline
+            .split_whitespace()
+            .any(|word| word == name || word.rsplit('/').next() == Some(name))
+    }
+
+    // Modules are identity mapped read-only by `remap_kernel`.
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { slice::from_raw_parts(self.start as *const u8, self.end - self.start) }
+    }
+}
+
+pub fn modules(boot_info: &BootInformation) -> ModuleIter {
+    ModuleIter { tags: tags(boot_info) }
+}
+
+pub struct ModuleIter {
+    tags: TagIter,
+}
+
+impl Iterator for ModuleIter {
+    type Item = Module;
+
+    fn next(&mut self) -> Option<Module> {
+        self.tags
+            .by_ref()
+            .find(|tag| tag.typ == TAG_MODULE)
+            .map(Module::from_tag)
+    }
+}
+
+#[repr(C)]
+struct FramebufferTag {
+    typ: u32,
+    size: u32,
+    address: u64,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    framebuffer_type: u8,
+    reserved: u16,
+    // for direct RGB modes, the position and size of each color field
+    red_position: u8,
+    red_size: u8,
+    green_position: u8,
+    green_size: u8,
+    blue_position: u8,
+    blue_size: u8,
+}
+
+const FRAMEBUFFER_RGB: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    pub address: PhysicalAddress,
+    pub pitch: usize,
+    pub width: usize,
+    pub height: usize,
+    pub bpp: usize,
+    // (position, size) of the red, green and blue fields, for RGB modes only;
+    // indexed color and EGA text framebuffers have none
+    pub fields: Option<[(u8, u8); 3]>,
+}
+
+// The framebuffer the boot loader set up, if it set up one.
+pub fn framebuffer(boot_info: &BootInformation) -> Option<FramebufferInfo> {
+    tags(boot_info).find(|tag| tag.typ == TAG_FRAMEBUFFER).map(|tag| {
+        let fb = unsafe { &*(tag as *const Tag as *const FramebufferTag) };
+        let fields = if fb.framebuffer_type == FRAMEBUFFER_RGB {
+            Some([(fb.red_position, fb.red_size),
+                  (fb.green_position, fb.green_size),
+                  (fb.blue_position, fb.blue_size)])
+        } else {
+            None
+        };
+        FramebufferInfo {
+            address: fb.address as usize,
+            pitch: fb.pitch as usize,
+            width: fb.width as usize,
+            height: fb.height as usize,
+            bpp: fb.bpp as usize,
+            fields: fields,
+        }
+    })
+}
+
+#[repr(C)]
+struct ElfSectionsTag {
+    typ: u32,
+    size: u32,
+    count: u32,
+    entry_size: u32,
+    string_table: u32,
+}
+
+// The section headers of the kernel. The multiboot2 crate hides the type and
+// link of a section, and skips the null one, which throws off the indices
+// links refer to. Empty if the headers are not the size expected, so that
+// backtraces go without names rather than the kernel stopping over them.
+pub fn kernel_sections(boot_info: &BootInformation) -> &'static [SectionHeader] {
+    match tags(boot_info).find(|tag| tag.typ == TAG_ELF_SECTIONS) {
+        Some(tag) => {
+            let elf = unsafe { &*(tag as *const Tag as *const ElfSectionsTag) };
+            if elf.entry_size as usize != ::core::mem::size_of::<SectionHeader>() {
+                return &[];
+            }
+            let first = (elf as *const ElfSectionsTag as usize +
+                         ::core::mem::size_of::<ElfSectionsTag>()) as *const SectionHeader;
+            unsafe { slice::from_raw_parts(first, elf.count as usize) }
+        }
+        None => &[],
+    }
+}
+
+// The kernel's symbol table and the string table of its names, which the boot
+// loader loads along with the rest of the kernel although nothing maps them.
+pub fn kernel_symbols(boot_info: &BootInformation)
+                      -> Option<(&'static SectionHeader, &'static SectionHeader)> {
+    let sections = kernel_sections(boot_info);
+    let symbols = match sections.iter().find(|section| section.typ == elf::SHT_SYMTAB) {
+        Some(symbols) => symbols,
+        None => return None,
+    };
+    match sections.get(symbols.link as usize) {
+        Some(names) if symbols.addr != 0 && names.addr != 0 => Some((symbols, names)),
+        _ => None,
+    }
+}
+
+lazy_static! {
+    static ref MODULES: Mutex<Vec<Module>> = Mutex::new(Vec::new());
+}
+
+// The kernel command line, which lives in the multiboot information.
+static CMDLINE: Mutex<&'static str> = Mutex::new("");
+
+pub fn init(boot_info: &BootInformation) {
+    assert_has_not_been_called!();
+    MODULES.lock().extend(modules(boot_info));
+    if let Some(tag) = tags(boot_info).find(|tag| tag.typ == TAG_CMDLINE) {
+        *CMDLINE.lock() = tag_string(tag, ::core::mem::size_of::<Tag>());
+    }
+}
+
+pub fn cmdline() -> &'static str {
+    *CMDLINE.lock()
+}
+
+// The value of the last `key=value` word on the kernel command line.
+pub fn option(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_whitespace()
+        .filter_map(|word| {
+            let mut parts = word.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == key => Some(value),
+                _ => None,
+            }
+        })
+        .last()
+}
+
+pub fn loaded_modules() -> Vec<Module> {
+    MODULES.lock().clone()
+}
+
+pub fn module(name: &str) -> Option<Module> {
+    MODULES.lock().iter().find(|m| m.is_named(name)).cloned()
+}
@@ -0,0 +1,415 @@
+// SATA disks behind an AHCI controller. Commands are issued from slot 0 of
+// each port and the data moves by DMA through a per-port bounce buffer, with
+// the controller's interrupt waking the thread waiting for completion.
+
+use core::ptr;
+use core::sync::atomic::{self, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use collections::string::String;
+use collections::vec::Vec;
+use spin::Mutex;
+
+use block::{self, BlockDevice, Error};
+use drivers::ata::Identity;
+use drivers::pci::{self, Bar, Device, Driver, CLASS_STORAGE};
+use int;
+use mem::{self, DmaBuffer};
+use mem::paging::{NO_CACHE, NO_EXECUTE, WRITABLE, WRITE_THROUGH};
+use time;
+
+const SUBCLASS_SATA: u8 = 0x06;
+const PROG_IF_AHCI: u8 = 0x01;
+const ABAR: usize = 5;
+
+const SECTOR_SIZE: usize = 512;
+
+// generic host control registers
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_IS: usize = 0x08;
+const HBA_PI: usize = 0x0c;
+
+const CAP_64BIT: u32 = 1 << 31;
+const GHC_INTERRUPT_ENABLE: u32 = 1 << 1;
+const GHC_AHCI_ENABLE: u32 = 1 << 31;
+
+// port registers, relative to the port's register block
+const PORTS: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PX_CLB: usize = 0x00;
+const PX_CLBU: usize = 0x04;
+const PX_FB: usize = 0x08;
+const PX_FBU: usize = 0x0c;
+const PX_IS: usize = 0x10;
+const PX_IE: usize = 0x14;
+const PX_CMD: usize = 0x18;
+const PX_TFD: usize = 0x20;
+const PX_SIG: usize = 0x24;
+const PX_SSTS: usize = 0x28;
+const PX_SERR: usize = 0x30;
+const PX_CI: usize = 0x38;
+
+const CMD_START: u32 = 1 << 0;
+const CMD_FIS_RECEIVE: u32 = 1 << 4;
+const CMD_FIS_RUNNING: u32 = 1 << 14;
+const CMD_LIST_RUNNING: u32 = 1 << 15;
+
+const IS_D2H_REGISTER: u32 = 1 << 0;
+const IS_PIO_SETUP: u32 = 1 << 1;
+const IS_ERRORS: u32 = 0xf << 27;
+
+const TFD_ERROR: u32 = 1 << 0;
+const TFD_DATA_REQUEST: u32 = 1 << 3;
+const TFD_BUSY: u32 = 1 << 7;
+
+const SSTS_PRESENT: u32 = 0x3;
+const SSTS_ACTIVE: u32 = 0x1 << 8;
+
+const SIGNATURE_ATA: u32 = 0x0000_0101;
+
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_FLUSH_CACHE_EXT: u8 = 0xea;
+const ATA_IDENTIFY: u8 = 0xec;
+
+// layout of the page holding a port's command structures
+const COMMAND_LIST: usize = 0x000;
+const RECEIVED_FIS: usize = 0x400;
+const COMMAND_TABLE: usize = 0x800;
+const PRDT: usize = COMMAND_TABLE + 0x80;
+
+const FIS_TYPE_H2D: u8 = 0x27;
+const FIS_LENGTH_DWORDS: u32 = 5;
+const HEADER_WRITE: u32 = 1 << 6;
+const PRD_INTERRUPT: u32 = 1 << 31;
+
+const BOUNCE_PAGES: usize = 16;
+
+const TIMEOUT_MS: u64 = 5000;
+
+static NEXT_DISK: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn read(address: usize) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn write(address: usize, value: u32) {
+    unsafe { ptr::write_volatile(address as *mut u32, value) }
+}
+
+struct Port {
+    registers: usize,
+    // interrupt status bits collected by the interrupt handler
+    events: Arc<AtomicUsize>,
+    memory: DmaBuffer,
+    bounce: DmaBuffer,
+}
+
+impl Port {
+    fn read(&self, register: usize) -> u32 {
+        read(self.registers + register)
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        write(self.registers + register, value)
+    }
+
+    fn wait_clear(&self, register: usize, bits: u32) -> block::Result<()> {
+        let deadline = time::uptime_ms() + TIMEOUT_MS;
+        while self.read(register) & bits != 0 {
+            if time::uptime_ms() > deadline {
+                return Err(Error::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> block::Result<()> {
+        let command = self.read(PX_CMD);
+        self.write(PX_CMD, command & !CMD_START);
+        self.wait_clear(PX_CMD, CMD_LIST_RUNNING)?;
+        self.write(PX_CMD, self.read(PX_CMD) & !CMD_FIS_RECEIVE);
+        self.wait_clear(PX_CMD, CMD_FIS_RUNNING)
+    }
+
+    fn start(&self) -> block::Result<()> {
+        let memory = self.memory.physical() as u64;
+        self.write(PX_CLB, (memory + COMMAND_LIST as u64) as u32);
+        self.write(PX_CLBU, ((memory + COMMAND_LIST as u64) >> 32) as u32);
+        self.write(PX_FB, (memory + RECEIVED_FIS as u64) as u32);
+        self.write(PX_FBU, ((memory + RECEIVED_FIS as u64) >> 32) as u32);
+
+        self.write(PX_SERR, 0xffff_ffff);
+        self.write(PX_IS, 0xffff_ffff);
+        self.write(PX_IE, IS_D2H_REGISTER | IS_PIO_SETUP | IS_ERRORS);
+
+        self.wait_clear(PX_CMD, CMD_LIST_RUNNING)?;
+        self.write(PX_CMD, self.read(PX_CMD) | CMD_FIS_RECEIVE);
+        self.write(PX_CMD, self.read(PX_CMD) | CMD_START);
+        Ok(())
+    }
+
+    // Runs one ATA command in slot 0, moving `bytes` bytes of the bounce
+    // buffer to or from the drive.
+    fn issue(&self, command: u8, lba: u64, count: usize, bytes: usize, to_device: bool)
+             -> block::Result<()> {
+        self.wait_clear(PX_TFD, TFD_BUSY | TFD_DATA_REQUEST)?;
+
+        let memory = self.memory.as_mut_slice();
+        for byte in memory[COMMAND_TABLE..].iter_mut() {
+            *byte = 0;
+        }
+
+        {
+            let fis = &mut memory[COMMAND_TABLE..COMMAND_TABLE + 20];
+            fis[0] = FIS_TYPE_H2D;
+            fis[1] = 0x80; // the command register is being written
+            fis[2] = command;
+            fis[4] = lba as u8;
+            fis[5] = (lba >> 8) as u8;
+            fis[6] = (lba >> 16) as u8;
+            fis[7] = 0x40; // LBA addressing
+            fis[8] = (lba >> 24) as u8;
+            fis[9] = (lba >> 32) as u8;
+            fis[10] = (lba >> 40) as u8;
+            fis[12] = count as u8;
+            fis[13] = (count >> 8) as u8;
+        }
+
+        let table = self.memory.physical() as u64 + COMMAND_TABLE as u64;
+        let mut flags = FIS_LENGTH_DWORDS;
+        if bytes > 0 {
+            let bounce = self.bounce.physical() as u64;
+            put_u32(memory, PRDT, bounce as u32);
+            put_u32(memory, PRDT + 4, (bounce >> 32) as u32);
+            put_u32(memory, PRDT + 12, PRD_INTERRUPT | (bytes as u32 - 1));
+            flags |= 1 << 16; // one PRDT entry
+        }
+        if to_device {
+            flags |= HEADER_WRITE;
+        }
+        put_u32(memory, COMMAND_LIST, flags);
+        put_u32(memory, COMMAND_LIST + 4, 0);
+        put_u32(memory, COMMAND_LIST + 8, table as u32);
+        put_u32(memory, COMMAND_LIST + 12, (table >> 32) as u32);
+
+        self.events.store(0, Ordering::SeqCst);
+        self.write(PX_IS, 0xffff_ffff);
+        // the command structures must be in memory before the controller
+        // is told to fetch them
+        atomic::fence(Ordering::SeqCst);
+        self.write(PX_CI, 1);
+
+        // completion is read from the port itself, so a lost or unrouted
+        // interrupt only delays it until the next timer tick
+        let deadline = time::uptime_ms() + TIMEOUT_MS;
+        int::wait_until(|| {
+            let status = self.events.load(Ordering::SeqCst) as u32 | self.read(PX_IS);
+            status & IS_ERRORS != 0 || self.read(PX_CI) & 1 == 0 ||
+            time::uptime_ms() > deadline
+        });
+        atomic::fence(Ordering::SeqCst);
+
+        let status = self.events.load(Ordering::SeqCst) as u32 | self.read(PX_IS);
+        if status & IS_ERRORS != 0 || self.read(PX_TFD) & TFD_ERROR != 0 {
+            self.recover();
+            return Err(Error::Device);
+        }
+        if self.read(PX_CI) & 1 != 0 {
+            self.recover();
+            return Err(Error::Timeout);
+        }
+        Ok(())
+    }
+
+    // Restarting the port clears the command that failed.
+    fn recover(&self) {
+        let _ = self.stop();
+        self.write(PX_SERR, 0xffff_ffff);
+        let _ = self.start();
+    }
+
+    fn identify(&self) -> block::Result<Identity> {
+        self.issue(ATA_IDENTIFY, 0, 0, SECTOR_SIZE, false)?;
+        let bytes = self.bounce.as_slice();
+        let mut data = [0u16; 256];
+        for (index, word) in data.iter_mut().enumerate() {
+            *word = bytes[index * 2] as u16 | (bytes[index * 2 + 1] as u16) << 8;
+        }
+        Ok(Identity::parse(&data))
+    }
+}
+
+fn put_u32(memory: &mut [u8], offset: usize, value: u32) {
+    for index in 0..4 {
+        memory[offset + index] = (value >> (index * 8)) as u8;
+    }
+}
+
+pub struct Disk {
+    port: Mutex<Port>,
+    sectors: u64,
+    model: String,
+}
+
+impl Disk {
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+impl BlockDevice for Disk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, block: u64, buf: &mut [u8]) -> block::Result<()> {
+        block::check_range(self, block, buf.len())?;
+        let port = self.port.lock();
+        let chunk_size = port.bounce.size();
+        for (index, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+            let lba = block + (index * chunk_size / SECTOR_SIZE) as u64;
+            let count = chunk.len() / SECTOR_SIZE;
+            port.issue(ATA_READ_DMA_EXT, lba, count, chunk.len(), false)?;
+            chunk.copy_from_slice(&port.bounce.as_slice()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn write(&self, block: u64, buf: &[u8]) -> block::Result<()> {
+        block::check_range(self, block, buf.len())?;
+        let port = self.port.lock();
+        let chunk_size = port.bounce.size();
+        for (index, chunk) in buf.chunks(chunk_size).enumerate() {
+            let lba = block + (index * chunk_size / SECTOR_SIZE) as u64;
+            let count = chunk.len() / SECTOR_SIZE;
+            port.bounce.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            port.issue(ATA_WRITE_DMA_EXT, lba, count, chunk.len(), true)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> block::Result<()> {
+        self.port.lock().issue(ATA_FLUSH_CACHE_EXT, 0, 0, 0, false)
+    }
+}
+
+struct AhciDriver;
+
+impl Driver for AhciDriver {
+    fn name(&self) -> &'static str {
+        "ahci"
+    }
+
+    fn matches(&self, device: &Device) -> bool {
+        device.class == CLASS_STORAGE && device.subclass == SUBCLASS_SATA &&
+        device.prog_if == PROG_IF_AHCI
+    }
+
+    fn probe(&self, device: &Device) -> Result<(), &'static str> {
+        let (address, size) = match device.bar(ABAR) {
+            Bar::Memory { address, size, .. } => (address as usize, size as usize),
+            _ => return Err("no memory mapped registers"),
+        };
+        let flags = WRITABLE | NO_CACHE | WRITE_THROUGH | NO_EXECUTE;
+        mem::with_controller(|mc| mc.identity_map(address, address + size, flags));
+        device.enable(true);
+
+        write(address + HBA_GHC, read(address + HBA_GHC) | GHC_AHCI_ENABLE);
+        let capabilities = read(address + HBA_CAP);
+        let implemented = read(address + HBA_PI);
+
+        let events: Arc<Vec<Arc<AtomicUsize>>> =
+            Arc::new((0..32).map(|_| Arc::new(AtomicUsize::new(0))).collect());
+        if device.interrupt_line < 16 {
+            let events = events.clone();
+            int::register_irq(device.interrupt_line,
+                              Arc::new(move || handle_interrupt(address, &events)));
+        }
+
+        let mut found = 0;
+        for index in (0..32).filter(|&index| implemented & 1 << index != 0) {
+            let registers = address + PORTS + index * PORT_SIZE;
+            let status = read(registers + PX_SSTS);
+            if status & 0xf != SSTS_PRESENT || status & 0xf00 != SSTS_ACTIVE {
+                continue;
+            }
+            if read(registers + PX_SIG) != SIGNATURE_ATA {
+                continue;
+            }
+            match probe_port(registers, events[index].clone(), capabilities) {
+                Ok(()) => found += 1,
+                Err(error) => warn!("ahci: port {}: {:?}", index, error),
+            }
+        }
+
+        write(address + HBA_IS, 0xffff_ffff);
+        write(address + HBA_GHC, read(address + HBA_GHC) | GHC_INTERRUPT_ENABLE);
+
+        if found == 0 {
+            return Err("no SATA disks");
+        }
+        Ok(())
+    }
+}
+
+fn probe_port(registers: usize, events: Arc<AtomicUsize>, capabilities: u32)
+              -> block::Result<()> {
+    let (memory, bounce) = mem::with_controller(|mc| (mc.alloc_dma(1), mc.alloc_dma(BOUNCE_PAGES)));
+    let (memory, bounce) = match (memory, bounce) {
+        (Some(memory), Some(bounce)) => (memory, bounce),
+        _ => return Err(Error::Device),
+    };
+    let above_4g = (memory.physical() as u64 | bounce.physical() as u64) >> 32 != 0;
+    if above_4g && capabilities & CAP_64BIT == 0 {
+        return Err(Error::Device);
+    }
+
+    let port = Port {
+        registers: registers,
+        events: events,
+        memory: memory,
+        bounce: bounce,
+    };
+    port.stop()?;
+    port.start()?;
+    let identity = port.identify()?;
+
+    let name = format!("sd{}", (b'a' + NEXT_DISK.fetch_add(1, Ordering::Relaxed) as u8) as char);
+    info!("ahci: {}: {} ({} MiB)",
+          name,
+          identity.model,
+          identity.sectors * SECTOR_SIZE as u64 >> 20);
+    block::register(&name,
+                    Arc::new(Disk {
+                        port: Mutex::new(port),
+                        sectors: identity.sectors,
+                        model: identity.model,
+                    }));
+    Ok(())
+}
+
+// Acknowledges every port that raised an interrupt, recording its status for
+// the command waiting on it.
+fn handle_interrupt(hba: usize, events: &[Arc<AtomicUsize>]) {
+    let pending = read(hba + HBA_IS);
+    if pending == 0 {
+        return;
+    }
+    for index in (0..32).filter(|&index| pending & 1 << index != 0) {
+        let registers = hba + PORTS + index * PORT_SIZE;
+        let status = read(registers + PX_IS);
+        write(registers + PX_IS, status);
+        events[index].fetch_or(status as usize, Ordering::SeqCst);
+    }
+    write(hba + HBA_IS, pending);
+}
+
+pub fn init() {
+    pci::register(Arc::new(AhciDriver));
+}
@@ -0,0 +1,386 @@
+// ATA disks on the IDE channels, driven by programmed I/O with interrupts
+// masked. ATAPI devices such as the boot CD are recognised and skipped.
+
+use alloc::arc::Arc;
+use collections::string::String;
+use spin::Mutex;
+use x86::shared::io::{inb, inw, outb, outw};
+
+use block::{self, BlockDevice, Error};
+use drivers::pci::{self, Bar, Device, Driver, CLASS_STORAGE};
+
+const SUBCLASS_IDE: u8 = 0x01;
+
+// prog_if bits telling whether a channel runs in native PCI mode, in which
+// case its ports come from the BARs instead of the legacy ISA addresses
+const PRIMARY_NATIVE: u8 = 1 << 0;
+const SECONDARY_NATIVE: u8 = 1 << 2;
+
+const LEGACY_PORTS: [(u16, u16); 2] = [(0x1f0, 0x3f6), (0x170, 0x376)];
+
+const SECTOR_SIZE: usize = 512;
+
+// command block registers, relative to the channel base
+const DATA: u16 = 0;
+const ERROR: u16 = 1;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DRIVE_SELECT: u16 = 6;
+const COMMAND: u16 = 7;
+const STATUS: u16 = 7;
+
+const CONTROL_INTERRUPT_DISABLE: u8 = 1 << 1;
+
+const STATUS_ERROR: u8 = 1 << 0;
+const STATUS_DATA_REQUEST: u8 = 1 << 3;
+const STATUS_DRIVE_FAULT: u8 = 1 << 5;
+const STATUS_BUSY: u8 = 1 << 7;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_FLUSH_CACHE: u8 = 0xe7;
+const CMD_FLUSH_CACHE_EXT: u8 = 0xea;
+const CMD_IDENTIFY: u8 = 0xec;
+
+const LBA28_LIMIT: u64 = 1 << 28;
+
+// the most sectors a single 28-bit command can move
+const MAX_SECTORS: usize = 256;
+
+const POLL_LIMIT: usize = 10_000_000;
+
+struct Channel {
+    base: u16,
+    control: u16,
+}
+
+impl Channel {
+    fn status(&self) -> u8 {
+        unsafe { inb(self.base + STATUS) }
+    }
+
+    // Reading the alternate status register four times gives the drive the
+    // 400ns it needs to put up a valid status after a select or command.
+    fn delay(&self) {
+        for _ in 0..4 {
+            unsafe {
+                inb(self.control);
+            }
+        }
+    }
+
+    fn select(&self, slave: bool, lba_top: u8) {
+        unsafe {
+            outb(self.base + DRIVE_SELECT, 0xe0 | (slave as u8) << 4 | lba_top & 0x0f);
+        }
+        self.delay();
+    }
+
+    fn wait_ready(&self) -> Result<u8, Error> {
+        for _ in 0..POLL_LIMIT {
+            let status = self.status();
+            if status & STATUS_BUSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    fn wait_data(&self) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            let status = self.status();
+            if status & STATUS_BUSY != 0 {
+                continue;
+            }
+            if status & (STATUS_ERROR | STATUS_DRIVE_FAULT) != 0 {
+                return Err(Error::Device);
+            }
+            if status & STATUS_DATA_REQUEST != 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let status = self.wait_ready()?;
+        if status & (STATUS_ERROR | STATUS_DRIVE_FAULT) != 0 {
+            return Err(Error::Device);
+        }
+        Ok(())
+    }
+
+    // Selects the drive and issues a transfer command for `count` sectors,
+    // using the 48-bit form when `lba48` is set. A count of 256 is written as
+    // zero in the 28-bit form, which the drive reads as 256.
+    fn command(&self, slave: bool, lba48: bool, lba: u64, count: usize, command: u8)
+               -> Result<(), Error> {
+        self.select(slave, if lba48 { 0 } else { (lba >> 24) as u8 });
+        self.wait_ready()?;
+        unsafe {
+            if lba48 {
+                // the high order bytes go first, through the same registers
+                outb(self.base + SECTOR_COUNT, (count >> 8) as u8);
+                outb(self.base + LBA_LOW, (lba >> 24) as u8);
+                outb(self.base + LBA_MID, (lba >> 32) as u8);
+                outb(self.base + LBA_HIGH, (lba >> 40) as u8);
+            }
+            outb(self.base + SECTOR_COUNT, count as u8);
+            outb(self.base + LBA_LOW, lba as u8);
+            outb(self.base + LBA_MID, (lba >> 8) as u8);
+            outb(self.base + LBA_HIGH, (lba >> 16) as u8);
+            outb(self.base + COMMAND, command);
+        }
+        self.delay();
+        Ok(())
+    }
+
+    fn read_sector(&self, buf: &mut [u8]) {
+        for word in buf.chunks_mut(2) {
+            let value = unsafe { inw(self.base + DATA) };
+            word[0] = value as u8;
+            word[1] = (value >> 8) as u8;
+        }
+    }
+
+    fn write_sector(&self, buf: &[u8]) {
+        for word in buf.chunks(2) {
+            unsafe {
+                outw(self.base + DATA, word[0] as u16 | (word[1] as u16) << 8);
+            }
+        }
+    }
+
+    fn identify(&self, slave: bool) -> Result<Option<[u16; 256]>, Error> {
+        self.select(slave, 0);
+        unsafe {
+            outb(self.base + SECTOR_COUNT, 0);
+            outb(self.base + LBA_LOW, 0);
+            outb(self.base + LBA_MID, 0);
+            outb(self.base + LBA_HIGH, 0);
+            outb(self.base + COMMAND, CMD_IDENTIFY);
+        }
+        self.delay();
+        // a floating bus reads as 0xff and an absent drive as zero
+        let status = self.status();
+        if status == 0 || status == 0xff {
+            return Ok(None);
+        }
+        self.wait_ready()?;
+
+        // ATAPI and SATA devices abort IDENTIFY and leave their signature
+        let signature = unsafe { (inb(self.base + LBA_MID), inb(self.base + LBA_HIGH)) };
+        if signature != (0, 0) {
+            return Ok(None);
+        }
+        self.wait_data()?;
+
+        let mut data = [0u16; 256];
+        for word in data.iter_mut() {
+            *word = unsafe { inw(self.base + DATA) };
+        }
+        Ok(Some(data))
+    }
+}
+
+// What a drive reports about itself in response to IDENTIFY DEVICE, which
+// AHCI drives answer with the same 256 words.
+pub struct Identity {
+    pub lba48: bool,
+    pub sectors: u64,
+    pub model: String,
+}
+
+impl Identity {
+    pub fn parse(data: &[u16; 256]) -> Identity {
+        let lba48 = data[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            data[100] as u64 | (data[101] as u64) << 16 | (data[102] as u64) << 32 |
+            (data[103] as u64) << 48
+        } else {
+            data[60] as u64 | (data[61] as u64) << 16
+        };
+
+        // the model number is stored with the bytes of each word swapped
+        let mut model = String::new();
+        for &word in &data[27..47] {
+            model.push((word >> 8) as u8 as char);
+            model.push(word as u8 as char);
+        }
+
+        Identity {
+            lba48: lba48,
+            sectors: sectors,
+            model: String::from(model.trim()),
+        }
+    }
+}
+
+pub struct Drive {
+    channel: Arc<Mutex<Channel>>,
+    slave: bool,
+    lba48: bool,
+    sectors: u64,
+    model: String,
+}
+
+impl Drive {
+    fn new(channel: Arc<Mutex<Channel>>, slave: bool, identity: Identity) -> Drive {
+        Drive {
+            channel: channel,
+            slave: slave,
+            lba48: identity.lba48,
+            sectors: identity.sectors,
+            model: identity.model,
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn needs_lba48(&self, lba: u64, count: usize) -> bool {
+        lba + count as u64 > LBA28_LIMIT
+    }
+}
+
+impl BlockDevice for Drive {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, block: u64, buf: &mut [u8]) -> block::Result<()> {
+        block::check_range(self, block, buf.len())?;
+        let channel = self.channel.lock();
+        for (index, chunk) in buf.chunks_mut(MAX_SECTORS * SECTOR_SIZE).enumerate() {
+            let lba = block + (index * MAX_SECTORS) as u64;
+            let count = chunk.len() / SECTOR_SIZE;
+            let lba48 = self.needs_lba48(lba, count);
+            let command = if lba48 { CMD_READ_SECTORS_EXT } else { CMD_READ_SECTORS };
+            channel.command(self.slave, lba48, lba, count, command)?;
+            for sector in chunk.chunks_mut(SECTOR_SIZE) {
+                channel.wait_data()?;
+                channel.read_sector(sector);
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, block: u64, buf: &[u8]) -> block::Result<()> {
+        block::check_range(self, block, buf.len())?;
+        let channel = self.channel.lock();
+        for (index, chunk) in buf.chunks(MAX_SECTORS * SECTOR_SIZE).enumerate() {
+            let lba = block + (index * MAX_SECTORS) as u64;
+            let count = chunk.len() / SECTOR_SIZE;
+            let lba48 = self.needs_lba48(lba, count);
+            let command = if lba48 { CMD_WRITE_SECTORS_EXT } else { CMD_WRITE_SECTORS };
+            channel.command(self.slave, lba48, lba, count, command)?;
+            for sector in chunk.chunks(SECTOR_SIZE) {
+                channel.wait_data()?;
+                channel.write_sector(sector);
+            }
+            channel.finish()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> block::Result<()> {
+        let channel = self.channel.lock();
+        let command = if self.lba48 { CMD_FLUSH_CACHE_EXT } else { CMD_FLUSH_CACHE };
+        channel.select(self.slave, 0);
+        channel.wait_ready()?;
+        unsafe {
+            outb(channel.base + COMMAND, command);
+        }
+        channel.delay();
+        channel.finish()
+    }
+}
+
+// Binds to IDE controllers and registers the drives on both channels as
+// hda (primary master) through hdd (secondary slave).
+struct AtaDriver;
+
+impl Driver for AtaDriver {
+    fn name(&self) -> &'static str {
+        "ata"
+    }
+
+    fn matches(&self, device: &Device) -> bool {
+        device.class == CLASS_STORAGE && device.subclass == SUBCLASS_IDE
+    }
+
+    fn probe(&self, device: &Device) -> Result<(), &'static str> {
+        device.enable(false);
+        let mut found = 0;
+        for index in 0..2 {
+            let native = if index == 0 { PRIMARY_NATIVE } else { SECONDARY_NATIVE };
+            let (base, control) = if device.prog_if & native != 0 {
+                match (device.bar(index * 2), device.bar(index * 2 + 1)) {
+                    (Bar::Io { port: base, .. }, Bar::Io { port: control, .. }) => {
+                        // the control register is the third port of its BAR
+                        (base, control + 2)
+                    }
+                    _ => return Err("native mode channel without I/O BARs"),
+                }
+            } else {
+                LEGACY_PORTS[index]
+            };
+            found += probe_channel(index, base, control);
+        }
+        if found == 0 {
+            return Err("no ATA drives");
+        }
+        Ok(())
+    }
+}
+
+fn probe_channel(index: usize, base: u16, control: u16) -> usize {
+    let channel = Channel {
+        base: base,
+        control: control,
+    };
+    unsafe {
+        outb(control, CONTROL_INTERRUPT_DISABLE);
+        // nothing is attached when the status register floats high
+        if inb(base + STATUS) == 0xff {
+            return 0;
+        }
+        inb(base + ERROR);
+    }
+
+    let channel = Arc::new(Mutex::new(channel));
+    let mut found = 0;
+    for &slave in &[false, true] {
+        let data = match channel.lock().identify(slave) {
+            Ok(Some(data)) => data,
+            Ok(None) => continue,
+            Err(error) => {
+                warn!("ata: identify failed on channel {} ({:?})", index, error);
+                continue;
+            }
+        };
+        let drive = Drive::new(channel.clone(), slave, Identity::parse(&data));
+        let name = ["hda", "hdb", "hdc", "hdd"][index * 2 + slave as usize];
+        info!("ata: {}: {} ({} MiB{})",
+              name,
+              drive.model(),
+              drive.sectors * SECTOR_SIZE as u64 >> 20,
+              if drive.lba48 { ", lba48" } else { "" });
+        block::register(name, Arc::new(drive));
+        found += 1;
+    }
+    found
+}
+
+pub fn init() {
+    pci::register(Arc::new(AtaDriver));
+}
@@ -0,0 +1,46 @@
+use alloc::arc::Arc;
+
+use fs::{self, devfs};
+use fs::devfs::CharDevice;
+use int;
+use kmsg::KMSG;
+use vga::vt::{self, VT_COUNT};
+
+// A virtual terminal. `console` is the kernel's log terminal, whose output
+// also goes to the kernel message buffer; `tty1` to `tty6` are the terminals
+// switched between with Alt+F1..F6. Reads return whatever was typed on the
+// terminal without waiting for more.
+struct Terminal {
+    index: usize,
+    log: bool,
+}
+
+impl CharDevice for Terminal {
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        Ok(vt::read_input(self.index, buf))
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        vt::write(self.index, buf);
+        if self.log {
+            int::without_interrupts(|| KMSG.lock().write(buf));
+        }
+        Ok(buf.len())
+    }
+}
+
+pub fn init() {
+    devfs::register("console",
+                    Arc::new(Terminal {
+                        index: 0,
+                        log: true,
+                    }));
+    for index in 0..VT_COUNT {
+        let name = format!("tty{}", index + 1);
+        devfs::register(&name,
+                        Arc::new(Terminal {
+                            index: index,
+                            log: false,
+                        }));
+    }
+}
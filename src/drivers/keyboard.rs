@@ -0,0 +1,235 @@
+// The PS/2 keyboard on IRQ 1. Scancodes, in set 1 as translated by the
+// controller, become bytes for the virtual terminal on the screen, except for
+// the console hot keys: Alt+F1..F6 switch terminals, and Shift+PageUp and
+// Shift+PageDown scroll back and forth.
+
+use core::mem;
+
+use alloc::arc::Arc;
+use spin::Mutex;
+use x86::shared::io::{inb, outb};
+
+use int;
+use vga::vt;
+
+const DATA: u16 = 0x60;
+const STATUS: u16 = 0x64;
+const COMMAND: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const CONFIG_IRQ: u8 = 1 << 0;
+const CONFIG_TRANSLATE: u8 = 1 << 6;
+
+// status polls before giving up on the controller
+const TIMEOUT: usize = 100_000;
+
+const KEYBOARD_IRQ: u8 = 1;
+
+const EXTENDED: u8 = 0xe0;
+const RELEASED: u8 = 0x80;
+
+const LEFT_SHIFT: u8 = 0x2a;
+const RIGHT_SHIFT: u8 = 0x36;
+const CTRL: u8 = 0x1d;
+const ALT: u8 = 0x38;
+const CAPS_LOCK: u8 = 0x3a;
+const F1: u8 = 0x3b;
+
+// extended codes
+const KEYPAD_ENTER: u8 = 0x1c;
+const KEYPAD_SLASH: u8 = 0x35;
+const HOME: u8 = 0x47;
+const UP: u8 = 0x48;
+const PAGE_UP: u8 = 0x49;
+const LEFT: u8 = 0x4b;
+const RIGHT: u8 = 0x4d;
+const END: u8 = 0x4f;
+const DOWN: u8 = 0x50;
+const PAGE_DOWN: u8 = 0x51;
+const INSERT: u8 = 0x52;
+const DELETE: u8 = 0x53;
+
+// US layout for the codes up to the space bar, without and with shift;
+// zero for keys that produce nothing by themselves
+const NORMAL: &'static [u8] = b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\n\0\
+                                asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const SHIFTED: &'static [u8] = b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\n\0\
+                                 ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+struct State {
+    // the previous byte was the extended prefix
+    extended: bool,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+    caps_lock: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    extended: false,
+    shift: false,
+    ctrl: false,
+    alt: false,
+    caps_lock: false,
+});
+
+fn is_letter(byte: u8) -> bool {
+    (byte >= b'a' && byte <= b'z') || (byte >= b'A' && byte <= b'Z')
+}
+
+impl State {
+    fn handle(&mut self, scancode: u8) {
+        trace!("keyboard: scancode {:#04x}", scancode);
+        if scancode == EXTENDED {
+            self.extended = true;
+            return;
+        }
+        let extended = mem::replace(&mut self.extended, false);
+        let pressed = scancode & RELEASED == 0;
+        let code = scancode & !RELEASED;
+
+        match (extended, code) {
+            // the fake shifts some keyboards send around extended keys
+            (true, LEFT_SHIFT) | (true, RIGHT_SHIFT) => {}
+            (false, LEFT_SHIFT) | (false, RIGHT_SHIFT) => self.shift = pressed,
+            (_, CTRL) => self.ctrl = pressed,
+            (_, ALT) => self.alt = pressed,
+            _ if !pressed => {}
+            (false, CAPS_LOCK) => self.caps_lock = !self.caps_lock,
+            (false, code) if self.alt && code >= F1 && code < F1 + vt::VT_COUNT as u8 => {
+                vt::switch((code - F1) as usize)
+            }
+            (true, PAGE_UP) if self.shift => {
+                vt::with_active(|writer| {
+                    let page = writer.page_lines();
+                    writer.scroll_back(page)
+                })
+            }
+            (true, PAGE_DOWN) if self.shift => {
+                vt::with_active(|writer| {
+                    let page = writer.page_lines();
+                    writer.scroll_forward(page)
+                })
+            }
+            (true, code) => {
+                if let Some(sequence) = escape_sequence(code) {
+                    vt::input(sequence);
+                }
+            }
+            (false, code) => {
+                if let Some(byte) = self.translate(code) {
+                    // alt sends an escape first, as on xterm
+                    if self.alt {
+                        vt::input(b"\x1b");
+                    }
+                    vt::input(&[byte]);
+                }
+            }
+        }
+    }
+
+    fn translate(&self, code: u8) -> Option<u8> {
+        let table = if self.shift { SHIFTED } else { NORMAL };
+        let mut byte = match table.get(code as usize) {
+            Some(&0) | None => return None,
+            Some(&byte) => byte,
+        };
+        if is_letter(byte) {
+            if self.caps_lock {
+                byte ^= 0x20;
+            }
+            if self.ctrl {
+                byte &= 0x1f;
+            }
+        }
+        Some(byte)
+    }
+}
+
+// What the extended keys send, as a VT100 would.
+fn escape_sequence(code: u8) -> Option<&'static [u8]> {
+    let sequence: &'static [u8] = match code {
+        KEYPAD_ENTER => b"\n",
+        KEYPAD_SLASH => b"/",
+        UP => b"\x1b[A",
+        DOWN => b"\x1b[B",
+        RIGHT => b"\x1b[C",
+        LEFT => b"\x1b[D",
+        HOME => b"\x1b[H",
+        END => b"\x1b[F",
+        INSERT => b"\x1b[2~",
+        DELETE => b"\x1b[3~",
+        PAGE_UP => b"\x1b[5~",
+        PAGE_DOWN => b"\x1b[6~",
+        _ => return None,
+    };
+    Some(sequence)
+}
+
+unsafe fn write_command(command: u8) -> bool {
+    for _ in 0..TIMEOUT {
+        if inb(STATUS) & STATUS_INPUT_FULL == 0 {
+            outb(COMMAND, command);
+            return true;
+        }
+    }
+    false
+}
+
+unsafe fn write_data(byte: u8) -> bool {
+    for _ in 0..TIMEOUT {
+        if inb(STATUS) & STATUS_INPUT_FULL == 0 {
+            outb(DATA, byte);
+            return true;
+        }
+    }
+    false
+}
+
+unsafe fn read_data() -> Option<u8> {
+    for _ in 0..TIMEOUT {
+        if inb(STATUS) & STATUS_OUTPUT_FULL != 0 {
+            return Some(inb(DATA));
+        }
+    }
+    None
+}
+
+// Turns on the keyboard interrupt and scancode translation in the controller,
+// which the firmware usually did already.
+pub fn init() {
+    let present = unsafe {
+        // drop whatever was typed before
+        for _ in 0..TIMEOUT {
+            if inb(STATUS) & STATUS_OUTPUT_FULL == 0 {
+                break;
+            }
+            inb(DATA);
+        }
+        if !write_command(READ_CONFIG) {
+            false
+        } else {
+            match read_data() {
+                Some(config) => {
+                    write_command(WRITE_CONFIG) &&
+                    write_data(config | CONFIG_IRQ | CONFIG_TRANSLATE)
+                }
+                None => false,
+            }
+        }
+    };
+    if !present {
+        warn!("keyboard: no PS/2 controller");
+        return;
+    }
+
+    int::register_irq(KEYBOARD_IRQ,
+                      Arc::new(|| {
+                          let scancode = unsafe { inb(DATA) };
+                          STATE.lock().handle(scancode);
+                      }));
+}
@@ -0,0 +1,19 @@
+pub mod ahci;
+pub mod ata;
+pub mod console;
+pub mod keyboard;
+pub mod pci;
+pub mod serial;
+pub mod virtio;
+
+pub fn init() {
+    console::init();
+    keyboard::init();
+    serial::init();
+
+    // PCI drivers are registered before enumeration binds them
+    ata::init();
+    ahci::init();
+    virtio::blk::init();
+    pci::init();
+}
@@ -0,0 +1,146 @@
+// Configuration space access, through the memory mapped enhanced mechanism
+// (ECAM) when the firmware describes it in the ACPI MCFG table and through
+// the legacy 0xcf8/0xcfc port pair otherwise.
+
+use collections::vec::Vec;
+use spin::Mutex;
+use x86::shared::io::{inl, outl};
+
+use acpi;
+use mem;
+use mem::PAGE_SIZE;
+use mem::paging::{PhysicalAddress, NO_CACHE, NO_EXECUTE, WRITABLE, WRITE_THROUGH};
+
+use super::Address;
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+
+const LEGACY_SIZE: u16 = 0x100;
+const ECAM_SIZE: u16 = 0x1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EcamRegion {
+    pub base: PhysicalAddress,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+#[repr(C, packed)]
+struct McfgEntry {
+    base: u64,
+    segment: u16,
+    start_bus: u8,
+    end_bus: u8,
+    reserved: u32,
+}
+
+// the MCFG header is followed by 8 reserved bytes before the entries
+const MCFG_ENTRIES: usize = acpi::HEADER_SIZE + 8;
+
+lazy_static! {
+    static ref REGIONS: Mutex<Vec<EcamRegion>> = Mutex::new(Vec::new());
+}
+
+// Serializes the two step address/data sequence of the legacy mechanism.
+static LEGACY: Mutex<()> = Mutex::new(());
+
+pub fn init() {
+    let mcfg = match acpi::find(b"MCFG") {
+        Some(mcfg) => mcfg,
+        None => return,
+    };
+
+    let mut regions = REGIONS.lock();
+    for entry in mcfg[MCFG_ENTRIES..].chunks(::core::mem::size_of::<McfgEntry>()) {
+        if entry.len() != ::core::mem::size_of::<McfgEntry>() {
+            break;
+        }
+        let entry = unsafe { &*(entry.as_ptr() as *const McfgEntry) };
+        regions.push(EcamRegion {
+            base: entry.base as usize,
+            segment: entry.segment,
+            start_bus: entry.start_bus,
+            end_bus: entry.end_bus,
+        });
+    }
+}
+
+// The (segment, first bus, last bus) ranges that can be enumerated.
+pub fn bus_ranges() -> Vec<(u16, u8, u8)> {
+    let regions = REGIONS.lock();
+    if regions.is_empty() {
+        return vec![(0, 0, 255)];
+    }
+    regions.iter().map(|r| (r.segment, r.start_bus, r.end_bus)).collect()
+}
+
+pub fn uses_ecam() -> bool {
+    !REGIONS.lock().is_empty()
+}
+
+// Size of the configuration space that `read` and `write` can reach.
+pub fn space_size() -> u16 {
+    if uses_ecam() { ECAM_SIZE } else { LEGACY_SIZE }
+}
+
+pub fn read(address: Address, offset: u16) -> u32 {
+    assert!(offset % 4 == 0, "unaligned configuration space access");
+    match ecam_address(address, offset) {
+        Some(pointer) => unsafe { ::core::ptr::read_volatile(pointer as *const u32) },
+        None => {
+            let _guard = LEGACY.lock();
+            unsafe {
+                outl(CONFIG_ADDRESS, legacy_address(address, offset));
+                inl(CONFIG_DATA)
+            }
+        }
+    }
+}
+
+pub fn write(address: Address, offset: u16, value: u32) {
+    assert!(offset % 4 == 0, "unaligned configuration space access");
+    match ecam_address(address, offset) {
+        Some(pointer) => unsafe { ::core::ptr::write_volatile(pointer as *mut u32, value) },
+        None => {
+            let _guard = LEGACY.lock();
+            unsafe {
+                outl(CONFIG_ADDRESS, legacy_address(address, offset));
+                outl(CONFIG_DATA, value);
+            }
+        }
+    }
+}
+
+fn legacy_address(address: Address, offset: u16) -> u32 {
+    assert!(address.segment == 0 && offset < LEGACY_SIZE);
+    1 << 31 | (address.bus as u32) << 16 | (address.device as u32) << 11 |
+    (address.function as u32) << 8 | offset as u32
+}
+
+// The configuration space of each function is mapped the first time it is
+// touched, since mapping whole bus ranges up front would take 256 MiB of
+// page table coverage per segment.
+fn ecam_address(address: Address, offset: u16) -> Option<PhysicalAddress> {
+    let base = {
+        let regions = REGIONS.lock();
+        if regions.is_empty() {
+            return None;
+        }
+        let region = regions.iter()
+            .find(|r| {
+                r.segment == address.segment && r.start_bus <= address.bus &&
+                address.bus <= r.end_bus
+            })
+            .expect("no ECAM region covers the bus");
+        region.base
+    };
+    assert!(offset < ECAM_SIZE);
+
+    let function = base + ((address.bus as usize) << 20 | (address.device as usize) << 15 |
+                           (address.function as usize) << 12);
+    let flags = WRITABLE | NO_CACHE | WRITE_THROUGH | NO_EXECUTE;
+    mem::with_controller(|mc| mc.identity_map(function, function + PAGE_SIZE, flags));
+    Some(function + offset as usize)
+}
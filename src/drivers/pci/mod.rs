@@ -0,0 +1,390 @@
+// PCI bus enumeration and the registry that binds drivers to the functions
+// found on it.
+
+pub mod config;
+
+use core::fmt;
+
+use alloc::arc::Arc;
+use collections::vec::Vec;
+use spin::Mutex;
+
+const VENDOR_ID: u16 = 0x00;
+const COMMAND: u16 = 0x04;
+const CLASS_REVISION: u16 = 0x08;
+const HEADER_TYPE: u16 = 0x0c;
+const BAR0: u16 = 0x10;
+const SUBSYSTEM: u16 = 0x2c;
+const CAPABILITIES_POINTER: u16 = 0x34;
+const INTERRUPT: u16 = 0x3c;
+
+const STATUS_CAPABILITIES: u32 = 1 << 20;
+const HEADER_MULTIFUNCTION: u8 = 0x80;
+const HEADER_TYPE_MASK: u8 = 0x7f;
+const HEADER_TYPE_BRIDGE: u8 = 0x01;
+
+const NO_DEVICE: u16 = 0xffff;
+
+pub const CAP_POWER_MANAGEMENT: u8 = 0x01;
+pub const CAP_MSI: u8 = 0x05;
+pub const CAP_VENDOR: u8 = 0x09;
+pub const CAP_PCI_EXPRESS: u8 = 0x10;
+pub const CAP_MSIX: u8 = 0x11;
+
+pub const CLASS_STORAGE: u8 = 0x01;
+pub const CLASS_NETWORK: u8 = 0x02;
+pub const CLASS_DISPLAY: u8 = 0x03;
+pub const CLASS_BRIDGE: u8 = 0x06;
+
+bitflags! {
+    pub flags Command: u16 {
+        const IO_SPACE          = 1 << 0,
+        const MEMORY_SPACE      = 1 << 1,
+        const BUS_MASTER        = 1 << 2,
+        const INTERRUPT_DISABLE = 1 << 10,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{:04x}:{:02x}:{:02x}.{}",
+               self.segment,
+               self.bus,
+               self.device,
+               self.function)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    None,
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+    },
+    Io { port: u16, size: u16 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capability {
+    pub id: u8,
+    pub offset: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub address: Address,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub subsystem_vendor_id: u16,
+    pub subsystem_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    pub bars: [Bar; 6],
+}
+
+impl Device {
+    fn probe(address: Address) -> Option<Device> {
+        let id = config::read(address, VENDOR_ID);
+        if id as u16 == NO_DEVICE {
+            return None;
+        }
+        let class = config::read(address, CLASS_REVISION);
+        let header_type = (config::read(address, HEADER_TYPE) >> 16) as u8;
+        let interrupt = config::read(address, INTERRUPT);
+        let subsystem = if header_type & HEADER_TYPE_MASK == 0 {
+            config::read(address, SUBSYSTEM)
+        } else {
+            0
+        };
+
+        let mut device = Device {
+            address: address,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            subsystem_vendor_id: subsystem as u16,
+            subsystem_id: (subsystem >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            revision: class as u8,
+            header_type: header_type,
+            interrupt_line: interrupt as u8,
+            interrupt_pin: (interrupt >> 8) as u8,
+            bars: [Bar::None; 6],
+        };
+        device.read_bars();
+        Some(device)
+    }
+
+    pub fn is_multifunction(&self) -> bool {
+        self.header_type & HEADER_MULTIFUNCTION != 0
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.header_type & HEADER_TYPE_MASK == HEADER_TYPE_BRIDGE
+    }
+
+    pub fn read32(&self, offset: u16) -> u32 {
+        config::read(self.address, offset)
+    }
+
+    pub fn write32(&self, offset: u16, value: u32) {
+        config::write(self.address, offset, value)
+    }
+
+    pub fn read16(&self, offset: u16) -> u16 {
+        (self.read32(offset & !3) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn write16(&self, offset: u16, value: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read32(offset & !3) & !(0xffff << shift);
+        self.write32(offset & !3, old | (value as u32) << shift)
+    }
+
+    pub fn read8(&self, offset: u16) -> u8 {
+        (self.read32(offset & !3) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn write8(&self, offset: u16, value: u8) {
+        let shift = (offset & 3) * 8;
+        let old = self.read32(offset & !3) & !(0xff << shift);
+        self.write32(offset & !3, old | (value as u32) << shift)
+    }
+
+    pub fn command(&self) -> Command {
+        Command::from_bits_truncate(self.read16(COMMAND))
+    }
+
+    pub fn set_command(&self, command: Command) {
+        // bits without a flag are kept, and writing zeroes to the upper half
+        // leaves its write-one-to-clear status bits alone
+        let other = self.read16(COMMAND) & !Command::all().bits();
+        self.write32(COMMAND, (other | command.bits()) as u32)
+    }
+
+    // Turns on decoding of the BARs and lets the function master the bus,
+    // which DMA capable devices need.
+    pub fn enable(&self, bus_master: bool) {
+        let mut command = self.command() | IO_SPACE | MEMORY_SPACE;
+        if bus_master {
+            command |= BUS_MASTER;
+        }
+        self.set_command(command)
+    }
+
+    pub fn bar(&self, index: usize) -> Bar {
+        self.bars[index]
+    }
+
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = Vec::new();
+        if self.read32(COMMAND) & STATUS_CAPABILITIES == 0 {
+            return capabilities;
+        }
+        let mut offset = (self.read8(CAPABILITIES_POINTER) & !3) as u16;
+        // a list can not hold more entries than fit in the legacy space
+        while offset != 0 && capabilities.len() < 48 {
+            let header = self.read16(offset);
+            capabilities.push(Capability {
+                id: header as u8,
+                offset: offset,
+            });
+            offset = (header >> 8) & !3;
+        }
+        capabilities
+    }
+
+    pub fn find_capability(&self, id: u8) -> Option<u16> {
+        self.capabilities().iter().find(|c| c.id == id).map(|c| c.offset)
+    }
+
+    fn read_bars(&mut self) {
+        let count = match self.header_type & HEADER_TYPE_MASK {
+            0 => 6,
+            HEADER_TYPE_BRIDGE => 2,
+            _ => 0,
+        };
+
+        // decoding is switched off while the BARs are sized so that the
+        // probe value can not claim any addresses
+        let command = self.command();
+        self.set_command(command - IO_SPACE - MEMORY_SPACE);
+
+        let mut index = 0;
+        while index < count {
+            let offset = BAR0 + index as u16 * 4;
+            let value = self.read32(offset);
+            let mask = self.size_mask(offset, value);
+
+            if value & 1 == 1 {
+                let size = (!(mask & !3)).wrapping_add(1);
+                self.bars[index] = Bar::Io {
+                    port: (value & !3) as u16,
+                    size: size as u16,
+                };
+            } else if mask & !0xf != 0 {
+                let is_64 = (value >> 1) & 3 == 2;
+                let (address, size) = if is_64 && index + 1 < count {
+                    let high = self.read32(offset + 4);
+                    let high_mask = self.size_mask(offset + 4, high);
+                    let mask = (high_mask as u64) << 32 | (mask & !0xf) as u64;
+                    ((high as u64) << 32 | (value & !0xf) as u64, (!mask).wrapping_add(1))
+                } else {
+                    ((value & !0xf) as u64, (!(mask & !0xf)).wrapping_add(1) as u64)
+                };
+                self.bars[index] = Bar::Memory {
+                    address: address,
+                    size: size,
+                    prefetchable: value & 8 != 0,
+                };
+                if is_64 {
+                    index += 1;
+                }
+            }
+            index += 1;
+        }
+
+        self.set_command(command);
+    }
+
+    fn size_mask(&self, offset: u16, value: u32) -> u32 {
+        self.write32(offset, 0xffff_ffff);
+        let mask = self.read32(offset);
+        self.write32(offset, value);
+        mask
+    }
+}
+
+pub trait Driver: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn matches(&self, device: &Device) -> bool;
+    fn probe(&self, device: &Device) -> Result<(), &'static str>;
+}
+
+struct Entry {
+    device: Device,
+    driver: Option<&'static str>,
+}
+
+lazy_static! {
+    static ref DEVICES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+    static ref DRIVERS: Mutex<Vec<Arc<Driver>>> = Mutex::new(Vec::new());
+}
+
+pub fn init() {
+    assert_has_not_been_called!();
+    config::init();
+
+    let mut found = Vec::new();
+    for (segment, start_bus, end_bus) in config::bus_ranges() {
+        for bus in start_bus as usize..end_bus as usize + 1 {
+            for device in 0..32 {
+                scan_device(segment, bus as u8, device, &mut found);
+            }
+        }
+    }
+
+    info!("pci: {} functions found ({})",
+          found.len(),
+          if config::uses_ecam() { "ecam" } else { "legacy" });
+    for device in &found {
+        debug!("pci: {} {:04x}:{:04x} class {:02x}.{:02x}.{:02x}",
+               device.address,
+               device.vendor_id,
+               device.device_id,
+               device.class,
+               device.subclass,
+               device.prog_if);
+    }
+
+    DEVICES.lock().extend(found.into_iter().map(|device| {
+        Entry {
+            device: device,
+            driver: None,
+        }
+    }));
+
+    let drivers = DRIVERS.lock().clone();
+    for driver in drivers {
+        bind(&driver);
+    }
+}
+
+fn scan_device(segment: u16, bus: u8, device: u8, found: &mut Vec<Device>) {
+    let address = |function| {
+        Address {
+            segment: segment,
+            bus: bus,
+            device: device,
+            function: function,
+        }
+    };
+    let first = match Device::probe(address(0)) {
+        Some(first) => first,
+        None => return,
+    };
+    let functions = if first.is_multifunction() { 8 } else { 1 };
+    found.push(first);
+    for function in 1..functions {
+        if let Some(device) = Device::probe(address(function)) {
+            found.push(device);
+        }
+    }
+}
+
+// Adds a driver and offers it every function that is not bound yet. Drivers
+// registered before enumeration are offered the devices once `init` runs.
+pub fn register(driver: Arc<Driver>) {
+    DRIVERS.lock().push(driver.clone());
+    bind(&driver);
+}
+
+fn bind(driver: &Arc<Driver>) {
+    // the lock is not held across probe, which may look up other devices
+    let candidates: Vec<Device> = DEVICES.lock()
+        .iter()
+        .filter(|entry| entry.driver.is_none() && driver.matches(&entry.device))
+        .map(|entry| entry.device.clone())
+        .collect();
+
+    for device in candidates {
+        match driver.probe(&device) {
+            Ok(()) => {
+                let mut devices = DEVICES.lock();
+                if let Some(entry) = devices.iter_mut().find(|e| e.device.address == device.address) {
+                    entry.driver = Some(driver.name());
+                }
+            }
+            Err(reason) => error!("pci: {}: {} failed: {}", device.address, driver.name(), reason),
+        }
+    }
+}
+
+// Every function found, with the name of the driver bound to it.
+pub fn devices() -> Vec<(Device, Option<&'static str>)> {
+    DEVICES.lock().iter().map(|entry| (entry.device.clone(), entry.driver)).collect()
+}
+
+pub fn find(vendor_id: u16, device_id: u16) -> Option<Device> {
+    DEVICES.lock()
+        .iter()
+        .find(|e| e.device.vendor_id == vendor_id && e.device.device_id == device_id)
+        .map(|e| e.device.clone())
+}
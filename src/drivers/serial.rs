@@ -0,0 +1,158 @@
+use core::fmt;
+
+use alloc::arc::Arc;
+use spin::Mutex;
+use x86::shared::io::{inb, outb};
+
+use fs::{self, devfs};
+use fs::devfs::CharDevice;
+
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+const COM1_BASE: u16 = 0x3f8;
+
+const LINE_DATA_READY: u8 = 1 << 0;
+const LINE_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+pub struct SerialPort {
+    base: u16,
+    present: bool,
+}
+
+pub static COM1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1_BASE));
+pub static COM2: Mutex<SerialPort> = Mutex::new(SerialPort::new(0x2f8));
+pub static COM3: Mutex<SerialPort> = Mutex::new(SerialPort::new(0x3e8));
+pub static COM4: Mutex<SerialPort> = Mutex::new(SerialPort::new(0x2e8));
+
+pub static PORTS: [&'static Mutex<SerialPort>; 4] = [&COM1, &COM2, &COM3, &COM4];
+
+impl SerialPort {
+    const fn new(base: u16) -> SerialPort {
+        SerialPort {
+            base: base,
+            present: false,
+        }
+    }
+
+    // Programs the uart for 38400 baud 8N1, returning whether one answered.
+    pub fn init(&mut self) -> bool {
+        unsafe {
+            outb(self.base + SCRATCH, 0x5a);
+            if inb(self.base + SCRATCH) != 0x5a {
+                return false;
+            }
+
+            outb(self.base + INTERRUPT_ENABLE, 0x00);
+            outb(self.base + LINE_CONTROL, 0x80); // divisor latch access
+            outb(self.base + DIVISOR_LOW, 0x03);
+            outb(self.base + DIVISOR_HIGH, 0x00);
+            outb(self.base + LINE_CONTROL, 0x03); // 8 bits, no parity, 1 stop bit
+            outb(self.base + FIFO_CONTROL, 0xc7); // enable and clear, 14 byte threshold
+
+            // check the data path in loopback mode before going live
+            outb(self.base + MODEM_CONTROL, 0x1e);
+            outb(self.base + DATA, 0xae);
+            if inb(self.base + DATA) != 0xae {
+                return false;
+            }
+            outb(self.base + MODEM_CONTROL, 0x0f);
+        }
+        self.present = true;
+        true
+    }
+
+    // COM1 for the emergency console, without waiting for its lock. A port
+    // whose lock is held was in use, so it is there.
+    pub fn emergency() -> SerialPort {
+        let present = match COM1.try_lock() {
+            Some(port) => port.present,
+            None => true,
+        };
+        SerialPort {
+            base: COM1_BASE,
+            present: present,
+        }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        if !self.present {
+            return;
+        }
+        unsafe {
+            while inb(self.base + LINE_STATUS) & LINE_TRANSMIT_EMPTY == 0 {}
+            outb(self.base + DATA, byte);
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if !self.present {
+            return None;
+        }
+        unsafe {
+            if inb(self.base + LINE_STATUS) & LINE_DATA_READY != 0 {
+                Some(inb(self.base + DATA))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+struct SerialDevice(&'static Mutex<SerialPort>);
+
+impl CharDevice for SerialDevice {
+    // Returns whatever has arrived without waiting for more.
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        let mut port = self.0.lock();
+        let mut count = 0;
+        while count < buf.len() {
+            match port.try_receive() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        let mut port = self.0.lock();
+        for &byte in buf {
+            port.send(byte);
+        }
+        Ok(buf.len())
+    }
+}
+
+pub fn init() {
+    for (index, port) in PORTS.iter().enumerate() {
+        if port.lock().init() {
+            let name = format!("ttyS{}", index);
+            devfs::register(&name, Arc::new(SerialDevice(*port)));
+        }
+    }
+}
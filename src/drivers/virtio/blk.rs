@@ -0,0 +1,226 @@
+// Virtio block devices. One request is in flight at a time, its header,
+// data and status living in DMA memory owned by the disk.
+
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use spin::Mutex;
+
+use block::{self, BlockDevice, Error};
+use drivers::pci::{self, Device, Driver};
+use int;
+use mem::{self, DmaBuffer};
+use time;
+use super::{device_type, Buffer, Transport, Virtqueue, DEVICE_BLOCK};
+
+const SECTOR_SIZE: usize = 512;
+
+const F_READ_ONLY: u64 = 1 << 5;
+const F_FLUSH: u64 = 1 << 9;
+
+const CONFIG_CAPACITY: usize = 0;
+
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+const REQUEST_FLUSH: u32 = 4;
+
+const STATUS_OK: u8 = 0;
+
+// the request header takes the first 16 bytes of the control page and the
+// status byte follows it
+const HEADER_SIZE: usize = 16;
+const STATUS_OFFSET: usize = HEADER_SIZE;
+
+const DATA_PAGES: usize = 16;
+
+const TIMEOUT_MS: u64 = 5000;
+
+static NEXT_DISK: AtomicUsize = ATOMIC_USIZE_INIT;
+
+struct Queue {
+    virtqueue: Virtqueue,
+    control: DmaBuffer,
+    data: DmaBuffer,
+}
+
+pub struct Disk {
+    transport: Arc<Transport>,
+    queue: Mutex<Queue>,
+    sectors: u64,
+    features: u64,
+}
+
+impl Disk {
+    // Runs one request with `length` bytes of the data buffer as its payload.
+    fn request(&self, queue: &mut Queue, kind: u32, sector: u64, length: usize)
+               -> block::Result<()> {
+        {
+            let control = queue.control.as_mut_slice();
+            for (index, byte) in control[..HEADER_SIZE].iter_mut().enumerate() {
+                *byte = match index {
+                    0...3 => (kind >> (index * 8)) as u8,
+                    8...15 => (sector >> ((index - 8) * 8)) as u8,
+                    _ => 0,
+                };
+            }
+            control[STATUS_OFFSET] = 0xff;
+        }
+
+        let control = queue.control.physical();
+        let data = queue.data.physical();
+        let header = Buffer {
+            address: control,
+            length: HEADER_SIZE as u32,
+            device_writes: false,
+        };
+        let payload = Buffer {
+            address: data,
+            length: length as u32,
+            device_writes: kind == REQUEST_IN,
+        };
+        let status = Buffer {
+            address: control + STATUS_OFFSET,
+            length: 1,
+            device_writes: true,
+        };
+
+        let submitted = if length > 0 {
+            queue.virtqueue.submit(&[header, payload, status])
+        } else {
+            queue.virtqueue.submit(&[header, status])
+        };
+        if submitted.is_none() {
+            return Err(Error::Device);
+        }
+        self.transport.notify(&queue.virtqueue);
+
+        let deadline = time::uptime_ms() + TIMEOUT_MS;
+        int::wait_until(|| queue.virtqueue.has_used() || time::uptime_ms() > deadline);
+        if queue.virtqueue.pop_used().is_none() {
+            return Err(Error::Timeout);
+        }
+
+        if queue.control.as_slice()[STATUS_OFFSET] != STATUS_OK {
+            return Err(Error::Device);
+        }
+        Ok(())
+    }
+
+    fn chunk_size(&self) -> usize {
+        DATA_PAGES * mem::PAGE_SIZE
+    }
+}
+
+impl BlockDevice for Disk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, block: u64, buf: &mut [u8]) -> block::Result<()> {
+        block::check_range(self, block, buf.len())?;
+        let chunk_size = self.chunk_size();
+        let mut queue = self.queue.lock();
+        for (index, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+            let sector = block + (index * chunk_size / SECTOR_SIZE) as u64;
+            self.request(&mut queue, REQUEST_IN, sector, chunk.len())?;
+            chunk.copy_from_slice(&queue.data.as_slice()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn write(&self, block: u64, buf: &[u8]) -> block::Result<()> {
+        if self.features & F_READ_ONLY != 0 {
+            return Err(Error::ReadOnly);
+        }
+        block::check_range(self, block, buf.len())?;
+        let chunk_size = self.chunk_size();
+        let mut queue = self.queue.lock();
+        for (index, chunk) in buf.chunks(chunk_size).enumerate() {
+            let sector = block + (index * chunk_size / SECTOR_SIZE) as u64;
+            queue.data.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            self.request(&mut queue, REQUEST_OUT, sector, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> block::Result<()> {
+        if self.features & F_FLUSH == 0 {
+            return Ok(());
+        }
+        self.request(&mut self.queue.lock(), REQUEST_FLUSH, 0, 0)
+    }
+}
+
+struct VirtioBlkDriver;
+
+impl Driver for VirtioBlkDriver {
+    fn name(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn matches(&self, device: &Device) -> bool {
+        device_type(device) == Some(DEVICE_BLOCK)
+    }
+
+    fn probe(&self, device: &Device) -> Result<(), &'static str> {
+        device.enable(true);
+        let transport = Arc::new(Transport::new(device)?);
+        let features = transport.initialize(F_READ_ONLY | F_FLUSH)?;
+
+        let virtqueue = match transport.setup_queue(0) {
+            Ok(virtqueue) => virtqueue,
+            Err(error) => {
+                transport.fail();
+                return Err(error);
+            }
+        };
+        let (control, data) = mem::with_controller(|mc| (mc.alloc_dma(1), mc.alloc_dma(DATA_PAGES)));
+        let (control, data) = match (control, data) {
+            (Some(control), Some(data)) => (control, data),
+            _ => {
+                transport.fail();
+                return Err("out of DMA memory");
+            }
+        };
+
+        // completion is noticed by polling the used ring, the interrupt only
+        // needs acknowledging so that the line drops again
+        if device.interrupt_line < 16 {
+            let transport = transport.clone();
+            int::register_irq(device.interrupt_line,
+                              Arc::new(move || {
+                                  transport.acknowledge_interrupt();
+                              }));
+        }
+        transport.finish_initialization();
+
+        let sectors = transport.read_config_u64(CONFIG_CAPACITY);
+        let name = format!("vd{}", (b'a' + NEXT_DISK.fetch_add(1, Ordering::Relaxed) as u8) as char);
+        info!("virtio-blk: {}: {} MiB ({}{})",
+              name,
+              sectors * SECTOR_SIZE as u64 >> 20,
+              if transport.is_modern() { "modern" } else { "legacy" },
+              if features & F_READ_ONLY != 0 { ", read-only" } else { "" });
+
+        block::register(&name,
+                        Arc::new(Disk {
+                            transport: transport,
+                            queue: Mutex::new(Queue {
+                                virtqueue: virtqueue,
+                                control: control,
+                                data: data,
+                            }),
+                            sectors: sectors,
+                            features: features,
+                        }));
+        Ok(())
+    }
+}
+
+pub fn init() {
+    pci::register(Arc::new(VirtioBlkDriver));
+}
@@ -0,0 +1,454 @@
+// Virtio devices on PCI: the legacy I/O port transport of transitional
+// devices, the capability-based transport of modern ones, and the split
+// virtqueues both of them use to exchange buffers with the device.
+
+pub mod blk;
+
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use collections::vec::Vec;
+use x86::shared::io::{inb, inl, inw, outb, outl, outw};
+
+use drivers::pci::{Bar, Device, CAP_VENDOR};
+use mem::{self, DmaBuffer, PAGE_SIZE};
+use mem::paging::{PhysicalAddress, NO_CACHE, NO_EXECUTE, WRITABLE, WRITE_THROUGH};
+
+pub const VENDOR_ID: u16 = 0x1af4;
+
+const LEGACY_DEVICE_FIRST: u16 = 0x1000;
+const LEGACY_DEVICE_LAST: u16 = 0x103f;
+const MODERN_DEVICE_BASE: u16 = 0x1040;
+
+pub const DEVICE_BLOCK: u16 = 2;
+
+// device status bits
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 128;
+
+pub const F_VERSION_1: u64 = 1 << 32;
+
+// legacy registers, relative to BAR 0
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_PFN: u16 = 0x08;
+const LEGACY_QUEUE_SIZE: u16 = 0x0c;
+const LEGACY_QUEUE_SELECT: u16 = 0x0e;
+const LEGACY_QUEUE_NOTIFY: u16 = 0x10;
+const LEGACY_STATUS: u16 = 0x12;
+const LEGACY_ISR: u16 = 0x13;
+const LEGACY_CONFIG: u16 = 0x14;
+
+// modern capability types and common configuration registers
+const CAP_COMMON: u8 = 1;
+const CAP_NOTIFY: u8 = 2;
+const CAP_ISR: u8 = 3;
+const CAP_DEVICE: u8 = 4;
+
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0c;
+const COMMON_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1c;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1e;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+// Returns the virtio device type of a PCI function, if it is one.
+pub fn device_type(device: &Device) -> Option<u16> {
+    if device.vendor_id != VENDOR_ID {
+        return None;
+    }
+    match device.device_id {
+        LEGACY_DEVICE_FIRST...LEGACY_DEVICE_LAST => Some(device.subsystem_id),
+        id if id >= MODERN_DEVICE_BASE => Some(id - MODERN_DEVICE_BASE),
+        _ => None,
+    }
+}
+
+pub enum Transport {
+    Legacy { port: u16 },
+    Modern {
+        common: usize,
+        notify: usize,
+        notify_multiplier: u32,
+        isr: usize,
+        config: usize,
+    },
+}
+
+fn mmio_read<T>(address: usize) -> T {
+    unsafe { ptr::read_volatile(address as *const T) }
+}
+
+fn mmio_write<T>(address: usize, value: T) {
+    unsafe { ptr::write_volatile(address as *mut T, value) }
+}
+
+impl Transport {
+    // Prefers the modern interface, which transitional devices offer too.
+    pub fn new(device: &Device) -> Result<Transport, &'static str> {
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut config = None;
+        for capability in device.capabilities().iter().filter(|c| c.id == CAP_VENDOR) {
+            let offset = capability.offset;
+            let bar = device.read8(offset + 4) as usize;
+            let address = match device.bars.get(bar) {
+                Some(&Bar::Memory { address, .. }) => {
+                    address as usize + device.read32(offset + 8) as usize
+                }
+                _ => continue,
+            };
+            let length = device.read32(offset + 12) as usize;
+            let flags = WRITABLE | NO_CACHE | WRITE_THROUGH | NO_EXECUTE;
+            mem::with_controller(|mc| mc.identity_map(address, address + length, flags));
+
+            match device.read8(offset + 3) {
+                CAP_COMMON => common = Some(address),
+                CAP_NOTIFY => notify = Some((address, device.read32(offset + 16))),
+                CAP_ISR => isr = Some(address),
+                CAP_DEVICE => config = Some(address),
+                _ => {}
+            }
+        }
+
+        if let (Some(common), Some((notify, multiplier)), Some(isr)) = (common, notify, isr) {
+            return Ok(Transport::Modern {
+                common: common,
+                notify: notify,
+                notify_multiplier: multiplier,
+                isr: isr,
+                config: config.unwrap_or(0),
+            });
+        }
+        match device.bar(0) {
+            Bar::Io { port, .. } if device.device_id <= LEGACY_DEVICE_LAST => {
+                Ok(Transport::Legacy { port: port })
+            }
+            _ => Err("no usable virtio transport"),
+        }
+    }
+
+    pub fn is_modern(&self) -> bool {
+        match *self {
+            Transport::Modern { .. } => true,
+            Transport::Legacy { .. } => false,
+        }
+    }
+
+    fn status(&self) -> u8 {
+        match *self {
+            Transport::Legacy { port } => unsafe { inb(port + LEGACY_STATUS) },
+            Transport::Modern { common, .. } => mmio_read(common + COMMON_STATUS),
+        }
+    }
+
+    fn set_status(&self, status: u8) {
+        match *self {
+            Transport::Legacy { port } => unsafe { outb(port + LEGACY_STATUS, status) },
+            Transport::Modern { common, .. } => mmio_write(common + COMMON_STATUS, status),
+        }
+    }
+
+    fn add_status(&self, status: u8) {
+        let current = self.status();
+        self.set_status(current | status)
+    }
+
+    fn device_features(&self) -> u64 {
+        match *self {
+            Transport::Legacy { port } => unsafe { inl(port + LEGACY_DEVICE_FEATURES) as u64 },
+            Transport::Modern { common, .. } => {
+                mmio_write(common + COMMON_DEVICE_FEATURE_SELECT, 0u32);
+                let low: u32 = mmio_read(common + COMMON_DEVICE_FEATURE);
+                mmio_write(common + COMMON_DEVICE_FEATURE_SELECT, 1u32);
+                let high: u32 = mmio_read(common + COMMON_DEVICE_FEATURE);
+                (high as u64) << 32 | low as u64
+            }
+        }
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        match *self {
+            Transport::Legacy { port } => unsafe {
+                outl(port + LEGACY_DRIVER_FEATURES, features as u32)
+            },
+            Transport::Modern { common, .. } => {
+                mmio_write(common + COMMON_DRIVER_FEATURE_SELECT, 0u32);
+                mmio_write(common + COMMON_DRIVER_FEATURE, features as u32);
+                mmio_write(common + COMMON_DRIVER_FEATURE_SELECT, 1u32);
+                mmio_write(common + COMMON_DRIVER_FEATURE, (features >> 32) as u32);
+            }
+        }
+    }
+
+    // Resets the device and negotiates the features both sides support out
+    // of `wanted`, returning them.
+    pub fn initialize(&self, wanted: u64) -> Result<u64, &'static str> {
+        self.set_status(0);
+        self.add_status(STATUS_ACKNOWLEDGE);
+        self.add_status(STATUS_DRIVER);
+
+        let mut wanted = wanted;
+        if self.is_modern() {
+            wanted |= F_VERSION_1;
+        }
+        let features = self.device_features() & wanted;
+        self.set_driver_features(features);
+
+        if self.is_modern() {
+            self.add_status(STATUS_FEATURES_OK);
+            if self.status() & STATUS_FEATURES_OK == 0 {
+                self.set_status(STATUS_FAILED);
+                return Err("device rejected the negotiated features");
+            }
+        }
+        Ok(features)
+    }
+
+    pub fn finish_initialization(&self) {
+        self.add_status(STATUS_DRIVER_OK);
+    }
+
+    pub fn fail(&self) {
+        self.add_status(STATUS_FAILED);
+    }
+
+    fn select_queue(&self, queue: u16) {
+        match *self {
+            Transport::Legacy { port } => unsafe { outw(port + LEGACY_QUEUE_SELECT, queue) },
+            Transport::Modern { common, .. } => mmio_write(common + COMMON_QUEUE_SELECT, queue),
+        }
+    }
+
+    fn queue_size(&self, queue: u16) -> u16 {
+        self.select_queue(queue);
+        match *self {
+            Transport::Legacy { port } => unsafe { inw(port + LEGACY_QUEUE_SIZE) },
+            Transport::Modern { common, .. } => mmio_read(common + COMMON_QUEUE_SIZE),
+        }
+    }
+
+    // Sets up virtqueue number `queue` in freshly allocated DMA memory.
+    pub fn setup_queue(&self, queue: u16) -> Result<Virtqueue, &'static str> {
+        let size = self.queue_size(queue);
+        if size == 0 {
+            return Err("queue does not exist");
+        }
+        let layout = Layout::new(size);
+        let memory = mem::with_controller(|mc| mc.alloc_dma(layout.pages()))
+            .ok_or("out of DMA memory")?;
+        let base = memory.physical();
+
+        self.select_queue(queue);
+        let notify = match *self {
+            Transport::Legacy { port } => {
+                unsafe {
+                    outl(port + LEGACY_QUEUE_PFN, (base / PAGE_SIZE) as u32);
+                }
+                0
+            }
+            Transport::Modern { common, notify, notify_multiplier, .. } => {
+                let write_address = |register: usize, address: PhysicalAddress| {
+                    mmio_write(common + register, address as u32);
+                    mmio_write(common + register + 4, (address as u64 >> 32) as u32);
+                };
+                write_address(COMMON_QUEUE_DESC, base + layout.descriptors);
+                write_address(COMMON_QUEUE_DRIVER, base + layout.available);
+                write_address(COMMON_QUEUE_DEVICE, base + layout.used);
+                mmio_write(common + COMMON_QUEUE_ENABLE, 1u16);
+                let offset: u16 = mmio_read(common + COMMON_QUEUE_NOTIFY_OFF);
+                notify + offset as usize * notify_multiplier as usize
+            }
+        };
+
+        Ok(Virtqueue {
+            index: queue,
+            size: size,
+            layout: layout,
+            memory: memory,
+            notify: notify,
+            free: (0..size).rev().collect(),
+            next_available: 0,
+            last_used: 0,
+        })
+    }
+
+    pub fn notify(&self, queue: &Virtqueue) {
+        atomic::fence(Ordering::SeqCst);
+        match *self {
+            Transport::Legacy { port } => unsafe {
+                outw(port + LEGACY_QUEUE_NOTIFY, queue.index)
+            },
+            Transport::Modern { .. } => mmio_write(queue.notify, queue.index),
+        }
+    }
+
+    // Reading the ISR status acknowledges the interrupt.
+    pub fn acknowledge_interrupt(&self) -> u8 {
+        match *self {
+            Transport::Legacy { port } => unsafe { inb(port + LEGACY_ISR) },
+            Transport::Modern { isr, .. } => mmio_read(isr),
+        }
+    }
+
+    pub fn read_config_u32(&self, offset: usize) -> u32 {
+        match *self {
+            Transport::Legacy { port } => unsafe { inl(port + LEGACY_CONFIG + offset as u16) },
+            Transport::Modern { config, .. } => mmio_read(config + offset),
+        }
+    }
+
+    pub fn read_config_u64(&self, offset: usize) -> u64 {
+        let low = self.read_config_u32(offset) as u64;
+        let high = self.read_config_u32(offset + 4) as u64;
+        high << 32 | low
+    }
+}
+
+// Where the three parts of a split virtqueue live within its memory, laid
+// out the way the legacy interface requires.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    descriptors: usize,
+    available: usize,
+    used: usize,
+    size: usize,
+}
+
+impl Layout {
+    fn new(queue_size: u16) -> Layout {
+        let queue_size = queue_size as usize;
+        let align = |offset: usize| (offset + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let available = 16 * queue_size;
+        let used = align(available + 6 + 2 * queue_size);
+        Layout {
+            descriptors: 0,
+            available: available,
+            used: used,
+            size: align(used + 6 + 8 * queue_size),
+        }
+    }
+
+    fn pages(&self) -> usize {
+        self.size / PAGE_SIZE
+    }
+}
+
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+// A buffer handed to the device: its physical address, its length, and
+// whether the device writes it rather than reads it.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub address: PhysicalAddress,
+    pub length: u32,
+    pub device_writes: bool,
+}
+
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    layout: Layout,
+    memory: DmaBuffer,
+    notify: usize,
+    free: Vec<u16>,
+    next_available: u16,
+    last_used: u16,
+}
+
+impl Virtqueue {
+    fn base(&self) -> usize {
+        self.memory.as_ptr() as usize
+    }
+
+    fn descriptor(&self, index: u16) -> *mut Descriptor {
+        let table = (self.base() + self.layout.descriptors) as *mut Descriptor;
+        table.wrapping_offset(index as isize)
+    }
+
+    // Chains the buffers into one request and makes it available to the
+    // device, returning the head descriptor that identifies it.
+    pub fn submit(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free.len() {
+            return None;
+        }
+
+        let mut next = 0;
+        let mut head = 0;
+        for (position, buffer) in buffers.iter().enumerate().rev() {
+            let index = self.free.pop().unwrap();
+            let mut flags = if buffer.device_writes { DESC_WRITE } else { 0 };
+            if position + 1 < buffers.len() {
+                flags |= DESC_NEXT;
+            }
+            unsafe {
+                ptr::write_volatile(self.descriptor(index),
+                                    Descriptor {
+                                        address: buffer.address as u64,
+                                        length: buffer.length,
+                                        flags: flags,
+                                        next: next,
+                                    });
+            }
+            next = index;
+            head = index;
+        }
+
+        let available = self.base() + self.layout.available;
+        let slot = available + 4 + 2 * (self.next_available % self.size) as usize;
+        mmio_write(slot, head);
+        self.next_available = self.next_available.wrapping_add(1);
+        // the ring entry must be visible before the index that publishes it
+        atomic::fence(Ordering::SeqCst);
+        mmio_write(available + 2, self.next_available);
+        Some(head)
+    }
+
+    pub fn has_used(&self) -> bool {
+        let used: u16 = mmio_read(self.base() + self.layout.used + 2);
+        used != self.last_used
+    }
+
+    // Takes the next request the device has finished with, returning its
+    // head descriptor and the number of bytes the device wrote.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        atomic::fence(Ordering::SeqCst);
+        let element = self.base() + self.layout.used + 4 + 8 * (self.last_used % self.size) as usize;
+        let head = mmio_read::<u32>(element) as u16;
+        let length = mmio_read::<u32>(element + 4);
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let mut index = head;
+        loop {
+            let descriptor = unsafe { ptr::read_volatile(self.descriptor(index)) };
+            self.free.push(index);
+            if descriptor.flags & DESC_NEXT == 0 {
+                break;
+            }
+            index = descriptor.next;
+        }
+        Some((head, length))
+    }
+}
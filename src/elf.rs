@@ -0,0 +1,230 @@
+use core::mem::size_of;
+use core::slice;
+
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+
+pub const TYPE_EXECUTABLE: u16 = 2;
+pub const MACHINE_X86_64: u16 = 62;
+
+pub const PT_NULL: u32 = 0;
+pub const PT_LOAD: u32 = 1;
+pub const PT_INTERP: u32 = 3;
+pub const PT_PHDR: u32 = 6;
+
+pub const SHT_SYMTAB: u32 = 2;
+
+pub const STT_FUNC: u8 = 2;
+
+bitflags! {
+    pub flags ProgramFlags: u32 {
+        const PF_X = 1 << 0,
+        const PF_W = 1 << 1,
+        const PF_R = 1 << 2,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Header {
+    pub ident: [u8; 16],
+    pub typ: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub flags: u32,
+    pub ehsize: u16,
+    pub phentsize: u16,
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub typ: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    pub fn flags(&self) -> ProgramFlags {
+        ProgramFlags::from_bits_truncate(self.flags)
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.vaddr as usize
+    }
+
+    pub fn end_address(&self) -> usize {
+        (self.vaddr + self.memsz) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub typ: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl SectionHeader {
+    pub fn start_address(&self) -> usize {
+        self.addr as usize
+    }
+
+    pub fn end_address(&self) -> usize {
+        (self.addr + self.size) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Symbol {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub shndx: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol {
+    pub fn is_function(&self) -> bool {
+        self.info & 0xf == STT_FUNC
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Truncated,
+    Misaligned,
+    BadMagic,
+    NotElf64,
+    NotLittleEndian,
+    BadVersion,
+    NotExecutable,
+    WrongMachine,
+    BadProgramHeaders,
+    BadSegment,
+    Interpreted,
+}
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    header: &'a Header,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Elf<'a>, Error> {
+        if data.len() < size_of::<Header>() {
+            return Err(Error::Truncated);
+        }
+        if data.as_ptr() as usize % 8 != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        let header = unsafe { &*(data.as_ptr() as *const Header) };
+        if header.ident[0..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if header.ident[4] != CLASS_64 {
+            return Err(Error::NotElf64);
+        }
+        if header.ident[5] != DATA_LITTLE_ENDIAN {
+            return Err(Error::NotLittleEndian);
+        }
+        if header.ident[6] != VERSION_CURRENT || header.version != VERSION_CURRENT as u32 {
+            return Err(Error::BadVersion);
+        }
+        if header.typ != TYPE_EXECUTABLE {
+            return Err(Error::NotExecutable);
+        }
+        if header.machine != MACHINE_X86_64 {
+            return Err(Error::WrongMachine);
+        }
+
+        let elf = Elf {
+            data: data,
+            header: header,
+        };
+        elf.check_program_headers()?;
+        Ok(elf)
+    }
+
+    pub fn header(&self) -> &'a Header {
+        self.header
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn entry(&self) -> usize {
+        self.header.entry as usize
+    }
+
+    pub fn program_headers(&self) -> &'a [ProgramHeader] {
+        let start = self.header.phoff as usize;
+        let count = self.header.phnum as usize;
+        unsafe { slice::from_raw_parts(self.data[start..].as_ptr() as *const ProgramHeader, count) }
+    }
+
+    pub fn segment_data(&self, ph: &ProgramHeader) -> &'a [u8] {
+        let start = ph.offset as usize;
+        &self.data[start..start + ph.filesz as usize]
+    }
+
+    fn check_program_headers(&self) -> Result<(), Error> {
+        let start = self.header.phoff as usize;
+        let count = self.header.phnum as usize;
+        if self.header.phentsize as usize != size_of::<ProgramHeader>() || start % 8 != 0 {
+            return Err(Error::BadProgramHeaders);
+        }
+        match count.checked_mul(size_of::<ProgramHeader>()).and_then(|len| len.checked_add(start)) {
+            Some(end) if end <= self.data.len() => {}
+            _ => return Err(Error::Truncated),
+        }
+
+        for ph in self.program_headers() {
+            match ph.typ {
+                PT_INTERP => return Err(Error::Interpreted),
+                PT_LOAD => {
+                    let file_end = ph.offset.checked_add(ph.filesz);
+                    let mem_end = ph.vaddr.checked_add(ph.memsz);
+                    if ph.filesz > ph.memsz || file_end.is_none() || mem_end.is_none() {
+                        return Err(Error::BadSegment);
+                    }
+                    if file_end.unwrap() > self.data.len() as u64 {
+                        return Err(Error::Truncated);
+                    }
+                    if ph.align > 1 && ph.vaddr % ph.align != ph.offset % ph.align {
+                        return Err(Error::BadSegment);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// The emergency console, for panics and fatal exceptions. It has to work
+// whatever state the kernel is in, in the middle of printing included, so it
+// waits for no lock: it writes on the screen as it is and to COM1 directly,
+// with interrupts off for good.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+
+use x86::shared::control_regs;
+
+use drivers::serial::SerialPort;
+use int;
+use mem;
+use symbols;
+use vga::{self, Color, ColorSpec, Writer};
+
+const MAX_FRAMES: usize = 32;
+
+// set once an error is being reported, to catch one in the reporting itself
+static REPORTING: AtomicBool = ATOMIC_BOOL_INIT;
+
+struct Console {
+    writer: Writer,
+    serial: SerialPort,
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let _ = self.writer.write_str(s);
+        self.serial.write_str(s)
+    }
+}
+
+// Reports a fatal error with the state of the CPU and a backtrace, and stops
+// the machine.
+pub fn fatal(args: fmt::Arguments) -> ! {
+    int::disable();
+    if REPORTING.swap(true, Ordering::SeqCst) {
+        // the report failed; the serial port may still get this far
+        let _ = write!(SerialPort::emergency(), "\nkernel error while reporting an error\n");
+        halt();
+    }
+    let mut console = console();
+    report(&mut console, args);
+    let _ = write_cpu_state(&mut console);
+    let _ = write_backtrace(&mut console);
+    halt()
+}
+
+// Reports an error the kernel carries on from, such as a breakpoint.
+pub fn error(args: fmt::Arguments) {
+    int::without_interrupts(|| {
+        let mut console = console();
+        report(&mut console, args);
+    })
+}
+
+fn console() -> Console {
+    Console {
+        writer: unsafe { vga::emergency_writer() },
+        serial: SerialPort::emergency(),
+    }
+}
+
+fn report(console: &mut Console, args: fmt::Arguments) {
+    console.writer.set_color(ColorSpec::new(Color::LightRed, Color::Black));
+    let _ = console.write_str("\nkernel error: ");
+    console.writer.set_color(ColorSpec::default());
+    let _ = console.write_fmt(args);
+    let _ = console.write_str("\n");
+}
+
+fn write_cpu_state(out: &mut Write) -> fmt::Result {
+    let (rsp, rbp, rflags, cr4): (u64, u64, u64, u64);
+    unsafe {
+        asm!("mov $0, rsp; mov $1, rbp; pushfq; pop $2; mov $3, cr4"
+             : "=r"(rsp), "=r"(rbp), "=r"(rflags), "=r"(cr4) ::: "intel", "volatile");
+    }
+    let (cr0, cr2, cr3) = unsafe {
+        (control_regs::cr0().bits(), control_regs::cr2(), control_regs::cr3())
+    };
+    writeln!(out, "rsp {:#018x} rbp {:#018x} rflags {:#x}", rsp, rbp, rflags)?;
+    writeln!(out,
+             "cr0 {:#x} cr2 {:#x} cr3 {:#x} cr4 {:#x}",
+             cr0,
+             cr2,
+             cr3,
+             cr4)
+}
+
+// Follows the chain of saved frame pointers up the stack, naming the function
+// each return address is in.
+fn write_backtrace(out: &mut Write) -> fmt::Result {
+    let mut rbp: usize;
+    unsafe {
+        asm!("mov $0, rbp" : "=r"(rbp) ::: "intel", "volatile");
+    }
+    writeln!(out, "backtrace:")?;
+    for _ in 0..MAX_FRAMES {
+        // the entry code clears rbp, which ends the chain
+        if rbp == 0 || rbp % 8 != 0 || rbp > usize::max_value() - 16 {
+            break;
+        }
+        // a corrupted frame pointer may point anywhere, and reading there
+        // would fault in the middle of the report
+        if !mem::is_mapped(rbp) || !mem::is_mapped(rbp + 8) {
+            writeln!(out, "  (frame pointer {:#x} is not mapped)", rbp)?;
+            break;
+        }
+        let (next, ret) = unsafe { (*(rbp as *const usize), *((rbp + 8) as *const usize)) };
+        if ret == 0 {
+            break;
+        }
+        // the return address may be past the end of a call at the very end of
+        // a function, so look up the call itself
+        match symbols::resolve(ret - 1) {
+            Some((name, offset)) => writeln!(out, "  {:#018x} {}+{:#x}", ret, name, offset + 1)?,
+            None => writeln!(out, "  {:#018x}", ret)?,
+        }
+        // callers' frames are further up the stack; anything else is garbage
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+    Ok(())
+}
+
+fn halt() -> ! {
+    loop {
+        unsafe {
+            asm!("cli; hlt" :::: "volatile");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// A filesystem of device nodes. Drivers register their devices here and
+// the nodes show up under wherever devfs is mounted, normally /dev.
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use spin::Mutex;
+
+use fs::{self, DirEntry, FileType, Filesystem, Inode, Metadata};
+use int;
+use kmsg::KMSG;
+
+const ROOT_INO: u64 = 1;
+
+pub trait CharDevice: Send + Sync {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize>;
+    fn write(&self, offset: u64, buf: &[u8]) -> fs::Result<usize>;
+
+    // See `Inode::read_from`.
+    fn read_from(&self, offset: u64, buf: &mut [u8]) -> fs::Result<(u64, usize)> {
+        self.read(offset, buf).map(|count| (offset, count))
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+}
+
+struct Node {
+    name: String,
+    kind: FileType,
+    device: Arc<CharDevice>,
+}
+
+lazy_static! {
+    static ref DEVICES: Mutex<Vec<Node>> = Mutex::new(Vec::new());
+}
+
+pub fn register(name: &str, device: Arc<CharDevice>) {
+    register_node(name, FileType::CharDevice, device)
+}
+
+// Block devices register through `block::register`, which wraps them in a
+// byte-granular adapter and shows them here as `FileType::BlockDevice`.
+pub fn register_node(name: &str, kind: FileType, device: Arc<CharDevice>) {
+    let mut devices = DEVICES.lock();
+    assert!(devices.iter().all(|node| node.name != name), "device registered twice");
+    devices.push(Node {
+        name: name.to_string(),
+        kind: kind,
+        device: device,
+    });
+}
+
+pub fn init() {
+    assert_has_not_been_called!();
+    register("null", Arc::new(Null));
+    register("zero", Arc::new(Zero));
+    register("kmsg", Arc::new(Kmsg));
+}
+
+pub struct DevFs {
+    dev: usize,
+}
+
+impl DevFs {
+    pub fn new() -> DevFs {
+        DevFs { dev: fs::alloc_dev() }
+    }
+}
+
+impl Filesystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<Inode> {
+        Arc::new(DevRoot { dev: self.dev })
+    }
+}
+
+struct DevRoot {
+    dev: usize,
+}
+
+impl Inode for DevRoot {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: self.dev,
+            ino: ROOT_INO,
+            kind: FileType::Directory,
+            size: 0,
+            mode: 0o755,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> fs::Result<Arc<Inode>> {
+        let devices = DEVICES.lock();
+        match devices.iter().position(|node| node.name == name) {
+            Some(index) => {
+                Ok(Arc::new(DevNode {
+                    dev: self.dev,
+                    ino: ROOT_INO + 1 + index as u64,
+                    kind: devices[index].kind,
+                    device: devices[index].device.clone(),
+                }))
+            }
+            None => Err(fs::Error::NotFound),
+        }
+    }
+
+    fn readdir(&self) -> fs::Result<Vec<DirEntry>> {
+        Ok(DEVICES.lock()
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                DirEntry {
+                    name: node.name.clone(),
+                    ino: ROOT_INO + 1 + index as u64,
+                    kind: node.kind,
+                }
+            })
+            .collect())
+    }
+}
+
+struct DevNode {
+    dev: usize,
+    ino: u64,
+    kind: FileType,
+    device: Arc<CharDevice>,
+}
+
+impl Inode for DevNode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: self.dev,
+            ino: self.ino,
+            kind: self.kind,
+            size: self.device.size(),
+            mode: 0o666,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        self.device.read(offset, buf)
+    }
+
+    fn read_from(&self, offset: u64, buf: &mut [u8]) -> fs::Result<(u64, usize)> {
+        self.device.read_from(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        self.device.write(offset, buf)
+    }
+
+    // opening with O_TRUNCATE is harmless for a device
+    fn truncate(&self, _size: u64) -> fs::Result<()> {
+        Ok(())
+    }
+}
+
+struct Null;
+
+impl CharDevice for Null {
+    fn read(&self, _offset: u64, _buf: &mut [u8]) -> fs::Result<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+struct Zero;
+
+impl CharDevice for Zero {
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        for byte in buf.iter_mut() {
+            *byte = 0;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+struct Kmsg;
+
+impl CharDevice for Kmsg {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        self.read_from(offset, buf).map(|(_, count)| count)
+    }
+
+    // Readers that fell behind skip to the oldest message still kept.
+    fn read_from(&self, offset: u64, buf: &mut [u8]) -> fs::Result<(u64, usize)> {
+        let (start, count) =
+            int::without_interrupts(|| KMSG.lock().read_at(offset as usize, buf));
+        Ok((start as u64, count))
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        int::without_interrupts(|| KMSG.lock().write(buf));
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,384 @@
+// A read-only ext2 filesystem on a block device, enough to use images built
+// by mke2fs on Linux. Directories are read linearly, which also covers
+// those carrying a hashed index.
+
+use core::cmp::min;
+use core::str;
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+
+use block::{self, BlockDevice};
+use fs::{self, DirEntry, Error, FileType, Filesystem, Inode, Metadata, Result};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xef53;
+
+const ROOT_INO: u32 = 2;
+
+const GOOD_OLD_REV: u32 = 0;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+const GROUP_DESCRIPTOR_SIZE: u64 = 32;
+
+// incompatible features that do not change how anything is read
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+const DIRECT_BLOCKS: usize = 12;
+const INDIRECT_BLOCK: usize = 12;
+const DOUBLE_INDIRECT_BLOCK: usize = 13;
+const TRIPLE_INDIRECT_BLOCK: usize = 14;
+
+const S_IFMT: u16 = 0xf000;
+const S_IFIFO: u16 = 0x1000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFBLK: u16 = 0x6000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xa000;
+
+// symbolic links this short keep their target in the block pointers
+const FAST_SYMLINK_MAX: u64 = 60;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    bytes[offset] as u16 | (bytes[offset + 1] as u16) << 8
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u16_at(bytes, offset) as u32 | (u16_at(bytes, offset + 2) as u32) << 16
+}
+
+struct Superblock {
+    inodes_count: u32,
+    block_size: u64,
+    first_data_block: u32,
+    inodes_per_group: u32,
+    inode_size: usize,
+    feature_incompat: u32,
+}
+
+impl Superblock {
+    fn parse(raw: &[u8]) -> Result<Superblock> {
+        if u16_at(raw, 56) != MAGIC {
+            return Err(Error::InvalidArgument);
+        }
+        let log_block_size = u32_at(raw, 24);
+        let revision = u32_at(raw, 76);
+        let (inode_size, feature_incompat) = if revision == GOOD_OLD_REV {
+            (GOOD_OLD_INODE_SIZE, 0)
+        } else {
+            (u16_at(raw, 88) as usize, u32_at(raw, 96))
+        };
+
+        if log_block_size > 6 || inode_size < GOOD_OLD_INODE_SIZE ||
+           !inode_size.is_power_of_two() {
+            return Err(Error::InvalidArgument);
+        }
+        if feature_incompat & !INCOMPAT_SUPPORTED != 0 {
+            return Err(Error::NotSupported);
+        }
+
+        let superblock = Superblock {
+            inodes_count: u32_at(raw, 0),
+            block_size: 1024 << log_block_size,
+            first_data_block: u32_at(raw, 20),
+            inodes_per_group: u32_at(raw, 40),
+            inode_size: inode_size,
+            feature_incompat: feature_incompat,
+        };
+        if superblock.inodes_per_group == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(superblock)
+    }
+}
+
+pub fn mount(device: Arc<BlockDevice>) -> Result<Arc<Filesystem>> {
+    let mut raw = [0; SUPERBLOCK_SIZE];
+    block::read_bytes(&*device, SUPERBLOCK_OFFSET, &mut raw)?;
+    let superblock = Superblock::parse(&raw)?;
+
+    let fs = Ext2 {
+        device: device,
+        superblock: superblock,
+        dev: fs::alloc_dev(),
+    };
+    if fs.read_inode(ROOT_INO)?.kind() != FileType::Directory {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(Arc::new(Ext2Fs(Arc::new(fs))))
+}
+
+struct Ext2 {
+    device: Arc<BlockDevice>,
+    superblock: Superblock,
+    dev: usize,
+}
+
+struct RawInode {
+    mode: u16,
+    size: u64,
+    // 512 byte sectors in use, for telling fast symbolic links apart
+    sectors: u32,
+    blocks: [u32; 15],
+}
+
+impl RawInode {
+    fn kind(&self) -> FileType {
+        match self.mode & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFCHR | S_IFIFO => FileType::CharDevice,
+            S_IFBLK => FileType::BlockDevice,
+            _ => FileType::File,
+        }
+    }
+}
+
+impl Ext2 {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        block::read_bytes(&*self.device, offset, buf).map_err(Error::from)
+    }
+
+    fn block_offset(&self, block: u32) -> u64 {
+        block as u64 * self.superblock.block_size
+    }
+
+    fn read_inode(&self, ino: u32) -> Result<RawInode> {
+        if ino == 0 || ino > self.superblock.inodes_count {
+            return Err(Error::Io);
+        }
+        let group = (ino - 1) / self.superblock.inodes_per_group;
+        let index = (ino - 1) % self.superblock.inodes_per_group;
+
+        // the descriptor table starts in the block after the superblock
+        let mut descriptor = [0; GROUP_DESCRIPTOR_SIZE as usize];
+        let table = self.block_offset(self.superblock.first_data_block + 1);
+        self.read(table + group as u64 * GROUP_DESCRIPTOR_SIZE, &mut descriptor)?;
+        let inode_table = u32_at(&descriptor, 8);
+
+        let mut raw = [0; GOOD_OLD_INODE_SIZE];
+        let offset = self.block_offset(inode_table) +
+                     index as u64 * self.superblock.inode_size as u64;
+        self.read(offset, &mut raw)?;
+
+        let mode = u16_at(&raw, 0);
+        let mut size = u32_at(&raw, 4) as u64;
+        if mode & S_IFMT == S_IFREG {
+            // large files keep the upper half of the size in i_dir_acl
+            size |= (u32_at(&raw, 108) as u64) << 32;
+        }
+        let mut blocks = [0; 15];
+        for (index, block) in blocks.iter_mut().enumerate() {
+            *block = u32_at(&raw, 40 + index * 4);
+        }
+        Ok(RawInode {
+            mode: mode,
+            size: size,
+            sectors: u32_at(&raw, 28),
+            blocks: blocks,
+        })
+    }
+
+    fn pointer(&self, block: u32, index: u64) -> Result<u32> {
+        if block == 0 {
+            return Ok(0);
+        }
+        let mut bytes = [0; 4];
+        self.read(self.block_offset(block) + index * 4, &mut bytes)?;
+        Ok(u32_at(&bytes, 0))
+    }
+
+    // The block holding logical block `index` of a file, 0 for a hole.
+    fn map_block(&self, inode: &RawInode, index: u64) -> Result<u32> {
+        let per_block = self.superblock.block_size / 4;
+        let mut index = index;
+        if index < DIRECT_BLOCKS as u64 {
+            return Ok(inode.blocks[index as usize]);
+        }
+        index -= DIRECT_BLOCKS as u64;
+
+        if index < per_block {
+            return self.pointer(inode.blocks[INDIRECT_BLOCK], index);
+        }
+        index -= per_block;
+
+        if index < per_block * per_block {
+            let indirect = self.pointer(inode.blocks[DOUBLE_INDIRECT_BLOCK], index / per_block)?;
+            return self.pointer(indirect, index % per_block);
+        }
+        index -= per_block * per_block;
+
+        if index < per_block * per_block * per_block {
+            let double = self.pointer(inode.blocks[TRIPLE_INDIRECT_BLOCK],
+                                      index / (per_block * per_block))?;
+            let indirect = self.pointer(double, index / per_block % per_block)?;
+            return self.pointer(indirect, index % per_block);
+        }
+        Err(Error::Io)
+    }
+
+    fn read_data(&self, inode: &RawInode, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+        let len = min(buf.len() as u64, inode.size - offset) as usize;
+        let block_size = self.superblock.block_size;
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let within = position % block_size;
+            let count = min(len - done, (block_size - within) as usize);
+            match self.map_block(inode, position / block_size)? {
+                0 => {
+                    for byte in &mut buf[done..done + count] {
+                        *byte = 0;
+                    }
+                }
+                block => self.read(self.block_offset(block) + within, &mut buf[done..done + count])?,
+            }
+            done += count;
+        }
+        Ok(len)
+    }
+
+    // The entries of a directory as (name, inode, type), leaving out the dot
+    // entries. The type is None when the filesystem does not record it.
+    fn read_dir(&self, inode: &RawInode) -> Result<Vec<(String, u32, Option<FileType>)>> {
+        let mut data = vec![0; inode.size as usize];
+        let len = self.read_data(inode, 0, &mut data)?;
+        let has_type = self.superblock.feature_incompat & INCOMPAT_FILETYPE != 0;
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= len {
+            let ino = u32_at(&data, offset);
+            let record_len = u16_at(&data, offset + 4) as usize;
+            let name_len = if has_type {
+                data[offset + 6] as usize
+            } else {
+                u16_at(&data, offset + 6) as usize
+            };
+            if record_len < 8 || offset + record_len > len || 8 + name_len > record_len {
+                return Err(Error::Io);
+            }
+
+            let name = str::from_utf8(&data[offset + 8..offset + 8 + name_len]).unwrap_or("");
+            if ino != 0 && !name.is_empty() && name != "." && name != ".." {
+                let kind = if has_type {
+                    match data[offset + 7] {
+                        1 => Some(FileType::File),
+                        2 => Some(FileType::Directory),
+                        3 | 5 => Some(FileType::CharDevice),
+                        4 => Some(FileType::BlockDevice),
+                        7 => Some(FileType::Symlink),
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+                entries.push((name.to_string(), ino, kind));
+            }
+            offset += record_len;
+        }
+        Ok(entries)
+    }
+}
+
+pub struct Ext2Fs(Arc<Ext2>);
+
+impl Filesystem for Ext2Fs {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> Arc<Inode> {
+        let inode = self.0.read_inode(ROOT_INO).expect("ext2: root inode became unreadable");
+        Arc::new(Ext2Inode {
+            fs: self.0.clone(),
+            ino: ROOT_INO,
+            inode: inode,
+        })
+    }
+}
+
+struct Ext2Inode {
+    fs: Arc<Ext2>,
+    ino: u32,
+    inode: RawInode,
+}
+
+impl Inode for Ext2Inode {
+    fn metadata(&self) -> Metadata {
+        let kind = self.inode.kind();
+        Metadata {
+            dev: self.fs.dev,
+            ino: self.ino as u64,
+            kind: kind,
+            size: if kind == FileType::Directory { 0 } else { self.inode.size },
+            mode: (self.inode.mode & 0o7777) as u32,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<Inode>> {
+        if self.inode.kind() != FileType::Directory {
+            return Err(Error::NotADirectory);
+        }
+        let ino = self.fs
+            .read_dir(&self.inode)?
+            .into_iter()
+            .find(|&(ref entry, _, _)| &entry[..] == name)
+            .map(|(_, ino, _)| ino)
+            .ok_or(Error::NotFound)?;
+        Ok(Arc::new(Ext2Inode {
+            fs: self.fs.clone(),
+            ino: ino,
+            inode: self.fs.read_inode(ino)?,
+        }))
+    }
+
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        if self.inode.kind() != FileType::Directory {
+            return Err(Error::NotADirectory);
+        }
+        let mut entries = Vec::new();
+        for (name, ino, kind) in self.fs.read_dir(&self.inode)? {
+            let kind = match kind {
+                Some(kind) => kind,
+                None => self.fs.read_inode(ino)?.kind(),
+            };
+            entries.push(DirEntry {
+                name: name,
+                ino: ino as u64,
+                kind: kind,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        if self.inode.kind() == FileType::Directory {
+            return Err(Error::IsADirectory);
+        }
+        self.fs.read_data(&self.inode, offset, buf)
+    }
+
+    fn readlink(&self) -> Result<String> {
+        if self.inode.kind() != FileType::Symlink {
+            return Err(Error::InvalidArgument);
+        }
+        let mut target = vec![0; self.inode.size as usize];
+        if self.inode.size < FAST_SYMLINK_MAX && self.inode.sectors == 0 {
+            for (index, byte) in target.iter_mut().enumerate() {
+                *byte = (self.inode.blocks[index / 4] >> (index % 4 * 8)) as u8;
+            }
+        } else {
+            let len = self.fs.read_data(&self.inode, 0, &mut target)?;
+            target.truncate(len);
+        }
+        String::from_utf8(target).map_err(|_| Error::Io)
+    }
+}
@@ -0,0 +1,145 @@
+// The BIOS parameter block in the first sector of a FAT volume, and the
+// layout of the volume that follows from it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub fat_type: FatType,
+    pub bytes_per_sector: u64,
+    pub sectors_per_cluster: u64,
+    pub fat_start: u64,
+    pub fat_sectors: u64,
+    pub fat_count: u64,
+    // the fixed root directory of FAT12 and FAT16, in sectors and entries
+    pub root_start: u64,
+    pub root_entries: u64,
+    // FAT32 keeps its root directory in an ordinary cluster chain
+    pub root_cluster: u32,
+    pub data_start: u64,
+    pub cluster_count: u32,
+    pub fsinfo_sector: Option<u64>,
+}
+
+fn u16_at(sector: &[u8], offset: usize) -> u64 {
+    (sector[offset] as u64) | (sector[offset + 1] as u64) << 8
+}
+
+fn u32_at(sector: &[u8], offset: usize) -> u64 {
+    u16_at(sector, offset) | u16_at(sector, offset + 2) << 16
+}
+
+impl Layout {
+    pub fn parse(sector: &[u8]) -> Option<Layout> {
+        if sector.len() < 512 || sector[510] != 0x55 || sector[511] != 0xaa {
+            return None;
+        }
+
+        let bytes_per_sector = u16_at(sector, 0x0b);
+        let sectors_per_cluster = sector[0x0d] as u64;
+        let reserved = u16_at(sector, 0x0e);
+        let fat_count = sector[0x10] as u64;
+        let root_entries = u16_at(sector, 0x11);
+        let total_sectors = match u16_at(sector, 0x13) {
+            0 => u32_at(sector, 0x20),
+            count => count,
+        };
+        let fat_sectors = match u16_at(sector, 0x16) {
+            0 => u32_at(sector, 0x24),
+            count => count,
+        };
+
+        match bytes_per_sector {
+            512 | 1024 | 2048 | 4096 => {}
+            _ => return None,
+        }
+        if !sectors_per_cluster.is_power_of_two() || reserved == 0 || fat_count == 0 ||
+           fat_sectors == 0 {
+            return None;
+        }
+
+        let root_start = reserved + fat_count * fat_sectors;
+        let root_sectors = (root_entries * 32 + bytes_per_sector - 1) / bytes_per_sector;
+        let data_start = root_start + root_sectors;
+        if total_sectors <= data_start {
+            return None;
+        }
+        let cluster_count = (total_sectors - data_start) / sectors_per_cluster;
+
+        // the type follows from the cluster count alone, whatever the label says
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let (root_cluster, fsinfo_sector) = if fat_type == FatType::Fat32 {
+            let fsinfo = u16_at(sector, 0x30);
+            (u32_at(sector, 0x2c) as u32,
+             if fsinfo == 0 || fsinfo == 0xffff { None } else { Some(fsinfo) })
+        } else {
+            if root_entries == 0 {
+                return None;
+            }
+            (0, None)
+        };
+
+        Some(Layout {
+            fat_type: fat_type,
+            bytes_per_sector: bytes_per_sector,
+            sectors_per_cluster: sectors_per_cluster,
+            fat_start: reserved,
+            fat_sectors: fat_sectors,
+            fat_count: fat_count,
+            root_start: root_start,
+            root_entries: root_entries,
+            root_cluster: root_cluster,
+            data_start: data_start,
+            cluster_count: cluster_count as u32,
+            fsinfo_sector: fsinfo_sector,
+        })
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    // Byte offset of a data cluster; clusters are numbered from 2.
+    pub fn cluster_offset(&self, cluster: u32) -> u64 {
+        (self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster) * self.bytes_per_sector
+    }
+
+    pub fn root_offset(&self) -> u64 {
+        self.root_start * self.bytes_per_sector
+    }
+
+    // Byte offset of a cluster's entry within the `copy`th FAT.
+    pub fn fat_entry_offset(&self, cluster: u32, copy: u64) -> u64 {
+        let within = match self.fat_type {
+            FatType::Fat12 => cluster as u64 + cluster as u64 / 2,
+            FatType::Fat16 => cluster as u64 * 2,
+            FatType::Fat32 => cluster as u64 * 4,
+        };
+        (self.fat_start + copy * self.fat_sectors) * self.bytes_per_sector + within
+    }
+
+    // Entries at or above this value end a chain.
+    pub fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xff8,
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+}
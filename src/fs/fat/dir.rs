@@ -0,0 +1,386 @@
+// Directory entries: 32 byte slots holding an 8.3 short name, preceded by
+// the slots of a long file name when the name does not fit 8.3 as it is.
+
+use core::char;
+use core::cmp::min;
+
+use collections::string::String;
+use collections::vec::Vec;
+
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+
+pub const END: u8 = 0x00;
+pub const DELETED: u8 = 0xe5;
+// a short name starting with 0xe5 is stored with 0x05 instead
+const ESCAPED_E5: u8 = 0x05;
+
+// case flags (byte 12) for 8.3 names that are entirely lower case
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME_UNITS: usize = 255;
+
+// 1980-01-01, the earliest date FAT can hold; there is no clock to ask
+const DEFAULT_DATE: u16 = 1 << 5 | 1;
+
+pub struct Entry {
+    pub name: String,
+    pub attr: u8,
+    pub cluster: u32,
+    pub size: u32,
+    // the slots of the long name, if any, followed by the short entry
+    pub first_slot: usize,
+    pub slot: usize,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+fn u16_at(slot: &[u8], offset: usize) -> u16 {
+    slot[offset] as u16 | (slot[offset + 1] as u16) << 8
+}
+
+fn put_u16(slot: &mut [u8], offset: usize, value: u16) {
+    slot[offset] = value as u8;
+    slot[offset + 1] = (value >> 8) as u8;
+}
+
+pub fn cluster(slot: &[u8]) -> u32 {
+    (u16_at(slot, 20) as u32) << 16 | u16_at(slot, 26) as u32
+}
+
+pub fn set_cluster(slot: &mut [u8], cluster: u32) {
+    put_u16(slot, 20, (cluster >> 16) as u16);
+    put_u16(slot, 26, cluster as u16);
+}
+
+pub fn size(slot: &[u8]) -> u32 {
+    u16_at(slot, 28) as u32 | (u16_at(slot, 30) as u32) << 16
+}
+
+pub fn set_size(slot: &mut [u8], size: u32) {
+    put_u16(slot, 28, size as u16);
+    put_u16(slot, 30, (size >> 16) as u16);
+}
+
+pub fn checksum(short: &[u8]) -> u8 {
+    short[..11].iter().fold(0u8, |sum, &byte| (sum >> 1 | sum << 7).wrapping_add(byte))
+}
+
+fn is_lower(c: char) -> bool {
+    'a' <= c && c <= 'z'
+}
+
+fn is_upper(c: char) -> bool {
+    'A' <= c && c <= 'Z'
+}
+
+fn to_lower(c: char) -> char {
+    if is_upper(c) { (c as u8 + 32) as char } else { c }
+}
+
+fn to_upper(c: char) -> char {
+    if is_lower(c) { (c as u8 - 32) as char } else { c }
+}
+
+fn decode_utf16(units: &[u16]) -> String {
+    let mut name = String::new();
+    let mut index = 0;
+    while index < units.len() {
+        let unit = units[index] as u32;
+        index += 1;
+        let code = if unit & 0xfc00 == 0xd800 && index < units.len() &&
+                      units[index] & 0xfc00 == 0xdc00 {
+            let low = units[index] as u32;
+            index += 1;
+            0x10000 + ((unit & 0x3ff) << 10 | low & 0x3ff)
+        } else {
+            unit
+        };
+        name.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+    name
+}
+
+fn short_name_string(slot: &[u8]) -> String {
+    let case = slot[12];
+    let mut name = String::new();
+    {
+        let mut push = |bytes: &[u8], lower: bool| {
+            for (index, &byte) in bytes.iter().enumerate() {
+                let byte = if index == 0 && byte == ESCAPED_E5 { DELETED } else { byte };
+                let c = if byte < 0x80 { byte as char } else { '?' };
+                name.push(if lower { to_lower(c) } else { c });
+            }
+        };
+        let base = slot[..8].iter().rposition(|&byte| byte != b' ').map_or(0, |end| end + 1);
+        let ext = slot[8..11].iter().rposition(|&byte| byte != b' ').map_or(0, |end| end + 1);
+        push(&slot[..base], case & CASE_LOWER_BASE != 0);
+        if ext > 0 {
+            push(b".", false);
+            push(&slot[8..8 + ext], case & CASE_LOWER_EXT != 0);
+        }
+    }
+    name
+}
+
+// FAT names compare without regard to case.
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.chars().zip(b.chars()).all(|(x, y)| to_upper(x) == to_upper(y))
+}
+
+// The live entries of a directory, leaving out the volume label and the dot
+// entries. Long names that do not belong to the short entry after them are
+// ignored.
+pub fn parse(raw: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut long: Vec<u16> = Vec::new();
+    let mut long_checksum = 0;
+    let mut long_first = 0;
+    // the sequence number of the long name slot seen last, 0 for none
+    let mut long_seq = 0;
+
+    for (index, slot) in raw.chunks(ENTRY_SIZE).enumerate() {
+        if slot.len() < ENTRY_SIZE || slot[0] == END {
+            break;
+        }
+        if slot[0] == DELETED {
+            long_seq = 0;
+            continue;
+        }
+
+        let attr = slot[11];
+        if attr & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+            let seq = (slot[0] & 0x1f) as usize;
+            if slot[0] & LFN_LAST != 0 && seq > 0 {
+                long.clear();
+                long.resize(seq * LFN_CHARS, 0xffff);
+                long_checksum = slot[13];
+                long_first = index;
+            } else if long_seq == 0 || seq + 1 != long_seq || slot[13] != long_checksum {
+                long_seq = 0;
+                continue;
+            }
+            for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                long[(seq - 1) * LFN_CHARS + i] = u16_at(slot, offset);
+            }
+            long_seq = seq;
+            continue;
+        }
+
+        let has_long = long_seq == 1 && checksum(slot) == long_checksum;
+        let first_slot = if has_long { long_first } else { index };
+        long_seq = 0;
+        if attr & ATTR_VOLUME_ID != 0 || slot[0] == b'.' {
+            continue;
+        }
+
+        let name = if has_long {
+            let end = long.iter()
+                .position(|&unit| unit == 0 || unit == 0xffff)
+                .unwrap_or(long.len());
+            decode_utf16(&long[..end])
+        } else {
+            short_name_string(slot)
+        };
+        entries.push(Entry {
+            name: name,
+            attr: attr,
+            cluster: cluster(slot),
+            size: size(slot),
+            first_slot: first_slot,
+            slot: index,
+        });
+    }
+    entries
+}
+
+// The first run of `count` unused slots, which may extend past the end of
+// the directory data.
+pub fn free_slots(raw: &[u8], count: usize) -> usize {
+    let mut run = 0;
+    for (index, slot) in raw.chunks(ENTRY_SIZE).enumerate() {
+        if slot[0] == END {
+            return index - run;
+        }
+        if slot[0] == DELETED {
+            run += 1;
+            if run == count {
+                return index + 1 - count;
+            }
+        } else {
+            run = 0;
+        }
+    }
+    raw.len() / ENTRY_SIZE - run
+}
+
+// Whether a live entry of the directory already has this short name.
+pub fn short_taken(raw: &[u8], short: &[u8; 11]) -> bool {
+    raw.chunks(ENTRY_SIZE)
+        .take_while(|slot| slot[0] != END)
+        .any(|slot| {
+            slot[0] != DELETED && slot[11] & ATTR_LONG_NAME_MASK != ATTR_LONG_NAME &&
+            &slot[..11] == &short[..]
+        })
+}
+
+fn is_short_char(c: char) -> bool {
+    match c {
+        'A'...'Z' | '0'...'9' => true,
+        '$' | '%' | '\'' | '-' | '_' | '@' | '~' | '`' | '!' | '(' | ')' | '{' | '}' | '^' |
+        '#' | '&' => true,
+        _ => false,
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." &&
+    name.encode_utf16().count() <= MAX_NAME_UNITS &&
+    name.chars().all(|c| c >= ' ' && !"\"*/:<>?\\|".contains(c)) &&
+    !name.ends_with('.') && !name.ends_with(' ')
+}
+
+// The 8.3 form of `part` padded to `len` bytes with its case flag, if it
+// fits as it is.
+fn fit_short(part: &str, len: usize, lower_flag: u8) -> Option<([u8; 8], u8)> {
+    if part.len() > len {
+        return None;
+    }
+    let lower = part.chars().any(|c| is_lower(c));
+    let upper = part.chars().any(|c| is_upper(c));
+    if lower && upper {
+        return None;
+    }
+    let mut bytes = [b' '; 8];
+    for (byte, c) in bytes.iter_mut().zip(part.chars()) {
+        let c = to_upper(c);
+        if !is_short_char(c) {
+            return None;
+        }
+        *byte = c as u8;
+    }
+    Some((bytes, if lower { lower_flag } else { 0 }))
+}
+
+// Picks the short name for `name`. It is `name` itself where that fits 8.3,
+// needing no long name slots; otherwise a numbered tail is added to what is
+// left of it, choosing the first that `taken` does not reject.
+pub fn short_name<F>(name: &str, taken: F) -> ([u8; 11], u8, bool)
+    where F: Fn(&[u8; 11]) -> bool
+{
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+
+    if !base.is_empty() {
+        if let (Some((base_bytes, base_case)), Some((ext_bytes, ext_case))) =
+            (fit_short(base, 8, CASE_LOWER_BASE), fit_short(ext, 3, CASE_LOWER_EXT)) {
+            let mut short = [b' '; 11];
+            short[..8].copy_from_slice(&base_bytes);
+            short[8..].copy_from_slice(&ext_bytes[..3]);
+            if !taken(&short) {
+                return (short, base_case | ext_case, false);
+            }
+        }
+    }
+
+    let convert = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = to_upper(c);
+                if is_short_char(c) { c as u8 } else { b'_' }
+            })
+            .collect()
+    };
+    let basis = convert(base);
+    let ext = convert(ext);
+
+    let mut short = [b' '; 11];
+    for (byte, &c) in short[8..].iter_mut().zip(ext.iter()) {
+        *byte = c;
+    }
+    for number in 1..1000000 {
+        let mut tail = Vec::new();
+        let mut n = number;
+        while n > 0 {
+            tail.insert(0, b'0' + (n % 10) as u8);
+            n /= 10;
+        }
+        tail.insert(0, b'~');
+
+        let keep = min(basis.len(), 8 - tail.len());
+        for byte in short[..8].iter_mut() {
+            *byte = b' ';
+        }
+        short[..keep].copy_from_slice(&basis[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(&tail);
+        if !taken(&short) {
+            break;
+        }
+    }
+    (short, 0, true)
+}
+
+// The slots for a new entry: its long name, if wanted, and the short entry.
+pub fn encode(name: &str, short: &[u8; 11], case: u8, long: bool, attr: u8, cluster: u32)
+              -> Vec<u8> {
+    let mut slots = Vec::new();
+    if long {
+        let mut units: Vec<u16> = name.encode_utf16().collect();
+        let count = (units.len() + LFN_CHARS - 1) / LFN_CHARS;
+        // the name ends with a null unless it fills the last slot, then padding
+        if units.len() % LFN_CHARS != 0 {
+            units.push(0);
+        }
+        units.resize(count * LFN_CHARS, 0xffff);
+
+        let sum = checksum(short);
+        for seq in (1..count + 1).rev() {
+            let mut slot = [0u8; ENTRY_SIZE];
+            slot[0] = seq as u8 | if seq == count { LFN_LAST } else { 0 };
+            slot[11] = ATTR_LONG_NAME;
+            slot[13] = sum;
+            for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                put_u16(&mut slot, offset, units[(seq - 1) * LFN_CHARS + i]);
+            }
+            slots.extend_from_slice(&slot);
+        }
+    }
+
+    let mut slot = [0u8; ENTRY_SIZE];
+    slot[..11].copy_from_slice(short);
+    slot[11] = attr;
+    slot[12] = case;
+    put_u16(&mut slot, 16, DEFAULT_DATE);
+    put_u16(&mut slot, 18, DEFAULT_DATE);
+    put_u16(&mut slot, 24, DEFAULT_DATE);
+    set_cluster(&mut slot, cluster);
+    slots.extend_from_slice(&slot);
+    slots
+}
+
+// The "." and ".." entries that start every directory but the root.
+pub fn dot_entries(cluster: u32, parent: u32) -> Vec<u8> {
+    let mut dot = [b' '; 11];
+    dot[0] = b'.';
+    let mut slots = encode("", &dot, 0, false, ATTR_DIRECTORY, cluster);
+    dot[1] = b'.';
+    slots.extend_from_slice(&encode("", &dot, 0, false, ATTR_DIRECTORY, parent));
+    slots
+}
@@ -0,0 +1,796 @@
+// FAT12, FAT16 and FAT32 volumes on a block device, with long file names.
+// The volume is locked as a whole for every operation, and inodes only
+// remember where their directory entry is, so that any number of them for
+// the same file agree on its size and clusters.
+
+mod bpb;
+mod dir;
+
+use core::cmp::min;
+
+use alloc::arc::Arc;
+use collections::vec::Vec;
+use spin::Mutex;
+
+use block::{self, BlockDevice};
+use fs::{self, DirEntry, Error, FileType, Filesystem, Inode, Metadata, Result};
+use self::bpb::{FatType, Layout};
+use self::dir::{ENTRY_SIZE, ATTR_ARCHIVE, ATTR_DIRECTORY, ATTR_READ_ONLY};
+
+const ROOT_INO: u64 = 1;
+
+// written to end a chain, cut down to the width of the FAT's entries
+const END_OF_CHAIN: u32 = 0x0fff_ffff;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_SIGNATURE_OFFSET: u64 = 484;
+const FSINFO_FREE_COUNT_OFFSET: u64 = 488;
+const FSINFO_UNKNOWN: u32 = 0xffff_ffff;
+
+// sizes are kept in 32 bits
+const MAX_FILE_SIZE: u64 = 0xffff_ffff;
+
+// written in pieces to zero clusters, which are whole sectors
+static ZEROES: [u8; 512] = [0; 512];
+
+pub fn mount(device: Arc<BlockDevice>) -> Result<Arc<Filesystem>> {
+    let mut sector = [0; 512];
+    block::read_bytes(&*device, 0, &mut sector)?;
+    let layout = Layout::parse(&sector).ok_or(Error::InvalidArgument)?;
+
+    let end = (layout.data_start + layout.cluster_count as u64 * layout.sectors_per_cluster) *
+              layout.bytes_per_sector;
+    if end > device.block_count() * device.block_size() as u64 {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut volume = Volume {
+        device: device,
+        layout: layout,
+        next_free: 2,
+        generation: 0,
+    };
+    if let Some(hint) = volume.read_fsinfo_hint()? {
+        volume.next_free = hint;
+    }
+
+    Ok(Arc::new(FatFs(Arc::new(Shared {
+        volume: Mutex::new(volume),
+        dev: fs::alloc_dev(),
+    }))))
+}
+
+fn u32_at(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn put_u32(bytes: &mut [u8], value: u32) {
+    for (index, byte) in bytes[..4].iter_mut().enumerate() {
+        *byte = (value >> (index * 8)) as u8;
+    }
+}
+
+// Where the entries of a directory are kept: the fixed root directory of
+// FAT12 and FAT16, or a cluster chain.
+#[derive(Clone, Copy)]
+enum Dir {
+    Root,
+    Chain(u32),
+}
+
+// A directory read into memory, with the clusters it came from.
+struct Directory {
+    dir: Dir,
+    clusters: Vec<u32>,
+    raw: Vec<u8>,
+}
+
+impl Directory {
+    fn entries(&self) -> Vec<dir::Entry> {
+        dir::parse(&self.raw)
+    }
+
+    fn find(&self, name: &str) -> Option<dir::Entry> {
+        self.entries().into_iter().find(|entry| dir::same_name(&entry.name, name))
+    }
+
+    // Byte offset of a slot on the device, if the directory is that long.
+    fn slot_offset(&self, layout: &Layout, slot: usize) -> Option<u64> {
+        let offset = slot * ENTRY_SIZE;
+        match self.dir {
+            Dir::Root if slot < layout.root_entries as usize => {
+                Some(layout.root_offset() + offset as u64)
+            }
+            Dir::Root => None,
+            Dir::Chain(_) => {
+                let size = layout.cluster_size();
+                self.clusters
+                    .get(offset / size)
+                    .map(|&cluster| layout.cluster_offset(cluster) + (offset % size) as u64)
+            }
+        }
+    }
+}
+
+// A place in the cluster chain starting at `first`: `cluster` is the
+// `index`th cluster of it. Inodes keep the last one they used, so that going
+// through a file does not walk its chain from the start every time.
+#[derive(Clone, Copy)]
+struct Cursor {
+    first: u32,
+    index: usize,
+    cluster: u32,
+    // the `Volume::generation` it was taken in
+    generation: usize,
+}
+
+struct Volume {
+    device: Arc<BlockDevice>,
+    layout: Layout,
+    // where the search for a free cluster starts
+    next_free: u32,
+    // bumped whenever a chain is cut short, which may leave cursors pointing
+    // at clusters no longer in it
+    generation: usize,
+}
+
+impl Volume {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        block::read_bytes(&*self.device, offset, buf).map_err(Error::from)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<()> {
+        block::write_bytes(&*self.device, offset, buf).map_err(Error::from)
+    }
+
+    fn fsinfo_offset(&self) -> Option<u64> {
+        self.layout.fsinfo_sector.map(|sector| sector * self.layout.bytes_per_sector)
+    }
+
+    fn read_fsinfo_hint(&self) -> Result<Option<u32>> {
+        let offset = match self.fsinfo_offset() {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let mut fsinfo = [0; 512];
+        self.read(offset, &mut fsinfo)?;
+        let hint = u32_at(&fsinfo[492..]);
+        if u32_at(&fsinfo) != FSINFO_LEAD_SIGNATURE ||
+           u32_at(&fsinfo[FSINFO_SIGNATURE_OFFSET as usize..]) != FSINFO_SIGNATURE ||
+           !self.layout.is_valid_cluster(hint) {
+            return Ok(None);
+        }
+        Ok(Some(hint))
+    }
+
+    // The free count in the FSInfo sector is not kept up to date, so it is
+    // marked unknown for the next mount to recount, and the hint saved.
+    fn write_fsinfo(&self) -> Result<()> {
+        let offset = match self.fsinfo_offset() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        let mut signature = [0; 4];
+        self.read(offset + FSINFO_SIGNATURE_OFFSET, &mut signature)?;
+        if u32_at(&signature) != FSINFO_SIGNATURE {
+            return Ok(());
+        }
+        let mut counts = [0; 8];
+        put_u32(&mut counts, FSINFO_UNKNOWN);
+        put_u32(&mut counts[4..], self.next_free);
+        self.write(offset + FSINFO_FREE_COUNT_OFFSET, &counts)
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Result<u32> {
+        let offset = self.layout.fat_entry_offset(cluster, 0);
+        let mut bytes = [0; 4];
+        match self.layout.fat_type {
+            FatType::Fat12 => {
+                self.read(offset, &mut bytes[..2])?;
+                let pair = u32_at(&bytes);
+                Ok(if cluster & 1 != 0 { pair >> 4 } else { pair & 0xfff })
+            }
+            FatType::Fat16 => {
+                self.read(offset, &mut bytes[..2])?;
+                Ok(u32_at(&bytes))
+            }
+            FatType::Fat32 => {
+                self.read(offset, &mut bytes)?;
+                Ok(u32_at(&bytes) & 0x0fff_ffff)
+            }
+        }
+    }
+
+    // Updates every copy of the FAT.
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<()> {
+        for copy in 0..self.layout.fat_count {
+            let offset = self.layout.fat_entry_offset(cluster, copy);
+            let mut bytes = [0; 4];
+            let width = match self.layout.fat_type {
+                FatType::Fat12 => {
+                    self.read(offset, &mut bytes[..2])?;
+                    let pair = u32_at(&bytes);
+                    let pair = if cluster & 1 != 0 {
+                        pair & 0x000f | (value & 0xfff) << 4
+                    } else {
+                        pair & 0xf000 | value & 0xfff
+                    };
+                    put_u32(&mut bytes, pair);
+                    2
+                }
+                FatType::Fat16 => {
+                    put_u32(&mut bytes, value & 0xffff);
+                    2
+                }
+                FatType::Fat32 => {
+                    // the top four bits are reserved and must be kept
+                    self.read(offset, &mut bytes)?;
+                    let old = u32_at(&bytes);
+                    put_u32(&mut bytes, old & 0xf000_0000 | value & 0x0fff_ffff);
+                    4
+                }
+            };
+            self.write(offset, &bytes[..width])?;
+        }
+        Ok(())
+    }
+
+    // The cluster after `cluster` in its chain, None at the end of it.
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>> {
+        let next = self.fat_entry(cluster)?;
+        if next >= self.layout.end_of_chain() {
+            Ok(None)
+        } else if self.layout.is_valid_cluster(next) {
+            Ok(Some(next))
+        } else {
+            Err(Error::Io)
+        }
+    }
+
+    // The clusters of the chain starting at `first`, none for cluster 0.
+    fn chain(&self, first: u32) -> Result<Vec<u32>> {
+        let mut chain = Vec::new();
+        if first == 0 {
+            return Ok(chain);
+        }
+        if !self.layout.is_valid_cluster(first) {
+            return Err(Error::Io);
+        }
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            // a chain longer than the volume has to loop
+            if chain.len() >= self.layout.cluster_count as usize {
+                return Err(Error::Io);
+            }
+            chain.push(current);
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(chain)
+    }
+
+    // Finds the `index`th cluster of the chain starting at `first`, walking on
+    // from `cursor` when it is further along the same chain.
+    fn seek(&self, first: u32, index: usize, cursor: Option<Cursor>) -> Result<Cursor> {
+        if !self.layout.is_valid_cluster(first) {
+            return Err(Error::Io);
+        }
+        let mut at = match cursor {
+            Some(cursor) if cursor.first == first && cursor.generation == self.generation &&
+                            cursor.index <= index => cursor,
+            _ => {
+                Cursor {
+                    first: first,
+                    index: 0,
+                    cluster: first,
+                    generation: self.generation,
+                }
+            }
+        };
+        // a chain longer than the volume has to loop
+        if index >= self.layout.cluster_count as usize {
+            return Err(Error::Io);
+        }
+        while at.index < index {
+            at.cluster = self.next_cluster(at.cluster)?.ok_or(Error::Io)?;
+            at.index += 1;
+        }
+        Ok(at)
+    }
+
+    // Calls `f` with (device offset, offset into the transfer, length) for
+    // each piece of `len` bytes at `offset` of the file starting at cluster
+    // `first`, moving `cursor` along.
+    fn for_each_piece<F>(&self,
+                         first: u32,
+                         offset: u64,
+                         len: usize,
+                         cursor: &mut Option<Cursor>,
+                         mut f: F)
+                         -> Result<()>
+        where F: FnMut(u64, usize, usize) -> Result<()>
+    {
+        let cluster_size = self.layout.cluster_size() as u64;
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let at = self.seek(first, (position / cluster_size) as usize, *cursor)?;
+            *cursor = Some(at);
+            let within = position % cluster_size;
+            let count = min(len - done, (cluster_size - within) as usize);
+            f(self.layout.cluster_offset(at.cluster) + within, done, count)?;
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn zero(&self, offset: u64, len: usize) -> Result<()> {
+        let mut done = 0;
+        while done < len {
+            let count = min(len - done, ZEROES.len());
+            self.write(offset + done as u64, &ZEROES[..count])?;
+            done += count;
+        }
+        Ok(())
+    }
+
+    // Takes a free cluster, zeroes it and appends it to the chain ending in
+    // `last`, or starts a new chain.
+    fn alloc_cluster(&mut self, last: Option<u32>) -> Result<u32> {
+        let count = self.layout.cluster_count;
+        let start = if self.layout.is_valid_cluster(self.next_free) { self.next_free - 2 } else { 0 };
+        for index in 0..count {
+            let cluster = 2 + (start + index) % count;
+            if self.fat_entry(cluster)? != 0 {
+                continue;
+            }
+
+            self.zero(self.layout.cluster_offset(cluster), self.layout.cluster_size())?;
+            self.set_fat_entry(cluster, END_OF_CHAIN)?;
+            if let Some(last) = last {
+                self.set_fat_entry(last, cluster)?;
+            }
+            self.next_free = cluster + 1;
+            return Ok(cluster);
+        }
+        Err(Error::NoSpace)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<()> {
+        if !self.layout.is_valid_cluster(first) {
+            return Err(Error::Io);
+        }
+        self.generation += 1;
+        let mut cluster = Some(first);
+        let mut freed = 0;
+        while let Some(current) = cluster {
+            if freed >= self.layout.cluster_count {
+                return Err(Error::Io);
+            }
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+            freed += 1;
+        }
+        Ok(())
+    }
+
+    // Makes the chain starting at `first`, `old_length` clusters long, hold
+    // `length` clusters. Returns its first cluster, 0 once it is empty.
+    // Clusters added before running out of space are given back.
+    fn resize_chain(&mut self,
+                    first: u32,
+                    old_length: usize,
+                    length: usize,
+                    cursor: &mut Option<Cursor>)
+                    -> Result<u32> {
+        if length > old_length {
+            let last = match old_length {
+                0 => None,
+                _ => {
+                    let at = self.seek(first, old_length - 1, *cursor)?;
+                    *cursor = Some(at);
+                    Some(at.cluster)
+                }
+            };
+            let mut added = None;
+            let mut tail = last;
+            for _ in old_length..length {
+                match self.alloc_cluster(tail) {
+                    Ok(cluster) => {
+                        added = added.or(Some(cluster));
+                        tail = Some(cluster);
+                    }
+                    Err(error) => {
+                        if let Some(last) = last {
+                            self.set_fat_entry(last, END_OF_CHAIN)?;
+                        }
+                        if let Some(added) = added {
+                            self.free_chain(added)?;
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+            Ok(if old_length == 0 { added.unwrap() } else { first })
+        } else if length < old_length {
+            if length == 0 {
+                self.free_chain(first)?;
+                return Ok(0);
+            }
+            let at = self.seek(first, length - 1, *cursor)?;
+            let rest = self.next_cluster(at.cluster)?.ok_or(Error::Io)?;
+            self.set_fat_entry(at.cluster, END_OF_CHAIN)?;
+            self.free_chain(rest)?;
+            Ok(first)
+        } else {
+            Ok(first)
+        }
+    }
+
+    fn root_dir(&self) -> Dir {
+        match self.layout.fat_type {
+            FatType::Fat32 => Dir::Chain(self.layout.root_cluster),
+            _ => Dir::Root,
+        }
+    }
+
+    fn open_dir(&self, dir: Dir) -> Result<Directory> {
+        let (clusters, raw) = match dir {
+            Dir::Root => {
+                let mut raw = vec![0; self.layout.root_entries as usize * ENTRY_SIZE];
+                self.read(self.layout.root_offset(), &mut raw)?;
+                (Vec::new(), raw)
+            }
+            Dir::Chain(first) => {
+                let size = self.layout.cluster_size();
+                let clusters = self.chain(first)?;
+                let mut raw = vec![0; clusters.len() * size];
+                for (chunk, &cluster) in raw.chunks_mut(size).zip(clusters.iter()) {
+                    self.read(self.layout.cluster_offset(cluster), chunk)?;
+                }
+                (clusters, raw)
+            }
+        };
+        Ok(Directory {
+            dir: dir,
+            clusters: clusters,
+            raw: raw,
+        })
+    }
+
+    // Writes an entry for `name` into the first free run of slots, growing
+    // the directory if there is none. Returns the offset of the short entry.
+    fn add_entry(&mut self, directory: &mut Directory, name: &str, attr: u8, cluster: u32)
+                 -> Result<u64> {
+        if !dir::is_valid_name(name) {
+            return Err(Error::InvalidArgument);
+        }
+        if directory.find(name).is_some() {
+            return Err(Error::Exists);
+        }
+
+        let slots = {
+            let raw = &directory.raw;
+            let (short, case, long) = dir::short_name(name, |short| dir::short_taken(raw, short));
+            dir::encode(name, &short, case, long, attr, cluster)
+        };
+        let count = slots.len() / ENTRY_SIZE;
+        let first = dir::free_slots(&directory.raw, count);
+
+        while directory.slot_offset(&self.layout, first + count - 1).is_none() {
+            match directory.dir {
+                Dir::Root => return Err(Error::NoSpace),
+                // every directory has a cluster for its dot entries, so one
+                // without is damaged; a first cluster allocated here would
+                // not be recorded in the directory's own entry
+                Dir::Chain(_) if directory.clusters.is_empty() => return Err(Error::Io),
+                Dir::Chain(_) => {
+                    let cluster = self.alloc_cluster(directory.clusters.last().cloned())?;
+                    directory.clusters.push(cluster);
+                    let length = directory.raw.len() + self.layout.cluster_size();
+                    directory.raw.resize(length, 0);
+                }
+            }
+        }
+
+        for (index, slot) in slots.chunks(ENTRY_SIZE).enumerate() {
+            let offset = directory.slot_offset(&self.layout, first + index).unwrap();
+            self.write(offset, slot)?;
+        }
+        let start = first * ENTRY_SIZE;
+        directory.raw[start..start + slots.len()].copy_from_slice(&slots);
+        Ok(directory.slot_offset(&self.layout, first + count - 1).unwrap())
+    }
+
+    // Marks the slots of an entry, long name included, as deleted.
+    fn remove_entry(&mut self, directory: &Directory, entry: &dir::Entry) -> Result<()> {
+        for slot in entry.first_slot..entry.slot + 1 {
+            let offset = directory.slot_offset(&self.layout, slot).ok_or(Error::Io)?;
+            self.write(offset, &[dir::DELETED])?;
+        }
+        Ok(())
+    }
+
+    // The short entry at `offset`, failing once the entry has been deleted.
+    fn read_slot(&self, offset: u64) -> Result<[u8; ENTRY_SIZE]> {
+        let mut slot = [0; ENTRY_SIZE];
+        self.read(offset, &mut slot)?;
+        if slot[0] == dir::DELETED || slot[0] == dir::END {
+            return Err(Error::NotFound);
+        }
+        Ok(slot)
+    }
+
+    fn read_file(&self,
+                 slot: &[u8],
+                 offset: u64,
+                 buf: &mut [u8],
+                 cursor: &mut Option<Cursor>)
+                 -> Result<usize> {
+        let size = dir::size(slot) as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = min(buf.len() as u64, size - offset) as usize;
+        self.for_each_piece(dir::cluster(slot), offset, len, cursor, |position, done, count| {
+            self.read(position, &mut buf[done..done + count])
+        })?;
+        Ok(len)
+    }
+
+    // Changes the size of a file, allocating or freeing clusters. Bytes past
+    // the old size are zeroed up to `zero_end`, the rest being about to be
+    // written.
+    fn resize_file(&mut self,
+                   slot: &mut [u8],
+                   size: u64,
+                   zero_end: u64,
+                   cursor: &mut Option<Cursor>)
+                   -> Result<()> {
+        if size > MAX_FILE_SIZE {
+            return Err(Error::NoSpace);
+        }
+        let old_size = dir::size(slot) as u64;
+        let cluster_size = self.layout.cluster_size() as u64;
+        let clusters = |size: u64| ((size + cluster_size - 1) / cluster_size) as usize;
+        let first =
+            self.resize_chain(dir::cluster(slot), clusters(old_size), clusters(size), cursor)?;
+
+        let zero_end = min(zero_end, size);
+        if zero_end > old_size {
+            self.for_each_piece(first,
+                                old_size,
+                                (zero_end - old_size) as usize,
+                                cursor,
+                                |position, _, count| self.zero(position, count))?;
+        }
+
+        dir::set_cluster(slot, first);
+        dir::set_size(slot, size as u32);
+        Ok(())
+    }
+
+    fn write_file(&mut self,
+                  offset: u64,
+                  buf: &[u8],
+                  entry: u64,
+                  cursor: &mut Option<Cursor>)
+                  -> Result<usize> {
+        let mut slot = self.read_slot(entry)?;
+        if slot[11] & ATTR_READ_ONLY != 0 {
+            return Err(Error::ReadOnly);
+        }
+        let end = offset + buf.len() as u64;
+        if end > dir::size(&slot) as u64 {
+            self.resize_file(&mut slot, end, offset, cursor)?;
+            self.write(entry, &slot)?;
+        }
+        let first = dir::cluster(&slot);
+        self.for_each_piece(first, offset, buf.len(), cursor, |position, done, count| {
+            self.write(position, &buf[done..done + count])
+        })?;
+        Ok(buf.len())
+    }
+}
+
+struct Shared {
+    volume: Mutex<Volume>,
+    dev: usize,
+}
+
+pub struct FatFs(Arc<Shared>);
+
+impl Filesystem for FatFs {
+    fn name(&self) -> &'static str {
+        "fat"
+    }
+
+    fn root(&self) -> Arc<Inode> {
+        Arc::new(FatInode {
+            shared: self.0.clone(),
+            entry: None,
+            cursor: Mutex::new(None),
+        })
+    }
+
+    fn sync(&self) -> Result<()> {
+        let volume = self.0.volume.lock();
+        volume.write_fsinfo()?;
+        volume.device.flush().map_err(Error::from)
+    }
+}
+
+struct FatInode {
+    shared: Arc<Shared>,
+    // byte offset of the short directory entry, None for the root directory
+    entry: Option<u64>,
+    // where in its chain the file was last read or written
+    cursor: Mutex<Option<Cursor>>,
+}
+
+fn ino(entry: u64) -> u64 {
+    entry / ENTRY_SIZE as u64
+}
+
+fn file_type(attr: u8) -> FileType {
+    if attr & ATTR_DIRECTORY != 0 { FileType::Directory } else { FileType::File }
+}
+
+impl FatInode {
+    fn child(&self, entry: u64) -> Arc<Inode> {
+        Arc::new(FatInode {
+            shared: self.shared.clone(),
+            entry: Some(entry),
+            cursor: Mutex::new(None),
+        })
+    }
+
+    fn dir(&self, volume: &Volume) -> Result<Directory> {
+        let dir = match self.entry {
+            None => volume.root_dir(),
+            Some(entry) => {
+                let slot = volume.read_slot(entry)?;
+                if slot[11] & ATTR_DIRECTORY == 0 {
+                    return Err(Error::NotADirectory);
+                }
+                Dir::Chain(dir::cluster(&slot))
+            }
+        };
+        volume.open_dir(dir)
+    }
+
+    fn file(&self, volume: &Volume) -> Result<(u64, [u8; ENTRY_SIZE])> {
+        let entry = self.entry.ok_or(Error::IsADirectory)?;
+        let slot = volume.read_slot(entry)?;
+        if slot[11] & ATTR_DIRECTORY != 0 {
+            return Err(Error::IsADirectory);
+        }
+        Ok((entry, slot))
+    }
+
+    // The cluster ".." refers to from a directory created in this one.
+    fn parent_cluster(&self, volume: &Volume) -> Result<u32> {
+        match self.entry {
+            None => Ok(0),
+            Some(entry) => Ok(dir::cluster(&volume.read_slot(entry)?)),
+        }
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Metadata {
+        let (ino, kind, size, mode) = match self.entry {
+            None => (ROOT_INO, FileType::Directory, 0, 0o755),
+            Some(entry) => {
+                match self.shared.volume.lock().read_slot(entry) {
+                    Ok(slot) => {
+                        let kind = file_type(slot[11]);
+                        let mode = match kind {
+                            FileType::Directory => 0o755,
+                            _ if slot[11] & ATTR_READ_ONLY != 0 => 0o444,
+                            _ => 0o644,
+                        };
+                        (ino(entry), kind, dir::size(&slot) as u64, mode)
+                    }
+                    // deleted while still open
+                    Err(_) => (ino(entry), FileType::File, 0, 0),
+                }
+            }
+        };
+        Metadata {
+            dev: self.shared.dev,
+            ino: ino,
+            kind: kind,
+            size: if kind == FileType::Directory { 0 } else { size },
+            mode: mode,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<Inode>> {
+        let volume = self.shared.volume.lock();
+        let directory = self.dir(&volume)?;
+        let entry = directory.find(name).ok_or(Error::NotFound)?;
+        let offset = directory.slot_offset(&volume.layout, entry.slot).ok_or(Error::Io)?;
+        Ok(self.child(offset))
+    }
+
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        let volume = self.shared.volume.lock();
+        let directory = self.dir(&volume)?;
+        let mut entries = Vec::new();
+        for entry in directory.entries() {
+            let offset = directory.slot_offset(&volume.layout, entry.slot).ok_or(Error::Io)?;
+            entries.push(DirEntry {
+                name: entry.name,
+                ino: ino(offset),
+                kind: file_type(entry.attr),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let volume = self.shared.volume.lock();
+        let (_, slot) = self.file(&volume)?;
+        volume.read_file(&slot, offset, buf, &mut self.cursor.lock())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut volume = self.shared.volume.lock();
+        let (entry, _) = self.file(&volume)?;
+        volume.write_file(offset, buf, entry, &mut self.cursor.lock())
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        let mut volume = self.shared.volume.lock();
+        let (entry, mut slot) = self.file(&volume)?;
+        if slot[11] & ATTR_READ_ONLY != 0 {
+            return Err(Error::ReadOnly);
+        }
+        volume.resize_file(&mut slot, size, size, &mut self.cursor.lock())?;
+        volume.write(entry, &slot)
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> Result<Arc<Inode>> {
+        let mut volume = self.shared.volume.lock();
+        let mut directory = self.dir(&volume)?;
+        let entry = match kind {
+            FileType::File => volume.add_entry(&mut directory, name, ATTR_ARCHIVE, 0)?,
+            FileType::Directory => {
+                let parent = self.parent_cluster(&volume)?;
+                let cluster = volume.alloc_cluster(None)?;
+                let offset = volume.layout.cluster_offset(cluster);
+                let created = match volume.write(offset, &dir::dot_entries(cluster, parent)) {
+                    Ok(()) => volume.add_entry(&mut directory, name, ATTR_DIRECTORY, cluster),
+                    Err(error) => Err(error),
+                };
+                match created {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        volume.free_chain(cluster)?;
+                        return Err(error);
+                    }
+                }
+            }
+            _ => return Err(Error::NotSupported),
+        };
+        Ok(self.child(entry))
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let mut volume = self.shared.volume.lock();
+        let directory = self.dir(&volume)?;
+        let entry = directory.find(name).ok_or(Error::NotFound)?;
+        if entry.is_dir() && !volume.open_dir(Dir::Chain(entry.cluster))?.entries().is_empty() {
+            return Err(Error::NotEmpty);
+        }
+        volume.remove_entry(&directory, &entry)?;
+        if entry.cluster != 0 {
+            volume.free_chain(entry.cluster)?;
+        }
+        Ok(())
+    }
+}
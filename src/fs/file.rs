@@ -0,0 +1,173 @@
+use alloc::arc::Arc;
+use collections::vec::Vec;
+use spin::Mutex;
+
+use super::{resolve, resolve_parent, DirEntry, Error, FileType, Inode, Result};
+
+pub type Fd = usize;
+
+const MAX_FILES: usize = 64;
+
+bitflags! {
+    pub flags OpenFlags: u32 {
+        const O_READ      = 1 << 0,
+        const O_WRITE     = 1 << 1,
+        const O_CREATE    = 1 << 2,
+        const O_TRUNCATE  = 1 << 3,
+        const O_APPEND    = 1 << 4,
+        const O_DIRECTORY = 1 << 5,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+pub fn open_inode(path: &str, flags: OpenFlags) -> Result<Arc<Inode>> {
+    let inode = match resolve(path) {
+        Ok(inode) => inode,
+        Err(Error::NotFound) if flags.contains(O_CREATE) => {
+            let (parent, name) = resolve_parent(path)?;
+            parent.create(name, FileType::File)?
+        }
+        Err(error) => return Err(error),
+    };
+
+    let kind = inode.metadata().kind;
+    if kind == FileType::Directory && flags.intersects(O_WRITE | O_TRUNCATE) {
+        return Err(Error::IsADirectory);
+    }
+    if kind != FileType::Directory && flags.contains(O_DIRECTORY) {
+        return Err(Error::NotADirectory);
+    }
+    if flags.contains(O_TRUNCATE) {
+        inode.truncate(0)?;
+    }
+    Ok(inode)
+}
+
+// An open file, shared by every descriptor duplicated from the same open.
+pub struct File {
+    inode: Arc<Inode>,
+    flags: OpenFlags,
+    offset: Mutex<u64>,
+}
+
+impl File {
+    pub fn new(inode: Arc<Inode>, flags: OpenFlags) -> File {
+        File {
+            inode: inode,
+            flags: flags,
+            offset: Mutex::new(0),
+        }
+    }
+
+    pub fn inode(&self) -> &Arc<Inode> {
+        &self.inode
+    }
+
+    pub fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if !self.flags.contains(O_READ) {
+            return Err(Error::BadDescriptor);
+        }
+        let mut offset = self.offset.lock();
+        let (start, count) = self.inode.read_from(*offset, buf)?;
+        *offset = start + count as u64;
+        Ok(count)
+    }
+
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        if !self.flags.contains(O_WRITE) {
+            return Err(Error::BadDescriptor);
+        }
+        let mut offset = self.offset.lock();
+        if self.flags.contains(O_APPEND) {
+            *offset = self.inode.metadata().size;
+        }
+        let count = self.inode.write_at(*offset, buf)?;
+        *offset += count as u64;
+        Ok(count)
+    }
+
+    pub fn seek(&self, pos: SeekFrom) -> Result<u64> {
+        let mut offset = self.offset.lock();
+        let new = match pos {
+            SeekFrom::Start(position) => position as i64,
+            SeekFrom::Current(delta) => *offset as i64 + delta,
+            SeekFrom::End(delta) => self.inode.metadata().size as i64 + delta,
+        };
+        if new < 0 {
+            return Err(Error::InvalidArgument);
+        }
+        *offset = new as u64;
+        Ok(*offset)
+    }
+
+    // For directories the offset counts entries rather than bytes.
+    pub fn readdir(&self) -> Result<Option<DirEntry>> {
+        let mut offset = self.offset.lock();
+        let mut entries = self.inode.readdir()?;
+        if *offset as usize >= entries.len() {
+            return Ok(None);
+        }
+        let entry = entries.swap_remove(*offset as usize);
+        *offset += 1;
+        Ok(Some(entry))
+    }
+}
+
+#[derive(Clone)]
+pub struct FileTable {
+    files: Vec<Option<Arc<File>>>,
+}
+
+impl FileTable {
+    pub fn new() -> FileTable {
+        FileTable { files: Vec::new() }
+    }
+
+    pub fn get(&self, fd: Fd) -> Result<Arc<File>> {
+        match self.files.get(fd) {
+            Some(&Some(ref file)) => Ok(file.clone()),
+            _ => Err(Error::BadDescriptor),
+        }
+    }
+
+    // Installs the file at the lowest free descriptor.
+    pub fn insert(&mut self, file: Arc<File>) -> Result<Fd> {
+        if let Some(fd) = self.files.iter().position(|slot| slot.is_none()) {
+            self.files[fd] = Some(file);
+            return Ok(fd);
+        }
+        if self.files.len() >= MAX_FILES {
+            return Err(Error::TooManyOpenFiles);
+        }
+        self.files.push(Some(file));
+        Ok(self.files.len() - 1)
+    }
+
+    pub fn insert_at(&mut self, fd: Fd, file: Arc<File>) -> Result<()> {
+        if fd >= MAX_FILES {
+            return Err(Error::BadDescriptor);
+        }
+        while self.files.len() <= fd {
+            self.files.push(None);
+        }
+        self.files[fd] = Some(file);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, fd: Fd) -> Result<Arc<File>> {
+        match self.files.get_mut(fd).and_then(|slot| slot.take()) {
+            Some(file) => Ok(file),
+            None => Err(Error::BadDescriptor),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use core::str;
+
+use super::{Entry, Error, Kind};
+
+const HEADER_SIZE: usize = 110;
+const TRAILER: &'static str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+pub fn is_cpio(data: &[u8]) -> bool {
+    // "newc" and its checksummed "crc" variant share the header layout
+    data.len() >= HEADER_SIZE && (&data[0..6] == b"070701" || &data[0..6] == b"070702")
+}
+
+pub struct Entries {
+    data: &'static [u8],
+    offset: usize,
+}
+
+pub fn entries(data: &'static [u8]) -> Entries {
+    Entries {
+        data: data,
+        offset: 0,
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Result<Entry, Error>> {
+        loop {
+            match self.parse() {
+                Ok(Some(Some(entry))) => return Some(Ok(entry)),
+                Ok(Some(None)) => continue,
+                Ok(None) => return None,
+                Err(error) => {
+                    self.offset = self.data.len();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl Entries {
+    // Ok(None) at the trailer, Ok(Some(None)) for entries that are skipped.
+    fn parse(&mut self) -> Result<Option<Option<Entry>>, Error> {
+        if self.offset + HEADER_SIZE > self.data.len() {
+            return Err(Error::Truncated);
+        }
+        let data = self.data;
+        let header = &data[self.offset..self.offset + HEADER_SIZE];
+        if !is_cpio(header) {
+            return Err(Error::BadHeader);
+        }
+
+        let mode = hex(&header[14..22])?;
+        let size = hex(&header[54..62])? as usize;
+        let name_size = hex(&header[94..102])? as usize;
+
+        let name_start = self.offset + HEADER_SIZE;
+        let data_start = align4(name_start + name_size);
+        let data_end = data_start + size;
+        if name_size == 0 || data_end > data.len() {
+            return Err(Error::Truncated);
+        }
+        self.offset = align4(data_end);
+
+        // the name size includes the terminating null byte
+        let name = str::from_utf8(&data[name_start..name_start + name_size - 1])
+            .map_err(|_| Error::BadHeader)?;
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let kind = match mode & S_IFMT {
+            S_IFREG => Kind::File,
+            S_IFDIR => Kind::Directory,
+            S_IFLNK => Kind::Symlink,
+            _ => return Ok(Some(None)),
+        };
+
+        Ok(Some(Some(Entry {
+            prefix: "",
+            name: name,
+            kind: kind,
+            mode: mode & 0o7777,
+            data: &data[data_start..data_end],
+        })))
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn hex(bytes: &[u8]) -> Result<u32, Error> {
+    let mut value = 0;
+    for &b in bytes {
+        let digit = match b {
+            b'0'...b'9' => b - b'0',
+            b'a'...b'f' => b - b'a' + 10,
+            b'A'...b'F' => b - b'A' + 10,
+            _ => return Err(Error::BadHeader),
+        };
+        value = value << 4 | digit as u32;
+    }
+    Ok(value)
+}
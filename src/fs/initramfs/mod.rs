@@ -0,0 +1,368 @@
+// A read-only filesystem over a ustar or newc cpio archive that stays in
+// place in memory, such as a boot module.
+
+mod cpio;
+mod tar;
+
+use core::cmp::min;
+use core::str;
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+
+use fs::{self, DirEntry, FileType, Filesystem, Inode, Metadata};
+
+pub type Ino = usize;
+
+pub const ROOT_INO: Ino = 0;
+
+const MAX_SYMLINK_DEPTH: usize = 8;
+
+const NO_DATA: &'static [u8] = &[];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    UnknownFormat,
+    Truncated,
+    BadHeader,
+    BadChecksum,
+    NotADirectory,
+}
+
+// An archive member, with its path split in two for tar's prefix field.
+pub struct Entry {
+    prefix: &'static str,
+    name: &'static str,
+    kind: Kind,
+    mode: u32,
+    data: &'static [u8],
+}
+
+pub struct Node {
+    name: &'static str,
+    kind: Kind,
+    mode: u32,
+    data: &'static [u8],
+    parent: Ino,
+    children: Vec<Ino>,
+}
+
+impl Node {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+}
+
+pub struct Initramfs {
+    nodes: Vec<Node>,
+}
+
+impl Initramfs {
+    pub fn new(archive: &'static [u8]) -> Result<Initramfs, Error> {
+        let mut fs = Initramfs {
+            nodes: vec![Node {
+                            name: "",
+                            kind: Kind::Directory,
+                            mode: 0o755,
+                            data: NO_DATA,
+                            parent: ROOT_INO,
+                            children: Vec::new(),
+                        }],
+        };
+
+        if tar::is_tar(archive) {
+            for entry in tar::entries(archive) {
+                fs.insert(entry?)?;
+            }
+        } else if cpio::is_cpio(archive) {
+            for entry in cpio::entries(archive) {
+                fs.insert(entry?)?;
+            }
+        } else {
+            return Err(Error::UnknownFormat);
+        }
+        Ok(fs)
+    }
+
+    fn insert(&mut self, entry: Entry) -> Result<(), Error> {
+        let mut components = components(entry.prefix).chain(components(entry.name)).peekable();
+        let mut dir = ROOT_INO;
+        while let Some(name) = components.next() {
+            let is_last = components.peek().is_none();
+            let existing = self.child(dir, name);
+            dir = match existing {
+                Some(ino) if is_last => {
+                    // a directory may appear after entries below it
+                    let node = &mut self.nodes[ino];
+                    node.kind = entry.kind;
+                    node.mode = entry.mode;
+                    node.data = entry.data;
+                    ino
+                }
+                Some(ino) => {
+                    if self.nodes[ino].kind != Kind::Directory {
+                        return Err(Error::NotADirectory);
+                    }
+                    ino
+                }
+                None if is_last => self.add_node(dir, name, entry.kind, entry.mode, entry.data),
+                None => self.add_node(dir, name, Kind::Directory, 0o755, NO_DATA),
+            };
+        }
+        Ok(())
+    }
+
+    fn add_node(&mut self,
+                parent: Ino,
+                name: &'static str,
+                kind: Kind,
+                mode: u32,
+                data: &'static [u8])
+                -> Ino {
+        let ino = self.nodes.len();
+        self.nodes.push(Node {
+            name: name,
+            kind: kind,
+            mode: mode,
+            data: data,
+            parent: parent,
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(ino);
+        ino
+    }
+
+    pub fn node(&self, ino: Ino) -> Option<&Node> {
+        self.nodes.get(ino)
+    }
+
+    pub fn child(&self, dir: Ino, name: &str) -> Option<Ino> {
+        match name {
+            "." => Some(dir),
+            ".." => Some(self.nodes[dir].parent),
+            _ => {
+                self.nodes[dir]
+                    .children
+                    .iter()
+                    .cloned()
+                    .find(|&ino| self.nodes[ino].name == name)
+            }
+        }
+    }
+
+    // Resolves an absolute path (or one relative to the root), following
+    // symbolic links.
+    pub fn lookup(&self, path: &str) -> Option<Ino> {
+        self.lookup_from(ROOT_INO, path, 0)
+    }
+
+    fn lookup_from(&self, start: Ino, path: &str, depth: usize) -> Option<Ino> {
+        let mut ino = if path.starts_with('/') { ROOT_INO } else { start };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if self.nodes[ino].kind != Kind::Directory {
+                return None;
+            }
+            let dir = ino;
+            ino = match self.child(dir, name) {
+                Some(child) if self.nodes[child].kind == Kind::Symlink => {
+                    match self.follow(dir, child, depth) {
+                        Some(target) => target,
+                        None => return None,
+                    }
+                }
+                Some(child) => child,
+                None => return None,
+            };
+        }
+        Some(ino)
+    }
+
+    fn follow(&self, dir: Ino, link: Ino, depth: usize) -> Option<Ino> {
+        if depth >= MAX_SYMLINK_DEPTH {
+            return None;
+        }
+        str::from_utf8(self.nodes[link].data)
+            .ok()
+            .and_then(|target| self.lookup_from(dir, target, depth + 1))
+    }
+
+    pub fn read_dir(&self, dir: Ino) -> Option<ReadDir> {
+        match self.nodes.get(dir) {
+            Some(node) if node.kind == Kind::Directory => {
+                Some(ReadDir {
+                    fs: self,
+                    children: node.children.iter(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn read(&self, ino: Ino, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.nodes[ino].data;
+        if offset >= data.len() {
+            return 0;
+        }
+        let count = min(buf.len(), data.len() - offset);
+        buf[..count].copy_from_slice(&data[offset..offset + count]);
+        count
+    }
+
+    pub fn read_file(&self, path: &str) -> Option<&'static [u8]> {
+        self.lookup(path)
+            .map(|ino| &self.nodes[ino])
+            .and_then(|node| if node.kind == Kind::File { Some(node.data) } else { None })
+    }
+}
+
+pub struct ReadDir<'a> {
+    fs: &'a Initramfs,
+    children: ::core::slice::Iter<'a, Ino>,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = (Ino, &'a Node);
+
+    fn next(&mut self) -> Option<(Ino, &'a Node)> {
+        self.children.next().map(|&ino| (ino, &self.fs.nodes[ino]))
+    }
+}
+
+fn components(path: &'static str) -> ::core::iter::Filter<str::Split<'static, char>, fn(&&str) -> bool> {
+    fn is_component(name: &&str) -> bool {
+        !name.is_empty() && *name != "."
+    }
+    path.split('/').filter(is_component as fn(&&str) -> bool)
+}
+
+pub fn mount(archive: &'static [u8]) -> Result<Arc<Filesystem>, Error> {
+    let shared = Shared {
+        fs: Initramfs::new(archive)?,
+        dev: fs::alloc_dev(),
+    };
+    Ok(Arc::new(InitramfsFs(Arc::new(shared))))
+}
+
+struct Shared {
+    fs: Initramfs,
+    dev: usize,
+}
+
+pub struct InitramfsFs(Arc<Shared>);
+
+impl Filesystem for InitramfsFs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn root(&self) -> Arc<Inode> {
+        Arc::new(InitramfsInode {
+            shared: self.0.clone(),
+            ino: ROOT_INO,
+        })
+    }
+}
+
+struct InitramfsInode {
+    shared: Arc<Shared>,
+    ino: Ino,
+}
+
+impl InitramfsInode {
+    fn node(&self) -> &Node {
+        &self.shared.fs.nodes[self.ino]
+    }
+}
+
+fn file_type(kind: Kind) -> FileType {
+    match kind {
+        Kind::File => FileType::File,
+        Kind::Directory => FileType::Directory,
+        Kind::Symlink => FileType::Symlink,
+    }
+}
+
+impl Inode for InitramfsInode {
+    fn metadata(&self) -> Metadata {
+        let node = self.node();
+        Metadata {
+            dev: self.shared.dev,
+            ino: self.ino as u64,
+            kind: file_type(node.kind),
+            size: node.size() as u64,
+            mode: node.mode,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> fs::Result<Arc<Inode>> {
+        if self.node().kind != Kind::Directory {
+            return Err(fs::Error::NotADirectory);
+        }
+        match self.shared.fs.child(self.ino, name) {
+            Some(ino) => {
+                Ok(Arc::new(InitramfsInode {
+                    shared: self.shared.clone(),
+                    ino: ino,
+                }))
+            }
+            None => Err(fs::Error::NotFound),
+        }
+    }
+
+    fn readdir(&self) -> fs::Result<Vec<DirEntry>> {
+        match self.shared.fs.read_dir(self.ino) {
+            Some(entries) => {
+                Ok(entries.map(|(ino, node)| {
+                        DirEntry {
+                            name: node.name.to_string(),
+                            ino: ino as u64,
+                            kind: file_type(node.kind),
+                        }
+                    })
+                    .collect())
+            }
+            None => Err(fs::Error::NotADirectory),
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        if self.node().kind == Kind::Directory {
+            return Err(fs::Error::IsADirectory);
+        }
+        Ok(self.shared.fs.read(self.ino, offset as usize, buf))
+    }
+
+    fn readlink(&self) -> fs::Result<String> {
+        let node = self.node();
+        if node.kind != Kind::Symlink {
+            return Err(fs::Error::InvalidArgument);
+        }
+        str::from_utf8(node.data)
+            .map(|target| target.to_string())
+            .map_err(|_| fs::Error::Io)
+    }
+}
@@ -0,0 +1,108 @@
+use core::str;
+
+use super::{Entry, Error, Kind};
+
+const BLOCK_SIZE: usize = 512;
+
+pub fn is_tar(data: &[u8]) -> bool {
+    data.len() >= BLOCK_SIZE && &data[257..262] == b"ustar"
+}
+
+pub struct Entries {
+    data: &'static [u8],
+    offset: usize,
+}
+
+pub fn entries(data: &'static [u8]) -> Entries {
+    Entries {
+        data: data,
+        offset: 0,
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Result<Entry, Error>> {
+        loop {
+            if self.offset + BLOCK_SIZE > self.data.len() {
+                return None;
+            }
+            let data = self.data;
+            let header = &data[self.offset..self.offset + BLOCK_SIZE];
+            if header.iter().all(|&b| b == 0) {
+                // end of archive marker
+                return None;
+            }
+
+            match self.parse(header) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(error) => {
+                    self.offset = self.data.len();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl Entries {
+    fn parse(&mut self, header: &'static [u8]) -> Result<Option<Entry>, Error> {
+        let expected = octal(&header[148..156])?;
+        let checksum = header.iter()
+            .enumerate()
+            .map(|(i, &b)| if i >= 148 && i < 156 { b' ' as u64 } else { b as u64 })
+            .sum::<u64>();
+        if checksum != expected {
+            return Err(Error::BadChecksum);
+        }
+
+        let size = octal(&header[124..136])? as usize;
+        let data_start = self.offset + BLOCK_SIZE;
+        let data_end = data_start + size;
+        if data_end > self.data.len() {
+            return Err(Error::Truncated);
+        }
+        self.offset = data_start + (size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+
+        let kind = match header[156] {
+            b'0' | 0 => Kind::File,
+            b'5' => Kind::Directory,
+            b'2' => Kind::Symlink,
+            // hard links, devices, fifos and pax/gnu extension headers
+            _ => return Ok(None),
+        };
+
+        let archive = self.data;
+        let data = match kind {
+            Kind::Symlink => field(&header[157..257]).as_bytes(),
+            _ => &archive[data_start..data_end],
+        };
+
+        Ok(Some(Entry {
+            prefix: field(&header[345..500]),
+            name: field(&header[0..100]),
+            kind: kind,
+            mode: octal(&header[100..108])? as u32 & 0o7777,
+            data: data,
+        }))
+    }
+}
+
+fn field(bytes: &'static [u8]) -> &'static str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    str::from_utf8(&bytes[..len]).unwrap_or("")
+}
+
+fn octal(bytes: &[u8]) -> Result<u64, Error> {
+    let mut value = 0;
+    for &b in bytes.iter().skip_while(|&&b| b == b' ') {
+        match b {
+            b'0'...b'7' => value = value * 8 + (b - b'0') as u64,
+            b' ' | 0 => break,
+            _ => return Err(Error::BadHeader),
+        }
+    }
+    Ok(value)
+}
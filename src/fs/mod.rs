@@ -0,0 +1,292 @@
+// The virtual filesystem: filesystems provide inodes, which are stitched
+// together into one tree by the mount table and accessed by processes through
+// file descriptors.
+
+pub mod devfs;
+pub mod ext2;
+pub mod fat;
+pub mod file;
+pub mod initramfs;
+mod mount;
+pub mod procfs;
+
+use core::result;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use collections::string::String;
+use collections::vec::Vec;
+
+use block::{self, BlockDevice};
+use boot;
+use proc;
+
+pub use self::file::{Fd, File, FileTable, SeekFrom};
+pub use self::file::{OpenFlags, O_READ, O_WRITE, O_CREATE, O_TRUNCATE, O_APPEND, O_DIRECTORY};
+pub use self::mount::{mount, mount_root, unmount, mounts, resolve, resolve_parent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    NotEmpty,
+    Exists,
+    ReadOnly,
+    NotSupported,
+    InvalidArgument,
+    BadDescriptor,
+    TooManyOpenFiles,
+    TooManyLinks,
+    NoSpace,
+    Busy,
+    Io,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    // identifies the filesystem instance; see `alloc_dev`
+    pub dev: usize,
+    pub ino: u64,
+    pub kind: FileType,
+    pub size: u64,
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub ino: u64,
+    pub kind: FileType,
+}
+
+pub trait Filesystem: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> Arc<Inode>;
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub trait Inode: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    fn lookup(&self, _name: &str) -> Result<Arc<Inode>> {
+        Err(Error::NotADirectory)
+    }
+
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        Err(Error::NotADirectory)
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<usize> {
+        Err(Error::IsADirectory)
+    }
+
+    // Like `read_at`, but also returns where the bytes read start, which is
+    // past `offset` for inodes that drop old data, like a ring buffer.
+    fn read_from(&self, offset: u64, buf: &mut [u8]) -> Result<(u64, usize)> {
+        self.read_at(offset, buf).map(|count| (offset, count))
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize> {
+        Err(Error::ReadOnly)
+    }
+
+    fn truncate(&self, _size: u64) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    fn create(&self, _name: &str, _kind: FileType) -> Result<Arc<Inode>> {
+        Err(Error::ReadOnly)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    fn readlink(&self) -> Result<String> {
+        Err(Error::InvalidArgument)
+    }
+}
+
+static NEXT_DEV: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Every filesystem instance takes a device number so that inodes from
+// different filesystems can be told apart.
+pub fn alloc_dev() -> usize {
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+pub fn init() {
+    assert_has_not_been_called!();
+    let root_device = boot::option("root").map(|name| name.trim_left_matches("/dev/"));
+    let mounted_root = match root_device {
+        Some(name) => mount_root_device(name),
+        None => false,
+    };
+    if !mounted_root {
+        match boot::module("initrd").map(|module| initramfs::mount(module.data())) {
+            Some(Ok(root)) => mount_root(root),
+            Some(Err(error)) => error!("initrd: unreadable archive ({:?})", error),
+            None => warn!("initrd: no module loaded, there is no root filesystem"),
+        }
+    }
+
+    if let Err(error) = mount_creating("/proc", Arc::new(procfs::ProcFs::new())) {
+        error!("procfs: could not mount on /proc ({:?})", error);
+    }
+    let dev = Arc::new(devfs::DevFs::new());
+    let dev_mounted = match mount_creating("/dev", dev.clone()) {
+        Ok(()) => true,
+        Err(error) => {
+            error!("devfs: could not mount on /dev ({:?})", error);
+            false
+        }
+    };
+
+    // the first other disk holding a FAT filesystem shows up under /mnt
+    for (name, device) in block::devices() {
+        if mounted_root && root_device == Some(&name[..]) {
+            continue;
+        }
+        if let Ok(fat) = fat::mount(device) {
+            match mount_creating("/mnt", fat) {
+                Ok(()) => info!("fat: {} mounted on /mnt", name),
+                Err(error) => warn!("fat: could not mount {} on /mnt ({:?})", name, error),
+            }
+            break;
+        }
+    }
+
+    // standard input, output and error, inherited by the first process, taken
+    // from devfs itself if it is not in the tree
+    let console = if dev_mounted {
+        file::open_inode("/dev/console", O_READ | O_WRITE)
+    } else {
+        dev.root().lookup("console")
+    };
+    if let Ok(console) = console {
+        let mut files = proc::current().files();
+        for _ in 0..3 {
+            if files.insert(Arc::new(File::new(console.clone(), O_READ | O_WRITE))).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// Mounts `fs` on `path`, first making the directory if the filesystem below
+// has none there and can make one.
+fn mount_creating(path: &str, fs: Arc<Filesystem>) -> Result<()> {
+    match mount(path, fs.clone()) {
+        Err(Error::NotFound) => {
+            let (parent, name) = resolve_parent(path)?;
+            parent.create(name, FileType::Directory)?;
+            mount(path, fs)
+        }
+        result => result,
+    }
+}
+
+// Mounts whichever filesystem `device` holds.
+pub fn probe(device: Arc<BlockDevice>) -> Result<Arc<Filesystem>> {
+    ext2::mount(device.clone()).or_else(|_| fat::mount(device))
+}
+
+// Mounts the disk named by `root=` on the kernel command line as the root
+// filesystem instead of the initrd.
+fn mount_root_device(name: &str) -> bool {
+    match block::get(name).ok_or(Error::NotFound).and_then(probe) {
+        Ok(root) => {
+            info!("root: mounted {} ({})", name, root.name());
+            mount_root(root);
+            true
+        }
+        Err(error) => {
+            warn!("root: could not mount {} ({:?}), using the initrd", name, error);
+            false
+        }
+    }
+}
+
+pub fn open(path: &str, flags: OpenFlags) -> Result<Fd> {
+    let inode = file::open_inode(path, flags)?;
+    let file = Arc::new(File::new(inode, flags));
+    proc::current().files().insert(file)
+}
+
+pub fn close(fd: Fd) -> Result<()> {
+    proc::current().files().remove(fd).map(|_| ())
+}
+
+pub fn read(fd: Fd, buf: &mut [u8]) -> Result<usize> {
+    let file = proc::current().files().get(fd)?;
+    file.read(buf)
+}
+
+pub fn write(fd: Fd, buf: &[u8]) -> Result<usize> {
+    let file = proc::current().files().get(fd)?;
+    file.write(buf)
+}
+
+pub fn seek(fd: Fd, pos: SeekFrom) -> Result<u64> {
+    let file = proc::current().files().get(fd)?;
+    file.seek(pos)
+}
+
+pub fn readdir(fd: Fd) -> Result<Option<DirEntry>> {
+    let file = proc::current().files().get(fd)?;
+    file.readdir()
+}
+
+pub fn dup(fd: Fd) -> Result<Fd> {
+    let process = proc::current();
+    let mut files = process.files();
+    let file = files.get(fd)?;
+    files.insert(file)
+}
+
+pub fn stat(path: &str) -> Result<Metadata> {
+    resolve(path).map(|inode| inode.metadata())
+}
+
+pub fn mkdir(path: &str) -> Result<()> {
+    let (parent, name) = resolve_parent(path)?;
+    parent.create(name, FileType::Directory).map(|_| ())
+}
+
+pub fn remove(path: &str) -> Result<()> {
+    let (parent, name) = resolve_parent(path)?;
+    parent.unlink(name)
+}
+
+pub fn read_to_end(path: &str) -> Result<Vec<u8>> {
+    let inode = resolve(path)?;
+    let metadata = inode.metadata();
+    if metadata.kind == FileType::Directory {
+        return Err(Error::IsADirectory);
+    }
+
+    // synthetic files report a size of zero, so read until end of file
+    let mut data = Vec::with_capacity(metadata.size as usize);
+    let mut chunk = [0; 512];
+    loop {
+        match inode.read_at(data.len() as u64, &mut chunk)? {
+            0 => return Ok(data),
+            count => data.extend_from_slice(&chunk[..count]),
+        }
+    }
+}
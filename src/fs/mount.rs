@@ -0,0 +1,158 @@
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use spin::Mutex;
+
+use super::{Error, FileType, Filesystem, Inode, Result};
+
+const MAX_SYMLINK_DEPTH: usize = 8;
+
+struct Mount {
+    path: String,
+    // the (dev, ino) of the directory this filesystem is mounted over, or
+    // None for the root filesystem
+    point: Option<(usize, u64)>,
+    fs: Arc<Filesystem>,
+}
+
+lazy_static! {
+    static ref MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+}
+
+pub fn mount_root(fs: Arc<Filesystem>) {
+    let mut mounts = MOUNTS.lock();
+    assert!(mounts.iter().all(|m| m.point.is_some()), "root filesystem is already mounted");
+    mounts.push(Mount {
+        path: "/".to_string(),
+        point: None,
+        fs: fs,
+    });
+}
+
+pub fn mount(path: &str, fs: Arc<Filesystem>) -> Result<()> {
+    let point = resolve(path)?.metadata();
+    if point.kind != FileType::Directory {
+        return Err(Error::NotADirectory);
+    }
+
+    let mut mounts = MOUNTS.lock();
+    let key = Some((point.dev, point.ino));
+    if mounts.iter().any(|m| m.point == key) {
+        return Err(Error::Busy);
+    }
+    mounts.push(Mount {
+        path: path.to_string(),
+        point: key,
+        fs: fs,
+    });
+    Ok(())
+}
+
+pub fn unmount(path: &str) -> Result<()> {
+    let root = resolve(path)?.metadata();
+    let mut mounts = MOUNTS.lock();
+    let index = mounts.iter()
+        .position(|m| m.point.is_some() && m.fs.root().metadata().dev == root.dev)
+        .ok_or(Error::InvalidArgument)?;
+    if mounts.iter().any(|m| m.point.map(|(dev, _)| dev) == Some(root.dev)) {
+        return Err(Error::Busy);
+    }
+    let mount = mounts.remove(index);
+    mount.fs.sync()
+}
+
+// (mount path, filesystem name) pairs in mount order.
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNTS.lock().iter().map(|m| (m.path.clone(), m.fs.name())).collect()
+}
+
+fn root() -> Result<Arc<Inode>> {
+    MOUNTS.lock()
+        .iter()
+        .find(|m| m.point.is_none())
+        .map(|m| m.fs.root())
+        .ok_or(Error::NotFound)
+}
+
+// Steps from a mount point into the root of the filesystem mounted on it, as
+// many times as filesystems are stacked there.
+fn cross_mounts(mut inode: Arc<Inode>) -> Arc<Inode> {
+    loop {
+        let metadata = inode.metadata();
+        let key = Some((metadata.dev, metadata.ino));
+        let mounted = MOUNTS.lock().iter().rev().find(|m| m.point == key).map(|m| m.fs.root());
+        match mounted {
+            Some(root) => inode = root,
+            None => return inode,
+        }
+    }
+}
+
+// Walks `path` on top of `stack`, which holds the directories from the root
+// down to the current one so that `..` can climb back out of mounts.
+fn walk(stack: &mut Vec<Arc<Inode>>, path: &str, follow_last: bool, depth: usize) -> Result<()> {
+    if path.starts_with('/') {
+        stack.truncate(1);
+    }
+
+    let mut components = path.split('/').filter(|name| !name.is_empty()).peekable();
+    while let Some(name) = components.next() {
+        let is_last = components.peek().is_none();
+        match name {
+            "." => continue,
+            ".." => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let child = {
+            let dir = stack.last().unwrap();
+            if dir.metadata().kind != FileType::Directory {
+                return Err(Error::NotADirectory);
+            }
+            cross_mounts(dir.lookup(name)?)
+        };
+
+        if child.metadata().kind == FileType::Symlink && (follow_last || !is_last) {
+            if depth >= MAX_SYMLINK_DEPTH {
+                return Err(Error::TooManyLinks);
+            }
+            let target = child.readlink()?;
+            walk(stack, &target, true, depth + 1)?;
+        } else {
+            stack.push(child);
+        }
+    }
+    Ok(())
+}
+
+pub fn resolve(path: &str) -> Result<Arc<Inode>> {
+    let mut stack = vec![root()?];
+    walk(&mut stack, path, true, 0)?;
+    Ok(stack.pop().unwrap())
+}
+
+// Resolves all but the last component of `path`, returning the containing
+// directory and the final name.
+pub fn resolve_parent(path: &str) -> Result<(Arc<Inode>, &str)> {
+    let trimmed = path.trim_right_matches('/');
+    let (dir, name) = match trimmed.rfind('/') {
+        Some(index) => (&trimmed[..index + 1], &trimmed[index + 1..]),
+        None => ("", trimmed),
+    };
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut stack = vec![root()?];
+    walk(&mut stack, dir, true, 0)?;
+    let parent = stack.pop().unwrap();
+    if parent.metadata().kind != FileType::Directory {
+        return Err(Error::NotADirectory);
+    }
+    Ok((parent, name))
+}
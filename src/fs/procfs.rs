@@ -0,0 +1,223 @@
+// A synthetic filesystem that renders kernel state as text, normally
+// mounted on /proc. Files are generated afresh on every read.
+
+use core::cmp::min;
+use core::fmt::Write;
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+
+use block;
+use boot;
+use drivers::pci;
+use fs::{self, DirEntry, FileType, Filesystem, Inode, Metadata};
+use holealloc;
+use int;
+use log;
+use mem::{self, PAGE_SIZE};
+use proc;
+use time;
+
+const ROOT_INO: u64 = 1;
+
+type Generator = fn(&mut String);
+
+const FILES: [(&'static str, Generator); 9] = [
+    ("meminfo", meminfo),
+    ("memmap", memmap),
+    ("modules", modules),
+    ("interrupts", interrupts),
+    ("pci", pci),
+    ("tasks", tasks),
+    ("uptime", uptime),
+    ("mounts", mounts),
+    ("dmesg", dmesg),
+];
+
+pub struct ProcFs {
+    dev: usize,
+}
+
+impl ProcFs {
+    pub fn new() -> ProcFs {
+        ProcFs { dev: fs::alloc_dev() }
+    }
+}
+
+impl Filesystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> Arc<Inode> {
+        Arc::new(ProcRoot { dev: self.dev })
+    }
+}
+
+struct ProcRoot {
+    dev: usize,
+}
+
+impl Inode for ProcRoot {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: self.dev,
+            ino: ROOT_INO,
+            kind: FileType::Directory,
+            size: 0,
+            mode: 0o555,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> fs::Result<Arc<Inode>> {
+        match FILES.iter().position(|&(file, _)| file == name) {
+            Some(index) => {
+                Ok(Arc::new(ProcFile {
+                    dev: self.dev,
+                    index: index,
+                }))
+            }
+            None => Err(fs::Error::NotFound),
+        }
+    }
+
+    fn readdir(&self) -> fs::Result<Vec<DirEntry>> {
+        Ok(FILES.iter()
+            .enumerate()
+            .map(|(index, &(name, _))| {
+                DirEntry {
+                    name: name.to_string(),
+                    ino: ROOT_INO + 1 + index as u64,
+                    kind: FileType::File,
+                }
+            })
+            .collect())
+    }
+}
+
+struct ProcFile {
+    dev: usize,
+    index: usize,
+}
+
+impl Inode for ProcFile {
+    // The size is unknown until the file is rendered, so it reads as zero.
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: self.dev,
+            ino: ROOT_INO + 1 + self.index as u64,
+            kind: FileType::File,
+            size: 0,
+            mode: 0o444,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        let mut text = String::new();
+        (FILES[self.index].1)(&mut text);
+
+        let bytes = text.as_bytes();
+        let offset = offset as usize;
+        if offset >= bytes.len() {
+            return Ok(0);
+        }
+        let count = min(buf.len(), bytes.len() - offset);
+        buf[..count].copy_from_slice(&bytes[offset..offset + count]);
+        Ok(count)
+    }
+}
+
+fn meminfo(out: &mut String) {
+    let (total, allocated, free) =
+        mem::with_controller(|mc| (mc.total_frames(), mc.allocated_frames(), mc.free_frames()));
+    let kib = |frames: usize| frames * PAGE_SIZE / 1024;
+    writeln!(out, "frames total:     {:>10} kB", kib(total)).unwrap();
+    writeln!(out, "frames allocated: {:>10} kB", kib(allocated)).unwrap();
+    writeln!(out, "frames free:      {:>10} kB", kib(free)).unwrap();
+
+    let (cached, dirty) = block::cache::stats();
+    writeln!(out, "block cache:      {:>10} kB", kib(cached)).unwrap();
+    writeln!(out, "block dirty:      {:>10} kB", kib(dirty)).unwrap();
+
+    let heap_used = holealloc::used();
+    writeln!(out, "heap start:       {:#10x}", holealloc::HEAP_START).unwrap();
+    writeln!(out, "heap size:        {:>10} B", holealloc::HEAP_SIZE).unwrap();
+    writeln!(out, "heap used:        {:>10} B", heap_used).unwrap();
+    writeln!(out, "heap free:        {:>10} B", holealloc::HEAP_SIZE - heap_used).unwrap();
+}
+
+fn memmap(out: &mut String) {
+    for area in mem::with_controller(|mc| mc.memory_areas()) {
+        writeln!(out,
+                 "{:#016x}-{:#016x} available",
+                 area.base_addr,
+                 area.base_addr + area.length - 1)
+            .unwrap();
+    }
+}
+
+fn modules(out: &mut String) {
+    for module in boot::loaded_modules() {
+        writeln!(out,
+                 "{:#010x}-{:#010x} {:>8} {} ({})",
+                 module.start_address(),
+                 module.end_address(),
+                 module.end_address() - module.start_address(),
+                 module.name(),
+                 module.cmdline())
+            .unwrap();
+    }
+}
+
+fn interrupts(out: &mut String) {
+    for (vector, name) in int::handlers() {
+        writeln!(out, "{:>3} {}", vector, name).unwrap();
+    }
+}
+
+fn pci(out: &mut String) {
+    for (device, driver) in pci::devices() {
+        writeln!(out,
+                 "{} {:04x}:{:04x} {:02x}{:02x}{:02x} {}",
+                 device.address,
+                 device.vendor_id,
+                 device.device_id,
+                 device.class,
+                 device.subclass,
+                 device.prog_if,
+                 driver.unwrap_or("-"))
+            .unwrap();
+    }
+}
+
+fn tasks(out: &mut String) {
+    writeln!(out, "{:>5} {:<8} {}", "pid", "state", "name").unwrap();
+    for process in proc::processes() {
+        let state = format!("{:?}", process.state());
+        writeln!(out, "{:>5} {:<8} {}", process.pid(), state, process.name()).unwrap();
+    }
+}
+
+fn uptime(out: &mut String) {
+    let ms = time::uptime_ms();
+    writeln!(out, "{}.{:03}", ms / 1000, ms % 1000).unwrap();
+}
+
+fn mounts(out: &mut String) {
+    for (path, name) in fs::mounts() {
+        writeln!(out, "{} {}", name, path).unwrap();
+    }
+}
+
+fn dmesg(out: &mut String) {
+    let bytes = int::without_interrupts(|| {
+        let dmesg = log::DMESG.lock();
+        let mut bytes = vec![0; dmesg.end() - dmesg.first()];
+        let (_, count) = dmesg.read_at(dmesg.first(), &mut bytes);
+        bytes.truncate(count);
+        bytes
+    });
+    // the oldest record may have been cut short
+    out.push_str(&String::from_utf8_lossy(&bytes));
+}
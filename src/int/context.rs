@@ -0,0 +1,142 @@
+// The state of the CPU when an exception struck, as saved by the entry code of
+// the exception handlers. Registers changed in it are restored on return.
+
+use core::fmt;
+
+bitflags! {
+    pub flags RFlags: u64 {
+        const CARRY                     = 1 << 0,
+        const PARITY                    = 1 << 2,
+        const ADJUST                    = 1 << 4,
+        const ZERO                      = 1 << 6,
+        const SIGN                      = 1 << 7,
+        const TRAP                      = 1 << 8,
+        const INTERRUPT                 = 1 << 9,
+        const DIRECTION                 = 1 << 10,
+        const OVERFLOW                  = 1 << 11,
+        const NESTED_TASK               = 1 << 14,
+        const RESUME                    = 1 << 16,
+        const VIRTUAL_8086              = 1 << 17,
+        const ALIGNMENT_CHECK           = 1 << 18,
+        const VIRTUAL_INTERRUPT         = 1 << 19,
+        const VIRTUAL_INTERRUPT_PENDING = 1 << 20,
+        const ID                        = 1 << 21,
+    }
+}
+
+const IOPL_SHIFT: u64 = 12;
+
+// In the order the entry code pushes them, the last pushed first.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Context {
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    // zero for exceptions that push none
+    pub error_code: u64,
+    // pushed by the CPU
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl Context {
+    pub fn flags(&self) -> RFlags {
+        RFlags::from_bits_truncate(self.rflags)
+    }
+
+    // Whether the exception struck in ring 3.
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = [[("rax", self.rax), ("rbx", self.rbx), ("rcx", self.rcx)],
+                    [("rdx", self.rdx), ("rsi", self.rsi), ("rdi", self.rdi)],
+                    [("rbp", self.rbp), ("rsp", self.rsp), ("r8", self.r8)],
+                    [("r9", self.r9), ("r10", self.r10), ("r11", self.r11)],
+                    [("r12", self.r12), ("r13", self.r13), ("r14", self.r14)],
+                    [("r15", self.r15), ("rip", self.rip), ("cr0", self.cr0)],
+                    [("cr2", self.cr2), ("cr3", self.cr3), ("cr4", self.cr4)]];
+        for row in rows.iter() {
+            for &(name, value) in row.iter() {
+                write!(f, "{:3} {:#018x}  ", name, value)?;
+            }
+            f.write_str("\n")?;
+        }
+        writeln!(f,
+                 "cs {:#06x} ss {:#06x} ds {:#06x} es {:#06x} fs {:#06x} gs {:#06x}",
+                 self.cs,
+                 self.ss,
+                 self.ds,
+                 self.es,
+                 self.fs,
+                 self.gs)?;
+        writeln!(f,
+                 "rflags {:#x} iopl {} {:?}",
+                 self.rflags,
+                 self.rflags >> IOPL_SHIFT & 3,
+                 self.flags())?;
+        write!(f, "error code {:#x}", self.error_code)
+    }
+}
+
+bitflags! {
+    pub flags PageFaultErrorCode: u64 {
+        const PROTECTION_VIOLATION = 1 << 0,
+        const CAUSED_BY_WRITE      = 1 << 1,
+        const USER_MODE            = 1 << 2,
+        const MALFORMED_TABLE      = 1 << 3,
+        const INSTRUCTION_FETCH    = 1 << 4,
+        const PROTECTION_KEY       = 1 << 5,
+        const SGX                  = 1 << 15,
+    }
+}
+
+// The error code of the faults caused by a segment selector: which selector
+// it was, or zero if none.
+pub struct SelectorErrorCode(pub u64);
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.0;
+        if code == 0 {
+            return write!(f, "no selector");
+        }
+        let table = match code >> 1 & 3 {
+            0 => "gdt",
+            2 => "ldt",
+            _ => "idt",
+        };
+        write!(f, "{} entry {}", table, code >> 3 & 0x1fff)?;
+        if code & 1 != 0 {
+            write!(f, ", external")?;
+        }
+        Ok(())
+    }
+}
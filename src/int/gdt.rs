@@ -0,0 +1,136 @@
+use core::mem::size_of;
+
+use bit_field::BitField;
+
+// The first two selectors match the GDT built in boot.asm, so CS and the data
+// segments stay valid when this table replaces it.
+pub const KERNEL_CODE_SELECTOR: u16 = 1 << 3;
+pub const KERNEL_DATA_SELECTOR: u16 = 2 << 3;
+pub const USER_DATA_SELECTOR: u16 = 3 << 3 | 3;
+pub const USER_CODE_SELECTOR: u16 = 4 << 3 | 3;
+pub const TSS_SELECTOR: u16 = 5 << 3;
+
+bitflags! {
+    flags DescriptorFlags: u64 {
+        const WRITABLE        = 1 << 41,
+        const EXECUTABLE      = 1 << 43,
+        const USER_SEGMENT    = 1 << 44,
+        const RING_3          = 3 << 45,
+        const PRESENT         = 1 << 47,
+        const LONG_MODE       = 1 << 53,
+    }
+}
+
+pub enum Descriptor {
+    UserSegment(u64),
+    SystemSegment(u64, u64),
+}
+
+impl Descriptor {
+    pub fn kernel_code_segment() -> Descriptor {
+        let flags = USER_SEGMENT | PRESENT | EXECUTABLE | LONG_MODE | WRITABLE;
+        Descriptor::UserSegment(flags.bits())
+    }
+
+    pub fn kernel_data_segment() -> Descriptor {
+        let flags = USER_SEGMENT | PRESENT | WRITABLE;
+        Descriptor::UserSegment(flags.bits())
+    }
+
+    pub fn user_code_segment() -> Descriptor {
+        let flags = USER_SEGMENT | PRESENT | EXECUTABLE | LONG_MODE | RING_3;
+        Descriptor::UserSegment(flags.bits())
+    }
+
+    pub fn user_data_segment() -> Descriptor {
+        let flags = USER_SEGMENT | PRESENT | WRITABLE | RING_3;
+        Descriptor::UserSegment(flags.bits())
+    }
+
+    pub fn tss_segment(tss: &'static TaskStateSegment) -> Descriptor {
+        let ptr = tss as *const _ as u64;
+
+        let mut low = PRESENT.bits();
+        low.set_range(0..16, (size_of::<TaskStateSegment>() - 1) as u64);
+        low.set_range(16..40, ptr.get_range(0..24));
+        low.set_range(40..44, 0b1001); // available 64-bit tss
+        low.set_range(56..64, ptr.get_range(24..32));
+
+        let mut high: u64 = 0;
+        high.set_range(0..32, ptr.get_range(32..64));
+
+        Descriptor::SystemSegment(low, high)
+    }
+}
+
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    pub privilege_stacks: [u64; 3],
+    reserved_2: u64,
+    pub interrupt_stacks: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub fn new() -> TaskStateSegment {
+        TaskStateSegment {
+            reserved_1: 0,
+            privilege_stacks: [0; 3],
+            reserved_2: 0,
+            interrupt_stacks: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+pub struct Gdt {
+    table: [u64; 8],
+    next_free: usize,
+}
+
+impl Gdt {
+    pub fn new() -> Gdt {
+        Gdt {
+            table: [0; 8],
+            next_free: 1,
+        }
+    }
+
+    pub fn add_entry(&mut self, entry: Descriptor) -> u16 {
+        let index = match entry {
+            Descriptor::UserSegment(value) => self.push(value),
+            Descriptor::SystemSegment(low, high) => {
+                let index = self.push(low);
+                self.push(high);
+                index
+            }
+        };
+        (index as u16) << 3
+    }
+
+    fn push(&mut self, value: u64) -> usize {
+        assert!(self.next_free < self.table.len(), "gdt is full");
+        let index = self.next_free;
+        self.table[index] = value;
+        self.next_free += 1;
+        index
+    }
+
+    pub fn load(&'static self) {
+        use x86::shared::dtables::{DescriptorTablePointer, lgdt};
+        let ptr = DescriptorTablePointer {
+            base: self.table.as_ptr() as *const _,
+            limit: (self.table.len() * size_of::<u64>() - 1) as u16,
+        };
+        unsafe {
+            lgdt(&ptr);
+            asm!("ltr $0" :: "r"(TSS_SELECTOR) :: "intel", "volatile");
+        }
+    }
+}
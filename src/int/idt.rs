@@ -1,11 +1,17 @@
 use bit_field::BitField;
 use x86::shared::segmentation::{self, SegmentSelector};
 
-pub struct Idt([Entry; 16]);
+pub const ENTRIES: usize = 256;
+
+pub struct Idt([Entry; ENTRIES]);
 
 impl Idt {
     pub fn new() -> Idt {
-        Idt([Entry::missing(); 16])
+        Idt([Entry::missing(); ENTRIES])
+    }
+
+    pub fn is_present(&self, entry: u8) -> bool {
+        self.0[entry as usize].options.0.get_bit(15)
     }
 
     pub fn load(&'static self) {
@@ -91,11 +97,13 @@ impl EntryOptions {
 //        self.0.set_range(13..15, dpl);
 //        self
 //    }
-//
-//    pub fn set_stack_index(&mut self, index: u16) -> &mut EntryOptions {
-//        self.0.set_range(0..3, index);
-//        self
-//    }
+
+    // Switches to interrupt stack `index` of the TSS, counting from 1, for
+    // handlers that cannot trust the stack they interrupted. Zero keeps it.
+    pub fn set_stack_index(&mut self, index: u16) -> &mut EntryOptions {
+        self.0.set_range(0..3, index);
+        self
+    }
 }
 
 pub type HandlerFunc = extern "C" fn() -> !;
@@ -1,6 +1,27 @@
+pub mod context;
+pub mod gdt;
 mod idt;
+pub mod pic;
 
-use vga::kerror;
+use core::fmt;
+
+use alloc::arc::Arc;
+use collections::vec::Vec;
+use spin::Mutex;
+
+use emergency;
+use mem;
+use mem::fault::Fault;
+use proc;
+
+pub use self::context::Context;
+use self::context::{PageFaultErrorCode, SelectorErrorCode};
+
+const PRIVILEGE_STACK_PAGES: usize = 4;
+const DOUBLE_FAULT_STACK_PAGES: usize = 4;
+
+// interrupt stacks count from 1 in the IDT, from 0 in the TSS
+const DOUBLE_FAULT_STACK_INDEX: u16 = 1;
 
 macro_rules! save_scratch_registers {
     () => {
@@ -59,49 +80,307 @@ macro_rules! handler {
     }}
 }
 
-macro_rules! error_code_handler {
+// Saves what a `Context` holds below the error code, which is pushed already.
+macro_rules! save_context {
+    () => {
+        asm!("
+             push rax
+             push rbx
+             push rcx
+             push rdx
+             push rsi
+             push rdi
+             push rbp
+             push r8
+             push r9
+             push r10
+             push r11
+             push r12
+             push r13
+             push r14
+             push r15
+             mov rax, gs
+             push rax
+             mov rax, fs
+             push rax
+             mov rax, es
+             push rax
+             mov rax, ds
+             push rax
+             mov rax, cr4
+             push rax
+             mov rax, cr3
+             push rax
+             mov rax, cr2
+             push rax
+             mov rax, cr0
+             push rax
+        " :::: "intel", "volatile");
+    }
+}
+
+// Puts back the general purpose registers, leaving the error code on the
+// stack.
+macro_rules! restore_context {
+    () => {
+        asm!("
+             add rsp, 8*8
+             pop r15
+             pop r14
+             pop r13
+             pop r12
+             pop r11
+             pop r10
+             pop r9
+             pop r8
+             pop rbp
+             pop rdi
+             pop rsi
+             pop rdx
+             pop rcx
+             pop rbx
+             pop rax
+        " :::: "intel", "volatile");
+    }
+}
+
+// An entry point for an exception, which passes the handler the saved
+// `Context`. Exceptions that push no error code get a zero in its place.
+macro_rules! exception_handler {
     ($name:ident) => {{
         #[naked]
         extern "C" fn wrapper() -> ! {
             unsafe {
-                save_scratch_registers!();
-                asm!("
-                    mov rsi, [rsp + 9*8]
-                    mov rdi, rsp
-                    add rdi, 10*8
-                    sub rsp, 8
-                    call $0
-                    add rsp, 8"
-                    :: "i"($name as extern "C" fn(*const ExceptionStackFrame, u64))
-                    : "rdi", "rsi" : "intel");
-                restore_scratch_registers!();
-
-                asm!("
-                     add rsp, 8
-                     iretq"
-                     :::: "intel", "volatile");
-
-                ::core::intrinsics::unreachable();
+                asm!("push 0" :::: "intel", "volatile");
+                exception_handler!(@body $name)
             }
         }
         wrapper
-    }}
+    }};
+    ($name:ident, error_code) => {{
+        #[naked]
+        extern "C" fn wrapper() -> ! {
+            unsafe {
+                exception_handler!(@body $name)
+            }
+        }
+        wrapper
+    }};
+    (@body $name:ident) => {{
+        save_context!();
+        // the context is 29 words, so the stack needs another to be
+        // aligned for the call
+        asm!("
+            mov rdi, rsp
+            sub rsp, 8
+            call $0
+            add rsp, 8"
+            :: "i"($name as extern "C" fn(&mut Context))
+            : "rdi" : "intel", "volatile");
+        restore_context!();
+
+        asm!("
+             add rsp, 8
+             iretq"
+             :::: "intel", "volatile");
+
+        ::core::intrinsics::unreachable();
+    }};
+}
+
+macro_rules! irq_handlers {
+    ($($irq:expr => $name:ident),*) => {
+        $(
+            extern "C" fn $name(_stack_frame: *const ExceptionStackFrame) {
+                dispatch_irq($irq);
+            }
+        )*
+    }
 }
 
+irq_handlers!(0 => irq0, 1 => irq1, 2 => irq2, 3 => irq3, 4 => irq4, 5 => irq5, 6 => irq6,
+              7 => irq7, 8 => irq8, 9 => irq9, 10 => irq10, 11 => irq11, 12 => irq12,
+              13 => irq13, 14 => irq14, 15 => irq15);
 
 lazy_static! {
         static ref IDT: idt::Idt = {
         let mut idt = idt::Idt::new();
-        idt.set_handler(0, handler!(divide_by_zero));
-        idt.set_handler(3, handler!(breakpoint));
-        idt.set_handler(6, handler!(invalid_opcode));
-        idt.set_handler(14, error_code_handler!(page_fault));
+        idt.set_handler(0, exception_handler!(divide_by_zero));
+        idt.set_handler(3, exception_handler!(breakpoint));
+        idt.set_handler(6, exception_handler!(invalid_opcode));
+        // a kernel stack overflow double faults, as the page fault cannot be
+        // pushed on the stack, so this one needs a stack of its own
+        idt.set_handler(8, exception_handler!(double_fault, error_code))
+            .set_stack_index(DOUBLE_FAULT_STACK_INDEX);
+        idt.set_handler(10, exception_handler!(invalid_tss, error_code));
+        idt.set_handler(11, exception_handler!(segment_not_present, error_code));
+        idt.set_handler(12, exception_handler!(stack_segment_fault, error_code));
+        idt.set_handler(13, exception_handler!(general_protection_fault, error_code));
+        idt.set_handler(14, exception_handler!(page_fault, error_code));
+
+        let irqs: [idt::HandlerFunc; 16] = [
+            handler!(irq0), handler!(irq1), handler!(irq2), handler!(irq3),
+            handler!(irq4), handler!(irq5), handler!(irq6), handler!(irq7),
+            handler!(irq8), handler!(irq9), handler!(irq10), handler!(irq11),
+            handler!(irq12), handler!(irq13), handler!(irq14), handler!(irq15),
+        ];
+        for (irq, &handler) in irqs.iter().enumerate() {
+            idt.set_handler(pic::IRQ_BASE + irq as u8, handler);
+        }
         idt
     };
 }
 
+lazy_static! {
+    static ref TSS: gdt::TaskStateSegment = {
+        let (stack, double_fault_stack) = mem::with_controller(|mc| {
+            (mc.alloc_stack(PRIVILEGE_STACK_PAGES), mc.alloc_stack(DOUBLE_FAULT_STACK_PAGES))
+        });
+        let stack = stack.expect("could not allocate privilege stack");
+        let double_fault_stack = double_fault_stack.expect("could not allocate fault stack");
+        let mut tss = gdt::TaskStateSegment::new();
+        tss.privilege_stacks[0] = stack.top() as u64;
+        tss.interrupt_stacks[DOUBLE_FAULT_STACK_INDEX as usize - 1] =
+            double_fault_stack.top() as u64;
+        tss
+    };
+}
+
+lazy_static! {
+    static ref GDT: gdt::Gdt = {
+        use self::gdt::Descriptor;
+        let mut gdt = gdt::Gdt::new();
+        assert_eq!(gdt.add_entry(Descriptor::kernel_code_segment()), gdt::KERNEL_CODE_SELECTOR);
+        assert_eq!(gdt.add_entry(Descriptor::kernel_data_segment()), gdt::KERNEL_DATA_SELECTOR);
+        assert_eq!(gdt.add_entry(Descriptor::user_data_segment()) | 3, gdt::USER_DATA_SELECTOR);
+        assert_eq!(gdt.add_entry(Descriptor::user_code_segment()) | 3, gdt::USER_CODE_SELECTOR);
+        assert_eq!(gdt.add_entry(Descriptor::tss_segment(&TSS)), gdt::TSS_SELECTOR);
+        gdt
+    };
+}
+
 pub fn init() {
+    GDT.load();
     IDT.load();
+    pic::init();
+}
+
+pub type IrqHandler = Arc<Fn() + Send + Sync>;
+
+lazy_static! {
+    static ref IRQ_HANDLERS: Mutex<Vec<(u8, IrqHandler)>> = Mutex::new(Vec::new());
+}
+
+// Adds a handler for a PIC line, which may be shared with other devices, and
+// unmasks the line. Handlers run with interrupts disabled and must not block.
+pub fn register_irq(irq: u8, handler: IrqHandler) {
+    assert!(irq < pic::IRQ_COUNT, "no such irq");
+    without_interrupts(|| {
+        IRQ_HANDLERS.lock().push((irq, handler));
+        pic::unmask(irq);
+    })
+}
+
+fn dispatch_irq(irq: u8) {
+    if pic::is_spurious(irq) {
+        return;
+    }
+    for &(line, ref handler) in IRQ_HANDLERS.lock().iter() {
+        if line == irq {
+            handler();
+        }
+    }
+    pic::end_of_interrupt(irq);
+}
+
+pub fn enable() {
+    unsafe {
+        asm!("sti" :::: "volatile");
+    }
+}
+
+pub fn disable() {
+    unsafe {
+        asm!("cli" :::: "volatile");
+    }
+}
+
+pub fn are_enabled() -> bool {
+    let flags: u64;
+    unsafe {
+        asm!("pushfq; pop $0" : "=r"(flags) ::: "intel", "volatile");
+    }
+    flags & (1 << 9) != 0
+}
+
+pub fn without_interrupts<F, T>(f: F) -> T
+    where F: FnOnce() -> T
+{
+    let enabled = are_enabled();
+    disable();
+    let result = f();
+    if enabled {
+        enable();
+    }
+    result
+}
+
+// Halts until `condition` holds, checking it again after every interrupt.
+// With interrupts disabled this degrades to spinning.
+pub fn wait_until<F>(mut condition: F)
+    where F: FnMut() -> bool
+{
+    if !are_enabled() {
+        while !condition() {}
+        return;
+    }
+    loop {
+        disable();
+        if condition() {
+            enable();
+            return;
+        }
+        // sti takes effect after the following instruction, so an interrupt
+        // arriving between the check and the hlt still wakes it
+        unsafe {
+            asm!("sti; hlt" :::: "volatile");
+        }
+    }
+}
+
+const EXCEPTIONS: [&'static str; 32] = [
+    "divide error", "debug", "non-maskable interrupt", "breakpoint",
+    "overflow", "bound range exceeded", "invalid opcode", "device not available",
+    "double fault", "coprocessor segment overrun", "invalid tss", "segment not present",
+    "stack-segment fault", "general protection fault", "page fault", "reserved",
+    "x87 floating point", "alignment check", "machine check", "simd floating point",
+    "virtualization", "reserved", "reserved", "reserved",
+    "reserved", "reserved", "reserved", "reserved",
+    "reserved", "reserved", "security exception", "reserved",
+];
+
+const IRQS: [&'static str; 16] = [
+    "timer", "keyboard", "cascade", "com2", "com1", "lpt2", "floppy", "lpt1",
+    "rtc", "irq 9", "irq 10", "irq 11", "mouse", "fpu", "ata primary", "ata secondary",
+];
+
+pub fn vector_name(vector: u8) -> &'static str {
+    if let Some(&name) = EXCEPTIONS.get(vector as usize) {
+        return name;
+    }
+    match vector.checked_sub(pic::IRQ_BASE) {
+        Some(irq) if irq < pic::IRQ_COUNT => IRQS[irq as usize],
+        _ => "interrupt",
+    }
+}
+
+// The vectors that have a handler installed.
+pub fn handlers() -> Vec<(u8, &'static str)> {
+    (0..idt::ENTRIES)
+        .map(|vector| vector as u8)
+        .filter(|&vector| IDT.is_present(vector))
+        .map(|vector| (vector, vector_name(vector)))
+        .collect()
 }
 
 #[derive(Debug)]
@@ -114,47 +393,109 @@ struct ExceptionStackFrame {
     ss: u64,
 }
 
-bitflags! {
-    flags PageFaultErrorCode: u64 {
-        const PROTECTION_VIOLATION = 1 << 0,
-        const CAUSED_BY_WRITE      = 1 << 1,
-        const USER_MODE            = 1 << 2,
-        const MALFORMED_TABLE      = 1 << 3,
-        const INSTRUCTION_FETCH    = 1 << 4,
+// Like the faults below, these kill a user task that causes them and stop
+// the machine when it was the kernel.
+extern "C" fn divide_by_zero(context: &mut Context) {
+    if context.is_user() {
+        let rip = context.rip;
+        kill_user(context, format_args!("division by zero at {:#x}", rip));
+        return;
     }
+    emergency::fatal(format_args!("division by zero at {:#x}\n{}", context.rip, context));
 }
 
-extern "C" fn divide_by_zero(stack_frame: *const ExceptionStackFrame) {
-    unsafe {
-        kerror(format_args!("division by zero\n{:#?}", *stack_frame));
-    };
-    loop {}
+extern "C" fn invalid_opcode(context: &mut Context) {
+    if context.is_user() {
+        let rip = context.rip;
+        kill_user(context, format_args!("invalid opcode at {:#x}", rip));
+        return;
+    }
+    emergency::fatal(format_args!("invalid opcode at {:#x}\n{}", context.rip, context));
 }
 
-extern "C" fn invalid_opcode(stack_frame: *const ExceptionStackFrame) {
-    unsafe {
-        kerror(format_args!("invalid opcode at {:#x}\n{:#?}",
-                            (*stack_frame).ip,
-                            *stack_frame));
-    };
-    loop {}
+extern "C" fn breakpoint(context: &mut Context) {
+    emergency::error(format_args!("breakpoint at {:#x}\n{}", context.rip, context));
 }
 
-extern "C" fn breakpoint(stack_frame: *const ExceptionStackFrame) {
-    unsafe {
-        kerror(format_args!("breakpoint at {:#x}\n{:#?}",
-                            (*stack_frame).ip,
-                            *stack_frame));
-    };
+extern "C" fn double_fault(context: &mut Context) {
+    // cr2 still holds the address of the page fault that could not be pushed
+    if !context.is_user() && mem::in_stack_area(context.cr2 as usize) {
+        stack_overflow(context);
+    }
+    emergency::fatal(format_args!("double fault at {:#x}\n{}", context.rip, context));
 }
 
-extern "C" fn page_fault(stack_frame: *const ExceptionStackFrame, error_code: u64) {
-    use x86::shared::control_regs;
-    unsafe {
-        kerror(format_args!("page fault accessing {:#x} ({:?})\n{:#?}",
-                            control_regs::cr2(),
-                            PageFaultErrorCode::from_bits(error_code).unwrap(),
-                            *stack_frame));
+extern "C" fn invalid_tss(context: &mut Context) {
+    selector_fault(10, context)
+}
+
+extern "C" fn segment_not_present(context: &mut Context) {
+    selector_fault(11, context)
+}
+
+extern "C" fn stack_segment_fault(context: &mut Context) {
+    selector_fault(12, context)
+}
+
+extern "C" fn general_protection_fault(context: &mut Context) {
+    selector_fault(13, context)
+}
+
+// The faults whose error code names a segment selector.
+fn selector_fault(vector: u8, context: &mut Context) {
+    if context.is_user() {
+        let (rip, code) = (context.rip, context.error_code);
+        kill_user(context,
+                  format_args!("{} at {:#x} ({})",
+                               vector_name(vector),
+                               rip,
+                               SelectorErrorCode(code)));
+        return;
+    }
+    emergency::fatal(format_args!("{} at {:#x} ({})\n{}",
+                                  vector_name(vector),
+                                  context.rip,
+                                  SelectorErrorCode(context.error_code),
+                                  context))
+}
+
+// Faults the resolver cannot fix kill the user task that caused them, which
+// returns to the kernel, or stop the machine when it was the kernel.
+extern "C" fn page_fault(context: &mut Context) {
+    // bits this kernel does not know, for protection keys or SGX as yet, are
+    // dropped here but shown with the rest of the context
+    let code = PageFaultErrorCode::from_bits_truncate(context.error_code);
+    let fault = match mem::fault::resolve(context.cr2 as usize, code) {
+        Ok(()) => return,
+        Err(fault) => fault,
     };
-    loop {}
+    if context.is_user() {
+        let (cr2, rip) = (context.cr2, context.rip);
+        kill_user(context,
+                  format_args!("{} accessing {:#x} at {:#x} ({:?})", fault, cr2, rip, code));
+        return;
+    }
+    if fault == Fault::GuardPage {
+        stack_overflow(context);
+    }
+    emergency::fatal(format_args!("page fault accessing {:#x} ({}, {:?})\n{}",
+                                  context.cr2,
+                                  fault,
+                                  code,
+                                  context));
+}
+
+// Ends the user task the exception struck in, making the exception return to
+// the kernel.
+fn kill_user(context: &mut Context, reason: fmt::Arguments) {
+    let process = proc::current();
+    error!("killed {} (pid {}): {}", process.name(), process.pid(), reason);
+    proc::kill_current(context);
+}
+
+fn stack_overflow(context: &Context) -> ! {
+    emergency::fatal(format_args!("kernel stack overflow: guard page {:#x} hit at {:#x}\n{}",
+                                  context.cr2,
+                                  context.rip,
+                                  context))
 }
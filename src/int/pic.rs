@@ -0,0 +1,106 @@
+// The pair of cascaded 8259 interrupt controllers. Their sixteen lines are
+// moved above the CPU exception vectors and masked until a handler is
+// registered for them.
+
+use x86::shared::io::{inb, outb};
+
+pub const IRQ_BASE: u8 = 32;
+pub const IRQ_COUNT: u8 = 16;
+
+const MASTER_COMMAND: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_COMMAND: u16 = 0xa0;
+const SLAVE_DATA: u16 = 0xa1;
+
+const ICW1_INIT: u8 = 0x11;
+const ICW4_8086: u8 = 0x01;
+const OCW3_READ_ISR: u8 = 0x0b;
+const END_OF_INTERRUPT: u8 = 0x20;
+
+const CASCADE_IRQ: u8 = 2;
+
+pub fn init() {
+    unsafe {
+        outb(MASTER_COMMAND, ICW1_INIT);
+        wait();
+        outb(SLAVE_COMMAND, ICW1_INIT);
+        wait();
+        outb(MASTER_DATA, IRQ_BASE);
+        wait();
+        outb(SLAVE_DATA, IRQ_BASE + 8);
+        wait();
+        outb(MASTER_DATA, 1 << CASCADE_IRQ);
+        wait();
+        outb(SLAVE_DATA, CASCADE_IRQ);
+        wait();
+        outb(MASTER_DATA, ICW4_8086);
+        wait();
+        outb(SLAVE_DATA, ICW4_8086);
+        wait();
+
+        outb(MASTER_DATA, !(1 << CASCADE_IRQ));
+        outb(SLAVE_DATA, 0xff);
+    }
+}
+
+// A write to an unused port takes long enough for the old controllers to
+// settle between initialization words.
+fn wait() {
+    unsafe {
+        outb(0x80, 0);
+    }
+}
+
+fn data_port(irq: u8) -> (u16, u8) {
+    if irq < 8 { (MASTER_DATA, irq) } else { (SLAVE_DATA, irq - 8) }
+}
+
+pub fn mask(irq: u8) {
+    let (port, line) = data_port(irq);
+    unsafe {
+        outb(port, inb(port) | 1 << line);
+    }
+}
+
+pub fn unmask(irq: u8) {
+    let (port, line) = data_port(irq);
+    unsafe {
+        outb(port, inb(port) & !(1 << line));
+    }
+}
+
+fn in_service(command: u16) -> u8 {
+    unsafe {
+        outb(command, OCW3_READ_ISR);
+        inb(command)
+    }
+}
+
+// IRQ 7 and 15 are raised spuriously when a request goes away before it is
+// acknowledged, in which case the in-service bit is clear and no end of
+// interrupt may be sent to the controller that raised it.
+pub fn is_spurious(irq: u8) -> bool {
+    match irq {
+        7 => in_service(MASTER_COMMAND) & 0x80 == 0,
+        15 => {
+            if in_service(SLAVE_COMMAND) & 0x80 == 0 {
+                // the master did see the cascade line, so it still wants one
+                unsafe {
+                    outb(MASTER_COMMAND, END_OF_INTERRUPT);
+                }
+                return true;
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(SLAVE_COMMAND, END_OF_INTERRUPT);
+        }
+        outb(MASTER_COMMAND, END_OF_INTERRUPT);
+    }
+}
@@ -0,0 +1,66 @@
+// The kernel message buffer: the most recent console output, kept in a
+// fixed-size ring so that it works before the heap exists.
+
+use core::cmp::min;
+use core::fmt;
+
+use spin::Mutex;
+
+const KMSG_SIZE: usize = 16 * 1024;
+
+pub struct LogBuffer {
+    data: [u8; KMSG_SIZE],
+    // total number of bytes ever written; the buffer holds the last
+    // KMSG_SIZE of them
+    written: usize,
+}
+
+// Locked with interrupts disabled, as interrupt handlers may print.
+pub static KMSG: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
+
+impl LogBuffer {
+    pub const fn new() -> LogBuffer {
+        LogBuffer {
+            data: [0; KMSG_SIZE],
+            written: 0,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.data[self.written % KMSG_SIZE] = byte;
+            self.written += 1;
+        }
+    }
+
+    // The position of the oldest byte still held.
+    pub fn first(&self) -> usize {
+        self.written.saturating_sub(KMSG_SIZE)
+    }
+
+    pub fn end(&self) -> usize {
+        self.written
+    }
+
+    // Copies out the bytes starting at absolute position `offset`. Readers
+    // that have fallen behind get the oldest retained data instead, so this
+    // returns the position the bytes start at along with their count.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> (usize, usize) {
+        let start = if offset < self.first() { self.first() } else { offset };
+        if start >= self.written {
+            return (start, 0);
+        }
+        let count = min(buf.len(), self.written - start);
+        for (i, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.data[(start + i) % KMSG_SIZE];
+        }
+        (start, count)
+    }
+}
+
+impl fmt::Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
@@ -31,15 +31,29 @@ extern crate x86;
 extern crate holealloc;
 
 
+mod kmsg;
 #[macro_use]
 mod vga;
 use vga::*;
+#[macro_use]
+mod log;
 
 mod mem;
 use mem::*;
 
 mod int;
 
+mod acpi;
+mod block;
+mod boot;
+mod drivers;
+mod elf;
+mod emergency;
+mod fs;
+mod proc;
+mod symbols;
+mod time;
+
 #[no_mangle]
 pub extern "C" fn __main__(multiboot_info_p: usize) {
     WRITER.lock().clear();
@@ -48,13 +62,28 @@ pub extern "C" fn __main__(multiboot_info_p: usize) {
 
     enable_nxe_bit();
     enable_write_protect_bit();
+    enable_write_combining();
 
     mem::init(boot_info);
+    boot::init(boot_info);
+    log::init();
+    symbols::init(boot_info);
+    vga::init(boot_info);
+    vga::banner("mezzo");
     int::init();
-
-    unsafe { int!(3) };
-    unsafe { *(0xdeadbeef as *mut u64) = 42 };
-    println!("it did not crash");
+    time::init();
+    int::enable();
+    acpi::init(boot_info);
+    vga::status::init();
+    fs::devfs::init();
+    block::cache::init();
+    drivers::init();
+    fs::init();
+
+    match proc::load_file("/sbin/init", &["init"], &[]) {
+        Ok(image) => proc::run(proc::spawn("init"), image),
+        Err(error) => error!("could not start /sbin/init: {:?}", error),
+    }
 
     loop {}
 }
@@ -75,6 +104,19 @@ fn enable_write_protect_bit() {
     }
 }
 
+// Reprograms the page attribute table the way Linux does, so that the write
+// through entry (PAT1) is write combining. Uncached mappings, which set both
+// NO_CACHE and WRITE_THROUGH, still select PAT3.
+fn enable_write_combining() {
+    use x86::shared::msr::{rdmsr, wrmsr};
+    const IA32_PAT: u32 = 0x277;
+    const WRITE_COMBINING: u64 = 0x01;
+    unsafe {
+        let pat = rdmsr(IA32_PAT);
+        wrmsr(IA32_PAT, pat & !(0xff << 8) | WRITE_COMBINING << 8);
+    }
+}
+
 #[lang = "eh_personality"]
 extern "C" fn eh_personality() {}
 
@@ -82,10 +124,7 @@ extern "C" fn eh_personality() {}
 #[lang = "panic_fmt"]
 #[no_mangle]
 pub extern "C" fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
-    unsafe {
-        vga::kerror(format_args!("{}:{}\n   {}", file, line, fmt));
-    }
-    loop {}
+    emergency::fatal(format_args!("{}:{}\n   {}", file, line, fmt))
 }
 
 #[allow(non_snake_case)]
@@ -0,0 +1,177 @@
+// Leveled kernel logging. A record below the level set for its module is
+// dropped; the others are stamped with the time since boot, kept in the dmesg
+// ring and printed on the console. The ring is static, so records from before
+// the heap and the framebuffer console are kept too.
+//
+// Levels come from the `log=` option on the command line: a default level and
+// `module=level` pairs, separated by commas, as in `log=warn,drivers::ahci=debug`.
+// A module stands for itself and the modules inside it, and the longest one
+// that matches wins.
+
+use core::fmt::{self, Write};
+
+use spin::Mutex;
+
+use boot;
+use int;
+use kmsg::LogBuffer;
+use time;
+
+const MAX_FILTERS: usize = 16;
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ({
+        $crate::log::log($level, module_path!(), format_args!($($arg)*));
+    });
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => (log!($crate::log::Level::Error, $($arg)*));
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => (log!($crate::log::Level::Warn, $($arg)*));
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => (log!($crate::log::Level::Info, $($arg)*));
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => (log!($crate::log::Level::Debug, $($arg)*));
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => (log!($crate::log::Level::Trace, $($arg)*));
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    // Info, the usual level, goes without one.
+    fn prefix(&self) -> &'static str {
+        match *self {
+            Level::Error => "error: ",
+            Level::Warn => "warning: ",
+            Level::Info => "",
+            Level::Debug => "debug: ",
+            Level::Trace => "trace: ",
+        }
+    }
+}
+
+// The log records alone, unlike KMSG, which has everything written to the
+// console. Read through /proc/dmesg.
+pub static DMESG: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
+
+struct Filters {
+    default: Level,
+    modules: [(&'static str, Level); MAX_FILTERS],
+    count: usize,
+}
+
+static FILTERS: Mutex<Filters> = Mutex::new(Filters {
+    default: Level::Info,
+    modules: [("", Level::Info); MAX_FILTERS],
+    count: 0,
+});
+
+impl Filters {
+    fn level(&self, module: &str) -> Level {
+        let mut level = self.default;
+        let mut longest = 0;
+        for &(name, filter) in &self.modules[..self.count] {
+            if covers(name, module) && name.len() >= longest {
+                level = filter;
+                longest = name.len();
+            }
+        }
+        level
+    }
+}
+
+fn covers(filter: &str, module: &str) -> bool {
+    module.starts_with(filter) &&
+    (module.len() == filter.len() || module[filter.len()..].starts_with("::"))
+}
+
+// Sets the levels from the command line. Until then everything at Info and
+// above is logged.
+pub fn init() {
+    assert_has_not_been_called!();
+    let spec = match boot::option("log") {
+        Some(spec) => spec,
+        None => return,
+    };
+    let mut invalid = None;
+    int::without_interrupts(|| {
+        let mut filters = FILTERS.lock();
+        for part in spec.split(',').filter(|part| !part.is_empty()) {
+            let mut pair = part.splitn(2, '=');
+            let (module, level) = match (pair.next(), pair.next()) {
+                (Some(level), None) => (None, level),
+                (module, Some(level)) => (module, level),
+                (None, None) => continue,
+            };
+            match (module, Level::parse(level)) {
+                (None, Some(level)) => filters.default = level,
+                (Some(module), Some(level)) if filters.count < MAX_FILTERS => {
+                    let count = filters.count;
+                    filters.modules[count] = (module, level);
+                    filters.count += 1;
+                }
+                _ => invalid = Some(part),
+            }
+        }
+    });
+    if let Some(part) = invalid {
+        warn!("log: ignoring `{}`", part);
+    }
+}
+
+// Whether records of `level` from `module`, as given by `module_path!`, are
+// logged.
+pub fn enabled(level: Level, module: &str) -> bool {
+    // paths start with the name of the kernel crate, which filters leave out
+    let module = match module.find("::") {
+        Some(index) => &module[index + 2..],
+        None => "",
+    };
+    level <= int::without_interrupts(|| FILTERS.lock().level(module))
+}
+
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    if !enabled(level, module) {
+        return;
+    }
+    // zero until the clock is calibrated
+    let us = time::uptime_us();
+    let (seconds, micros) = (us / 1_000_000, us % 1_000_000);
+    int::without_interrupts(|| {
+        let _ = write!(DMESG.lock(),
+                       "[{:5}.{:06}] {}{}\n",
+                       seconds,
+                       micros,
+                       level.prefix(),
+                       args);
+    });
+    print!("[{:5}.{:06}] {}{}\n", seconds, micros, level.prefix(), args);
+}
@@ -1,18 +1,52 @@
-use core::ops::Range;
+use core::cmp::max;
 
-use mem::{Frame, FrameAllocator};
+use mem::{PAGE_SIZE, Frame, FrameAllocator};
+use mem::paging::{Entry, Page, PhysicalAddress};
+use mem::paging::{NO_EXECUTE, PRESENT, WRITABLE};
 use multiboot2::{MemoryArea, MemoryAreaIter};
 
+const MAX_RESERVED: usize = 16;
+
+// ends the free list
+const NO_FRAME: usize = !0;
+
+// A page the allocator maps freed frames to, one at a time, to get at the
+// link kept in their first word. `entry` is the page table entry of `page`.
+struct Window {
+    page: Page,
+    entry: *mut Entry,
+}
+
 pub struct AreaFrameAllocator {
     area: Option<&'static MemoryArea>,
     areas: MemoryAreaIter,
     next: Frame,
-    kernel: Range<Frame>,
-    multiboot: Range<Frame>,
+    // inclusive frame number ranges that must never be handed out
+    reserved: [(usize, usize); MAX_RESERVED],
+    reserved_count: usize,
+    // frames given back after the bump pointer passed them, handed out first.
+    // Each holds the number of the next one, so that freeing never allocates.
+    free_list: Option<Frame>,
+    freed: usize,
+    window: Option<Window>,
+    allocated: usize,
 }
 
+// The memory area iterator points into the multiboot information structure,
+// which stays identity mapped for the lifetime of the kernel.
+unsafe impl Send for AreaFrameAllocator {}
+
 impl FrameAllocator for AreaFrameAllocator {
     fn alloc(&mut self) -> Option<Frame> {
+        if let Some(frame) = self.free_list.take() {
+            let next = unsafe { *self.link(&frame) };
+            if next != NO_FRAME {
+                self.free_list = Some(Frame { number: next });
+            }
+            self.freed -= 1;
+            self.allocated += 1;
+            return Some(frame);
+        }
         if let Some(area) = self.area {
             let frame = Frame { number: self.next.number };
 
@@ -24,54 +58,151 @@ impl FrameAllocator for AreaFrameAllocator {
             if frame > last_frame {
                 self.select_next_area();
                 return self.alloc();
+            } else if let Some(end) = self.reservation_containing(&frame) {
+                self.next.number = end + 1;
+                return self.alloc();
             } else {
                 self.next.number += 1;
+                self.allocated += 1;
                 return Some(frame);
             }
         }
         None
     }
 
-    fn free(&mut self, _frame: Frame) {
-        unimplemented!()
+    // Only called once `set_window` was, as the free list is reached through
+    // the window.
+    fn free(&mut self, frame: Frame) {
+        debug_assert!(frame < self.next || self.area.is_none());
+        let next = self.free_list.take().map_or(NO_FRAME, |next| next.number);
+        unsafe {
+            *self.link(&frame) = next;
+        }
+        self.free_list = Some(frame);
+        self.freed += 1;
+        self.allocated -= 1;
     }
 }
 
 impl AreaFrameAllocator {
-    pub fn new(kernel_start: usize,
-               kernel_end: usize,
-               multiboot_start: usize,
-               multiboot_end: usize,
-               memory_areas: MemoryAreaIter)
-               -> AreaFrameAllocator {
+    pub fn new(memory_areas: MemoryAreaIter) -> AreaFrameAllocator {
         let mut allocator = AreaFrameAllocator {
             area: None,
             areas: memory_areas,
             next: Frame::containing(0),
-            kernel: Range {
-                start: Frame::containing(kernel_start),
-                end: Frame::containing(kernel_end),
-            },
-            multiboot: Range {
-                start: Frame::containing(multiboot_start),
-                end: Frame::containing(multiboot_end),
-            },
+            reserved: [(0, 0); MAX_RESERVED],
+            reserved_count: 0,
+            free_list: None,
+            freed: 0,
+            window: None,
+            allocated: 0,
         };
         allocator.select_next_area();
         allocator
     }
 
+    // Sets the page freed frames are reached through. The tables mapping it
+    // must stay, and be shared by every address space.
+    pub fn set_window(&mut self, page: Page, entry: *mut Entry) {
+        self.window = Some(Window {
+            page: page,
+            entry: entry,
+        });
+    }
+
+    // Maps the window to `frame` and returns where its link is.
+    fn link(&self, frame: &Frame) -> *mut usize {
+        let window = self.window.as_ref().expect("frame freed before mem::init");
+        unsafe {
+            (*window.entry).set(frame.clone(), PRESENT | WRITABLE | NO_EXECUTE);
+            ::x86::shared::tlb::flush(window.page.start());
+        }
+        window.page.start() as *mut usize
+    }
+
+    // Keeps the frames covering `start..end` (such as the kernel image, the
+    // multiboot information and boot modules) from being allocated.
+    pub fn reserve(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+        if end <= start {
+            return;
+        }
+        assert!(self.reserved_count < MAX_RESERVED, "too many reserved ranges");
+        let first = Frame::containing(start).number;
+        let last = Frame::containing(end - 1).number;
+        self.reserved[self.reserved_count] = (first, last);
+        self.reserved_count += 1;
+    }
+
+    // Hands out `count` physically consecutive frames, for device buffers.
+    // Frames skipped to find a large enough run are not reused.
+    pub fn alloc_contiguous(&mut self, count: usize) -> Option<Frame> {
+        assert!(count > 0);
+        while let Some(area) = self.area {
+            let first = self.next.number;
+            let last = first + count - 1;
+            let area_last = Frame::containing((area.base_addr + area.length - 1) as usize).number;
+
+            if last > area_last {
+                self.next.number = area_last + 1;
+                self.select_next_area();
+            } else if let Some(end) = (first..last + 1)
+                .filter_map(|number| self.reservation_containing(&Frame { number: number }))
+                .next() {
+                self.next.number = end + 1;
+            } else {
+                self.next.number = last + 1;
+                self.allocated += count;
+                return Some(Frame { number: first });
+            }
+        }
+        None
+    }
+
+    pub fn memory_areas(&self) -> MemoryAreaIter {
+        self.areas.clone()
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.areas.clone().map(|area| area.length as usize / PAGE_SIZE).sum()
+    }
+
+    pub fn allocated_frames(&self) -> usize {
+        self.allocated
+    }
+
+    // Frames that can still be handed out: the free list plus whatever the
+    // bump pointer has not reached yet, less the reservations ahead of it.
+    pub fn free_frames(&self) -> usize {
+        let next = self.next.number;
+        let ahead: usize = self.areas
+            .clone()
+            .map(|area| {
+                let first = Frame::containing(area.base_addr as usize).number;
+                let last = Frame::containing((area.base_addr + area.length - 1) as usize).number;
+                if last < next { 0 } else { last + 1 - max(first, next) }
+            })
+            .sum();
+        let reserved: usize = self.reserved[..self.reserved_count]
+            .iter()
+            .filter(|&&(_, last)| last >= next)
+            .map(|&(first, last)| last + 1 - max(first, next))
+            .sum();
+        self.freed + ahead.saturating_sub(reserved)
+    }
+
+    fn reservation_containing(&self, frame: &Frame) -> Option<usize> {
+        self.reserved[..self.reserved_count]
+            .iter()
+            .find(|&&(first, last)| first <= frame.number && frame.number <= last)
+            .map(|&(_, last)| last)
+    }
+
     fn select_next_area(&mut self) {
         self.area = self.areas
             .clone()
             .filter(|area| {
                 let last_address = area.base_addr + area.length - 1;
-                let frame = Frame::containing(last_address as usize);
-
-                frame >= self.next &&
-                !(self.kernel.start.number..(self.kernel.end.number)).contains(frame.number) &&
-                !(self.multiboot.start.number..(self.multiboot.end.number))
-                     .contains(frame.number)
+                Frame::containing(last_address as usize) >= self.next
             })
             .min_by_key(|area| area.base_addr);
 
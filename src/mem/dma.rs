@@ -0,0 +1,90 @@
+use core::slice;
+
+use mem::{PAGE_SIZE, Frame};
+use mem::area_frame_allocator::AreaFrameAllocator;
+use mem::paging::{self, Page, PageIter, ActivePageTable, PhysicalAddress, VirtualAddress};
+
+// Buffers that devices read and write directly. Each is backed by physically
+// consecutive frames and mapped into its own stretch of kernel address space.
+pub struct DmaAllocator {
+    range: PageIter,
+}
+
+impl DmaAllocator {
+    pub fn new(range: PageIter) -> DmaAllocator {
+        DmaAllocator { range: range }
+    }
+
+    pub fn alloc(&mut self,
+                 active_table: &mut ActivePageTable,
+                 allocator: &mut AreaFrameAllocator,
+                 size_in_pages: usize)
+                 -> Option<DmaBuffer> {
+        if size_in_pages == 0 {
+            return None;
+        }
+
+        let mut range = self.range.clone();
+        let start = match range.next() {
+            Some(start) => start,
+            None => return None,
+        };
+        if size_in_pages > 1 && range.nth(size_in_pages - 2).is_none() {
+            return None;
+        }
+        let first = match allocator.alloc_contiguous(size_in_pages) {
+            Some(first) => first,
+            None => return None,
+        };
+        self.range = range;
+
+        for index in 0..size_in_pages {
+            let frame = Frame { number: first.number + index };
+            active_table.map_to(start + index,
+                                frame,
+                                paging::WRITABLE | paging::NO_EXECUTE,
+                                allocator);
+        }
+
+        let buffer = DmaBuffer {
+            physical: first.start(),
+            virt: start.start(),
+            size: size_in_pages * PAGE_SIZE,
+        };
+        for byte in buffer.as_mut_slice() {
+            *byte = 0;
+        }
+        Some(buffer)
+    }
+}
+
+#[derive(Debug)]
+pub struct DmaBuffer {
+    physical: PhysicalAddress,
+    virt: VirtualAddress,
+    size: usize,
+}
+
+impl DmaBuffer {
+    pub fn physical(&self) -> PhysicalAddress {
+        self.physical
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virt as *mut u8
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.virt as *const u8, self.size) }
+    }
+
+    // The device may write the buffer at any time, so exclusive access is up
+    // to the driver that owns it.
+    pub fn as_mut_slice(&self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.virt as *mut u8, self.size) }
+    }
+}
@@ -0,0 +1,195 @@
+// Resolving page faults on user memory. Memory a program gets beyond the data
+// in its file, its bss and its stack, is only described by regions at exec
+// time, and frames are mapped when it is first touched:
+//
+// - reading an untouched page maps a shared zero frame read-only, marked
+//   COPY_ON_WRITE;
+// - writing to a copy-on-write page, or to an untouched one, gives it a frame
+//   of its own;
+// - a stack region stretches down to its maximum size, with the page below it
+//   left unmapped as a guard.
+//
+// There is one user address space at a time, so the regions are global.
+
+use core::fmt;
+use core::ptr;
+
+use collections::vec::Vec;
+use spin::Mutex;
+
+use int::context::{PageFaultErrorCode, CAUSED_BY_WRITE, INSTRUCTION_FETCH, MALFORMED_TABLE};
+use int::context::{PROTECTION_VIOLATION, USER_MODE};
+use super::{PAGE_SIZE, Frame, FrameAllocator, MemoryController, MEMORY_CONTROLLER};
+use super::paging::{EntryFlags, Page, VirtualAddress};
+use super::paging::{COPY_ON_WRITE, NO_EXECUTE, WRITABLE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Anonymous,
+    // the page below it is a guard page
+    Stack,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start: VirtualAddress,
+    pub end: VirtualAddress,
+    pub flags: EntryFlags,
+    pub kind: RegionKind,
+}
+
+impl Region {
+    fn contains(&self, address: VirtualAddress) -> bool {
+        address >= self.start && address < self.end
+    }
+
+    fn guards(&self, address: VirtualAddress) -> bool {
+        self.kind == RegionKind::Stack && address < self.start && address >= self.start - PAGE_SIZE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // nothing is meant to be at the address
+    Unmapped,
+    // the access is not allowed there
+    Protection,
+    // a user stack reached its guard page
+    StackOverflow,
+    // a kernel stack did
+    GuardPage,
+    OutOfMemory,
+    MalformedTable,
+    // the fault struck while the memory controller was locked
+    ControllerBusy,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Fault::Unmapped => "no memory there",
+            Fault::Protection => "access not allowed",
+            Fault::StackOverflow => "stack overflow",
+            Fault::GuardPage => "kernel stack overflow",
+            Fault::OutOfMemory => "out of memory",
+            Fault::MalformedTable => "malformed page table",
+            Fault::ControllerBusy => "memory controller in use",
+        })
+    }
+}
+
+lazy_static! {
+    static ref REGIONS: Mutex<Vec<Region>> = Mutex::new(Vec::new());
+}
+
+// Shared by every page that was read but never written. Allocated on first
+// use and never freed.
+static ZERO_FRAME: Mutex<Option<Frame>> = Mutex::new(None);
+
+// Replaces the regions of the user address space, when it is switched to.
+pub fn set_regions(regions: Vec<Region>) {
+    *REGIONS.lock() = regions;
+}
+
+pub fn clear_regions() {
+    REGIONS.lock().clear();
+}
+
+// Whether `frame` is the zero frame, which stays when the pages it is mapped
+// to are freed.
+pub fn is_zero_frame(frame: &Frame) -> bool {
+    ZERO_FRAME.lock().as_ref() == Some(frame)
+}
+
+// Makes the access that faulted on `address` possible, so that it can be
+// retried, or tells why it cannot be.
+pub fn resolve(address: VirtualAddress, code: PageFaultErrorCode) -> Result<(), Fault> {
+    if code.contains(MALFORMED_TABLE) {
+        return Err(Fault::MalformedTable);
+    }
+    if !code.contains(USER_MODE) && !code.contains(PROTECTION_VIOLATION) &&
+       super::in_stack_area(address) {
+        return Err(Fault::GuardPage);
+    }
+
+    let region = {
+        let regions = REGIONS.lock();
+        match regions.iter().find(|region| region.contains(address)) {
+            Some(&region) => region,
+            None if regions.iter().any(|region| region.guards(address)) => {
+                return Err(Fault::StackOverflow)
+            }
+            None if code.contains(PROTECTION_VIOLATION) => return Err(Fault::Protection),
+            None => return Err(Fault::Unmapped),
+        }
+    };
+    let write = code.contains(CAUSED_BY_WRITE);
+    if (write && !region.flags.contains(WRITABLE)) ||
+       (code.contains(INSTRUCTION_FETCH) && region.flags.contains(NO_EXECUTE)) {
+        return Err(Fault::Protection);
+    }
+
+    // the kernel faulting while it holds the controller is a bug, and waiting
+    // for it would hang
+    let mut controller = match MEMORY_CONTROLLER.try_lock() {
+        Some(controller) => controller,
+        None => return Err(Fault::ControllerBusy),
+    };
+    let mc = controller.as_mut().expect("page fault before mem::init");
+    let page = Page::containing(address);
+    match mc.active_table.flags(page) {
+        None if write => map_zeroed(mc, page, region.flags),
+        None => map_zero_frame(mc, page, region.flags),
+        Some(flags) if write && flags.contains(COPY_ON_WRITE) => copy(mc, page, region.flags),
+        // mapped since the access, which can just be retried
+        Some(_) if !code.contains(PROTECTION_VIOLATION) => Ok(()),
+        Some(_) => Err(Fault::Protection),
+    }
+}
+
+fn map_zeroed(mc: &mut MemoryController, page: Page, flags: EntryFlags) -> Result<(), Fault> {
+    let frame = mc.frame_allocator.alloc().ok_or(Fault::OutOfMemory)?;
+    mc.active_table.map_to(page, frame, flags, &mut mc.frame_allocator);
+    unsafe {
+        ptr::write_bytes(page.start() as *mut u8, 0, PAGE_SIZE);
+    }
+    Ok(())
+}
+
+fn map_zero_frame(mc: &mut MemoryController,
+                  page: Page,
+                  flags: EntryFlags)
+                  -> Result<(), Fault> {
+    let mut zero_frame = ZERO_FRAME.lock();
+    if zero_frame.is_none() {
+        let frame = mc.frame_allocator.alloc().ok_or(Fault::OutOfMemory)?;
+        let address = mc.temporary_page.map(frame.clone(), &mut mc.active_table);
+        unsafe {
+            ptr::write_bytes(address as *mut u8, 0, PAGE_SIZE);
+        }
+        mc.temporary_page.unmap(&mut mc.active_table);
+        *zero_frame = Some(frame);
+    }
+    let frame = zero_frame.as_ref().unwrap().clone();
+    let flags = if flags.contains(WRITABLE) {
+        flags - WRITABLE | COPY_ON_WRITE
+    } else {
+        flags
+    };
+    mc.active_table.map_to(page, frame, flags, &mut mc.frame_allocator);
+    Ok(())
+}
+
+// Gives a copy-on-write page a frame of its own with what it held.
+fn copy(mc: &mut MemoryController, page: Page, flags: EntryFlags) -> Result<(), Fault> {
+    let frame = mc.frame_allocator.alloc().ok_or(Fault::OutOfMemory)?;
+    let address = mc.temporary_page.map(frame.clone(), &mut mc.active_table);
+    unsafe {
+        ptr::copy_nonoverlapping(page.start() as *const u8, address as *mut u8, PAGE_SIZE);
+    }
+    mc.temporary_page.unmap(&mut mc.active_table);
+    // the shared frame is the zero frame, which is kept
+    mc.active_table.unmap(page, &mut mc.frame_allocator);
+    mc.active_table.map_to(page, frame, flags, &mut mc.frame_allocator);
+    Ok(())
+}
@@ -1,15 +1,49 @@
 mod area_frame_allocator;
-mod paging;
+mod dma;
+mod stack_allocator;
+pub mod fault;
+pub mod paging;
 
-use multiboot2::BootInformation;
+use multiboot2::{BootInformation, MemoryAreaIter};
+use spin::Mutex;
+
+use boot;
 
 pub use self::area_frame_allocator::AreaFrameAllocator;
+pub use self::dma::DmaBuffer;
 pub use self::paging::test_paging;
+pub use self::stack_allocator::Stack;
 
-use self::paging::PhysicalAddress;
+use self::paging::{ActivePageTable, InactivePageTable, EntryFlags, Page, TemporaryPage};
+use self::paging::{PhysicalAddress, VirtualAddress};
+use self::dma::DmaAllocator;
+use self::stack_allocator::StackAllocator;
 
 pub const PAGE_SIZE: usize = 4096;
 
+const STACK_AREA_PAGES: usize = 100;
+
+// device buffers live at 256 GiB, clear of identity mapped RAM and MMIO
+const DMA_AREA_START: VirtualAddress = 0o000_400_000_000_0000;
+const DMA_AREA_PAGES: usize = 4096;
+
+// the page through which the frame allocator reaches freed frames, just below
+// the device buffers
+const FREE_LIST_WINDOW: VirtualAddress = DMA_AREA_START - PAGE_SIZE;
+
+// below this many free frames, caches are asked to give memory back
+pub const LOW_WATERMARK: usize = 256;
+
+const MAX_SHRINKERS: usize = 8;
+
+static MEMORY_CONTROLLER: Mutex<Option<MemoryController>> = Mutex::new(None);
+
+// Called with the number of frames wanted, returns how many it freed. A
+// shrinker must not be holding the memory controller when it is invoked.
+pub type Shrinker = fn(usize) -> usize;
+
+static SHRINKERS: Mutex<[Option<Shrinker>; MAX_SHRINKERS]> = Mutex::new([None; MAX_SHRINKERS]);
+
 pub fn init(boot_info: &BootInformation) {
     assert_has_not_been_called!();
 
@@ -27,15 +61,29 @@ pub fn init(boot_info: &BootInformation) {
         .max()
         .unwrap();
 
-    let mut frame_allocator = AreaFrameAllocator::new(kernel_start,
-                                                      kernel_end,
-                                                      boot_info.start_address(),
-                                                      boot_info.end_address(),
-                                                      memory_map.memory_areas());
+    let mut frame_allocator = AreaFrameAllocator::new(memory_map.memory_areas());
+    frame_allocator.reserve(kernel_start, kernel_end);
+    frame_allocator.reserve(boot_info.start_address(), boot_info.end_address());
+    for module in boot::modules(boot_info) {
+        frame_allocator.reserve(module.start_address(), module.end_address());
+    }
+    // not part of the kernel image, but kept for backtraces
+    if let Some((symbols, names)) = boot::kernel_symbols(boot_info) {
+        frame_allocator.reserve(symbols.start_address(), symbols.end_address());
+        frame_allocator.reserve(names.start_address(), names.end_address());
+    }
 
     let mut active_table = paging::remap_kernel(&mut frame_allocator, boot_info);
 
-    use self::paging::Page;
+    // map the window once for the tables above it, then give its frame back
+    // through it
+    let window = Page::containing(FREE_LIST_WINDOW);
+    active_table.map(window, paging::WRITABLE | paging::NO_EXECUTE, &mut frame_allocator);
+    let entry = active_table.entry_address(window);
+    let frame = active_table.unmap(window, &mut frame_allocator);
+    frame_allocator.set_window(window, entry);
+    frame_allocator.free(frame);
+
     use holealloc::{HEAP_START, HEAP_SIZE};
 
     let heap_start_page = Page::containing(HEAP_START);
@@ -43,6 +91,216 @@ pub fn init(boot_info: &BootInformation) {
     for page in Page::range_inclusive(heap_start_page, heap_end_page) {
         active_table.map(page, paging::WRITABLE, &mut frame_allocator);
     }
+
+    let stack_allocator = {
+        let stack_start = stack_area_start();
+        let stack_end = stack_start + STACK_AREA_PAGES;
+        StackAllocator::new(Page::range_inclusive(stack_start, stack_end))
+    };
+
+    let dma_allocator = {
+        let start = Page::containing(DMA_AREA_START);
+        DmaAllocator::new(Page::range_inclusive(start, start + (DMA_AREA_PAGES - 1)))
+    };
+
+    let temporary_page = TemporaryPage::new(Page::containing(0xcafebabe000), &mut frame_allocator);
+
+    *MEMORY_CONTROLLER.lock() = Some(MemoryController {
+        active_table: active_table,
+        frame_allocator: frame_allocator,
+        stack_allocator: stack_allocator,
+        dma_allocator: dma_allocator,
+        temporary_page: temporary_page,
+    });
+}
+
+// Kernel stacks are allocated right after the heap.
+fn stack_area_start() -> Page {
+    use holealloc::{HEAP_START, HEAP_SIZE};
+    Page::containing(HEAP_START + HEAP_SIZE - 1) + 1
+}
+
+// Whether `address` is in the area kernel stacks come from. Only guard pages
+// and pages not handed out yet are unmapped there, so a fault on one means a
+// stack overflowed. Unlike `MemoryController::is_stack_address` it takes no
+// lock, for fault handlers.
+pub fn in_stack_area(address: VirtualAddress) -> bool {
+    let start = stack_area_start().start();
+    address >= start && address < start + (STACK_AREA_PAGES + 1) * PAGE_SIZE
+}
+
+// Whether `address` is mapped in the active address space. It reads the page
+// tables without the controller, for the emergency console, which may run
+// while the controller is locked.
+pub fn is_mapped(address: VirtualAddress) -> bool {
+    let canonical = address < 0x0000_8000_0000_0000 || address >= 0xffff_8000_0000_0000;
+    canonical && unsafe { paging::Mapper::new() }.translate(address).is_some()
+}
+
+pub fn with_controller<F, T>(f: F) -> T
+    where F: FnOnce(&mut MemoryController) -> T
+{
+    let mut controller = MEMORY_CONTROLLER.lock();
+    f(controller.as_mut().expect("memory controller used before mem::init"))
+}
+
+pub fn register_shrinker(shrinker: Shrinker) {
+    let mut shrinkers = SHRINKERS.lock();
+    let slot = shrinkers.iter_mut().find(|slot| slot.is_none()).expect("too many shrinkers");
+    *slot = Some(shrinker);
+}
+
+pub fn free_frames() -> usize {
+    with_controller(|mc| mc.free_frames())
+}
+
+// Like `free_frames`, but gives up rather than wait for the controller, for
+// interrupt handlers.
+pub fn try_free_frames() -> Option<usize> {
+    match MEMORY_CONTROLLER.try_lock() {
+        Some(controller) => {
+            let free = controller.as_ref().map(|mc| mc.free_frames());
+            free
+        }
+        None => None,
+    }
+}
+
+// Shrinks caches until `frames` frames are free on top of the low watermark.
+// Returns false if not even `frames` could be made free.
+pub fn reclaim(frames: usize) -> bool {
+    let wanted = frames + LOW_WATERMARK;
+    let shrinkers = *SHRINKERS.lock();
+    for shrinker in shrinkers.iter().filter_map(|shrinker| *shrinker) {
+        let free = free_frames();
+        if free >= wanted {
+            return true;
+        }
+        shrinker(wanted - free);
+    }
+    free_frames() >= frames
+}
+
+pub struct MemoryController {
+    active_table: ActivePageTable,
+    frame_allocator: AreaFrameAllocator,
+    stack_allocator: StackAllocator,
+    dma_allocator: DmaAllocator,
+    temporary_page: TemporaryPage,
+}
+
+impl MemoryController {
+    pub fn alloc_frame(&mut self) -> Option<Frame> {
+        self.frame_allocator.alloc()
+    }
+
+    pub fn memory_areas(&self) -> MemoryAreaIter {
+        self.frame_allocator.memory_areas()
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.frame_allocator.total_frames()
+    }
+
+    pub fn allocated_frames(&self) -> usize {
+        self.frame_allocator.allocated_frames()
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.frame_allocator.free_frames()
+    }
+
+    pub fn alloc_stack(&mut self, size_in_pages: usize) -> Option<Stack> {
+        self.stack_allocator.alloc_stack(&mut self.active_table,
+                                         &mut self.frame_allocator,
+                                         size_in_pages)
+    }
+
+    pub fn alloc_dma(&mut self, size_in_pages: usize) -> Option<DmaBuffer> {
+        self.dma_allocator.alloc(&mut self.active_table,
+                                 &mut self.frame_allocator,
+                                 size_in_pages)
+    }
+
+    pub fn is_stack_address(&self, address: VirtualAddress) -> bool {
+        self.stack_allocator.contains(address)
+    }
+
+    pub fn translate(&self, address: VirtualAddress) -> Option<PhysicalAddress> {
+        self.active_table.translate(address)
+    }
+
+    pub fn map(&mut self, page: Page, flags: EntryFlags) {
+        self.active_table.map(page, flags, &mut self.frame_allocator)
+    }
+
+    // Unmaps `page` and returns its frame to the frame allocator.
+    pub fn unmap(&mut self, page: Page) {
+        let frame = self.active_table.unmap(page, &mut self.frame_allocator);
+        self.frame_allocator.free(frame);
+    }
+
+    pub fn update_flags(&mut self, page: Page, flags: EntryFlags) {
+        self.active_table.update_flags(page, flags)
+    }
+
+    // Identity maps the physical range `start..end`, for firmware tables and
+    // device memory that lie outside the usable memory areas. Pages that are
+    // already mapped to the same frame are left as they are.
+    pub fn identity_map(&mut self, start: PhysicalAddress, end: PhysicalAddress, flags: EntryFlags) {
+        if end <= start {
+            return;
+        }
+        let first = Frame::containing(start);
+        let last = Frame::containing(end - 1);
+        for frame in Frame::range_inclusive(first, last) {
+            let page = Page::containing(frame.start());
+            match self.active_table.translate_page(page) {
+                Some(mapped) => assert!(mapped == frame, "{:#x} is already in use", frame.start()),
+                None => self.active_table.identity_map(frame, flags, &mut self.frame_allocator),
+            }
+        }
+    }
+
+    // Maps the physical range `start..start + size` to consecutive pages from
+    // `page` on, for device memory that need not be identity mapped. Returns
+    // the address `start` ends up at.
+    pub fn map_physical(&mut self,
+                        page: Page,
+                        start: PhysicalAddress,
+                        size: usize,
+                        flags: EntryFlags)
+                        -> VirtualAddress {
+        let first = Frame::containing(start);
+        let last = Frame::containing(start + size - 1);
+        for (index, frame) in Frame::range_inclusive(first, last).enumerate() {
+            self.active_table.map_to(page + index, frame, flags, &mut self.frame_allocator);
+        }
+        page.start() + start % PAGE_SIZE
+    }
+
+    pub fn new_address_space(&mut self) -> InactivePageTable {
+        let frame = self.frame_allocator.alloc().expect("no frames available");
+        InactivePageTable::new_user(frame, &mut self.active_table, &mut self.temporary_page)
+    }
+
+    pub fn switch(&mut self, table: InactivePageTable) -> InactivePageTable {
+        self.active_table.switch(table)
+    }
+
+    // Gives back the frames of a user address space that is not active any
+    // more: its page tables and everything mapped from `start` to `end`.
+    pub fn free_address_space(&mut self,
+                              table: InactivePageTable,
+                              start: VirtualAddress,
+                              end: VirtualAddress) {
+        self.active_table.free(table,
+                               start,
+                               end,
+                               &mut self.temporary_page,
+                               fault::is_zero_frame,
+                               &mut self.frame_allocator)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -1,5 +1,7 @@
 use multiboot2::ElfSection;
 
+use elf::ProgramFlags;
+
 use mem::Frame;
 
 bitflags! {
@@ -13,10 +15,17 @@ bitflags! {
         const DIRTY =           1 << 6,
         const HUGE_PAGE =       1 << 7,
         const GLOBAL =          1 << 8,
+        // bits 9 to 11 are left to the OS: a read-only page shared until
+        // written to, see `mem::fault`
+        const COPY_ON_WRITE =   1 << 9,
         const NO_EXECUTE =      1 << 63,
     }
 }
 
+// With the page attribute table set up by `enable_write_combining`, a write
+// through entry selects write combining instead.
+pub const WRITE_COMBINING: EntryFlags = WRITE_THROUGH;
+
 impl EntryFlags {
     pub fn from_elf_section_flags(section: &ElfSection) -> EntryFlags {
         use multiboot2::{ELF_SECTION_ALLOCATED, ELF_SECTION_WRITABLE, ELF_SECTION_EXECUTABLE};
@@ -32,6 +41,18 @@ impl EntryFlags {
         }
         flags
     }
+
+    pub fn from_elf_program_flags(program_flags: ProgramFlags) -> EntryFlags {
+        use elf::{PF_W, PF_X};
+        let mut flags = PRESENT;
+        if program_flags.contains(PF_W) {
+            flags |= WRITABLE;
+        }
+        if !program_flags.contains(PF_X) {
+            flags |= NO_EXECUTE;
+        }
+        flags
+    }
 }
 
 pub struct Entry(u64);
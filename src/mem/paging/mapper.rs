@@ -68,7 +68,29 @@ impl Mapper {
             .or_else(huge_page)
     }
 
-    pub fn unmap<A>(&mut self, page: Page, allocator: &mut A)
+    // The flags `page` is mapped with, or None if it is not mapped.
+    pub fn flags(&self, page: Page) -> Option<EntryFlags> {
+        self.p4()
+            .next_table(page.p4_index())
+            .and_then(|p3| p3.next_table(page.p3_index()))
+            .and_then(|p2| p2.next_table(page.p2_index()))
+            .map(|p1| p1[page.p1_index()].flags())
+            .and_then(|flags| if flags.contains(PRESENT) { Some(flags) } else { None })
+    }
+
+    // The entry that maps `page`, for code that remaps one page over and over
+    // and must not allocate. The tables above it have to exist.
+    pub fn entry_address(&mut self, page: Page) -> *mut Entry {
+        let p1 = self.p4_mut()
+            .next_table_mut(page.p4_index())
+            .and_then(|p3| p3.next_table_mut(page.p3_index()))
+            .and_then(|p2| p2.next_table_mut(page.p2_index()))
+            .expect("no table maps the page");
+        &mut p1[page.p1_index()] as *mut Entry
+    }
+
+    // Returns the frame that was mapped, which the caller may free.
+    pub fn unmap<A>(&mut self, page: Page, allocator: &mut A) -> Frame
         where A: FrameAllocator
     {
         assert!(self.translate(page.start()).is_some());
@@ -77,13 +99,13 @@ impl Mapper {
             .and_then(|p3| p3.next_table_mut(page.p3_index()))
             .and_then(|p2| p2.next_table_mut(page.p2_index()))
             .expect("mapping code does not support huge pages");
-        let _ = p1[page.p1_index()].frame().unwrap();
+        let frame = p1[page.p1_index()].frame().unwrap();
         p1[page.p1_index()].set_unused();
         unsafe {
             ::x86::shared::tlb::flush(page.start());
         }
         // TODO: free p1, p2, p3 table if empty
-        // allocator.free(frame);
+        frame
     }
 
     pub fn map<A>(&mut self, page: Page, flags: EntryFlags, allocator: &mut A)
@@ -103,12 +125,81 @@ impl Mapper {
     pub fn map_to<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags, allocator: &mut A)
         where A: FrameAllocator
     {
+        let table_flags = flags & USER_ACCESSIBLE;
         let p4 = self.p4_mut();
-        let mut p3 = p4.next_table_create(page.p4_index(), allocator);
-        let mut p2 = p3.next_table_create(page.p3_index(), allocator);
-        let mut p1 = p2.next_table_create(page.p2_index(), allocator);
+        let mut p3 = p4.next_table_create(page.p4_index(), table_flags, allocator);
+        let mut p2 = p3.next_table_create(page.p3_index(), table_flags, allocator);
+        let mut p1 = p2.next_table_create(page.p2_index(), table_flags, allocator);
 
         assert!(p1[page.p1_index()].is_unused());
         p1[page.p1_index()].set(frame, flags | PRESENT);
     }
+
+    pub fn update_flags(&mut self, page: Page, flags: EntryFlags) {
+        let p1 = self.p4_mut()
+            .next_table_mut(page.p4_index())
+            .and_then(|p3| p3.next_table_mut(page.p3_index()))
+            .and_then(|p2| p2.next_table_mut(page.p2_index()))
+            .expect("mapping code does not support huge pages");
+        let frame = p1[page.p1_index()].frame().expect("page is not mapped");
+        p1[page.p1_index()].set(frame, flags | PRESENT);
+        unsafe {
+            ::x86::shared::tlb::flush(page.start());
+        }
+    }
+
+    // Unmaps everything from `start` to `end`, which must be whole top-level
+    // entries, and frees the frames mapped there along with the tables that
+    // mapped them. Frames that `shared` picks are mapped elsewhere as well and
+    // are left alone.
+    pub fn free_range<A, F>(&mut self,
+                            start: VirtualAddress,
+                            end: VirtualAddress,
+                            shared: F,
+                            allocator: &mut A)
+        where A: FrameAllocator,
+              F: Fn(&Frame) -> bool
+    {
+        let slot_size = ENTRY_COUNT * ENTRY_COUNT * ENTRY_COUNT * PAGE_SIZE;
+        assert!(start % slot_size == 0 && end % slot_size == 0);
+        let first = Page::containing(start).p4_index();
+        let last = Page::containing(end - 1).p4_index();
+        for i4 in first..last + 1 {
+            if let Some(p3) = self.p4_mut().next_table_mut(i4) {
+                for i3 in 0..ENTRY_COUNT {
+                    if let Some(p2) = p3.next_table_mut(i3) {
+                        for i2 in 0..ENTRY_COUNT {
+                            if let Some(p1) = p2.next_table_mut(i2) {
+                                for i1 in 0..ENTRY_COUNT {
+                                    if let Some(frame) = p1[i1].frame() {
+                                        if !shared(&frame) {
+                                            allocator.free(frame);
+                                        }
+                                    }
+                                    p1[i1].set_unused();
+                                }
+                            }
+                            free_table(&mut p2[i2], allocator);
+                        }
+                    }
+                    free_table(&mut p3[i3], allocator);
+                }
+            }
+            free_table(&mut self.p4_mut()[i4], allocator);
+        }
+        unsafe {
+            ::x86::shared::tlb::flush_all();
+        }
+    }
+}
+
+// Frees the table `entry` points to, whose own entries were freed already.
+fn free_table<A>(entry: &mut Entry, allocator: &mut A)
+    where A: FrameAllocator
+{
+    assert!(!entry.flags().contains(HUGE_PAGE), "mapping code does not support huge pages");
+    if let Some(frame) = entry.frame() {
+        allocator.free(frame);
+    }
+    entry.set_unused();
 }
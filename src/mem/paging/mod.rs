@@ -1,10 +1,11 @@
-use core::ops::{Deref, DerefMut};
+use core::ops::{Add, Deref, DerefMut};
 
 use multiboot2::BootInformation;
 
+use boot;
 use mem::{PAGE_SIZE, Frame, FrameAllocator};
 pub use self::entry::*;
-use self::tpage::TemporaryPage;
+pub use self::tpage::TemporaryPage;
 
 pub use self::mapper::Mapper;
 
@@ -56,6 +57,20 @@ pub fn remap_kernel<A>(allocator: &mut A, boot_info: &BootInformation) -> Active
         for frame in Frame::range_inclusive(multiboot_start, multiboot_end) {
             mapper.identity_map(frame, PRESENT, allocator);
         }
+
+        for module in boot::modules(boot_info) {
+            if module.end_address() <= module.start_address() {
+                continue;
+            }
+            let start = Frame::containing(module.start_address());
+            let end = Frame::containing(module.end_address() - 1);
+            for frame in Frame::range_inclusive(start, end) {
+                // the multiboot information may share a page with a module
+                if mapper.translate_page(Page::containing(frame.start())).is_none() {
+                    mapper.identity_map(frame, PRESENT | NO_EXECUTE, allocator);
+                }
+            }
+        }
     });
 
     let old_table = active_table.switch(new_table);
@@ -97,6 +112,24 @@ impl ActivePageTable {
         old_table
     }
 
+    // Frees `table`, which must not be active, together with everything it
+    // maps from `start` to `end`, see `Mapper::free_range`.
+    pub fn free<A, F>(&mut self,
+                      mut table: InactivePageTable,
+                      start: VirtualAddress,
+                      end: VirtualAddress,
+                      temporary_page: &mut TemporaryPage,
+                      shared: F,
+                      allocator: &mut A)
+        where A: FrameAllocator,
+              F: Fn(&Frame) -> bool
+    {
+        self.with(&mut table,
+                  temporary_page,
+                  |mapper| mapper.free_range(start, end, shared, allocator));
+        allocator.free(table.p4_frame);
+    }
+
     pub fn with<F>(&mut self,
                    table: &mut InactivePageTable,
                    temporary_page: &mut TemporaryPage,
@@ -140,6 +173,28 @@ impl InactivePageTable {
 
         InactivePageTable { p4_frame: frame }
     }
+
+    // A table for a user address space: the kernel's top-level entries are
+    // shared so the kernel stays mapped while the process runs.
+    pub fn new_user(frame: Frame,
+                    active_table: &mut ActivePageTable,
+                    temporary_page: &mut TemporaryPage)
+                    -> InactivePageTable {
+        {
+            let table = temporary_page.map_table_frame(frame.clone(), active_table);
+            table.zero();
+            for index in 0..(ENTRY_COUNT - 1) {
+                let entry = &active_table.p4()[index];
+                if let Some(p3_frame) = entry.frame() {
+                    table[index].set(p3_frame, entry.flags());
+                }
+            }
+            table[511].set(frame.clone(), PRESENT | WRITABLE)
+        }
+        temporary_page.unmap(active_table);
+
+        InactivePageTable { p4_frame: frame }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -181,6 +236,15 @@ impl Page {
     }
 }
 
+impl Add<usize> for Page {
+    type Output = Page;
+
+    fn add(self, rhs: usize) -> Page {
+        Page { number: self.number + rhs }
+    }
+}
+
+#[derive(Clone)]
 pub struct PageIter {
     start: Page,
     end: Page,
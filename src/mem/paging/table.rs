@@ -79,6 +79,7 @@ impl<L> Table<L>
 
     pub fn next_table_create<A>(&mut self,
                                 index: usize,
+                                flags: EntryFlags,
                                 allocator: &mut A)
                                 -> &mut Table<L::NextLevel>
         where A: FrameAllocator
@@ -86,8 +87,13 @@ impl<L> Table<L>
         if self.next_table(index).is_none() {
             assert!(!self.entries[index].flags().contains(HUGE_PAGE));
             let frame = allocator.alloc().expect("no frames available");
-            self.entries[index].set(frame, PRESENT | WRITABLE);
+            self.entries[index].set(frame, PRESENT | WRITABLE | flags);
             self.next_table_mut(index).unwrap().zero();
+        } else if !self.entries[index].flags().contains(flags) {
+            // a user page below a table created for kernel pages
+            let frame = self.entries[index].frame().unwrap();
+            let existing = self.entries[index].flags();
+            self.entries[index].set(frame, existing | flags);
         }
         self.next_table_mut(index).unwrap()
     }
@@ -0,0 +1,80 @@
+use mem::{PAGE_SIZE, FrameAllocator};
+use mem::paging::{self, Page, PageIter, ActivePageTable, VirtualAddress};
+
+pub struct StackAllocator {
+    range: PageIter,
+    start: VirtualAddress,
+    end: VirtualAddress,
+}
+
+impl StackAllocator {
+    pub fn new(range: PageIter) -> StackAllocator {
+        let (start, end) = {
+            let mut pages = range.clone();
+            let first = pages.next().expect("empty stack range");
+            let last = pages.last().unwrap_or(first);
+            (first.start(), last.start() + PAGE_SIZE)
+        };
+        StackAllocator {
+            range: range,
+            start: start,
+            end: end,
+        }
+    }
+
+    // Stacks are handed out bottom-up, each preceded by an unmapped guard page.
+    pub fn alloc_stack<A>(&mut self,
+                          active_table: &mut ActivePageTable,
+                          allocator: &mut A,
+                          size_in_pages: usize)
+                          -> Option<Stack>
+        where A: FrameAllocator
+    {
+        if size_in_pages == 0 {
+            return None;
+        }
+
+        let mut range = self.range.clone();
+        let guard_page = range.next();
+        let stack_start = range.next();
+        let stack_end = if size_in_pages == 1 {
+            stack_start
+        } else {
+            range.nth(size_in_pages - 2)
+        };
+
+        match (guard_page, stack_start, stack_end) {
+            (Some(_), Some(start), Some(end)) => {
+                self.range = range;
+                for page in Page::range_inclusive(start, end) {
+                    active_table.map(page, paging::WRITABLE, allocator);
+                }
+                Some(Stack {
+                    top: end.start() + PAGE_SIZE,
+                    bottom: start.start(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, address: VirtualAddress) -> bool {
+        address >= self.start && address < self.end
+    }
+}
+
+#[derive(Debug)]
+pub struct Stack {
+    top: VirtualAddress,
+    bottom: VirtualAddress,
+}
+
+impl Stack {
+    pub fn top(&self) -> VirtualAddress {
+        self.top
+    }
+
+    pub fn bottom(&self) -> VirtualAddress {
+        self.bottom
+    }
+}
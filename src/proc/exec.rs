@@ -0,0 +1,283 @@
+use core::mem::size_of;
+use core::{ptr, slice};
+
+use collections::vec::Vec;
+
+use elf::{self, Elf, ProgramHeader, PT_LOAD, PT_PHDR};
+use fs::{self, FileType};
+use mem::{self, PAGE_SIZE, MemoryController};
+use mem::fault::{Region, RegionKind};
+use mem::paging::{EntryFlags, InactivePageTable, Page, VirtualAddress};
+use mem::paging::{NO_EXECUTE, USER_ACCESSIBLE, WRITABLE};
+
+// User programs live in their own top-level page table slots, above the
+// kernel's identity mapped first 512GiB.
+pub const USER_START: VirtualAddress = 0x0000_0080_0000_0000;
+pub const USER_END: VirtualAddress = 0x0000_0800_0000_0000;
+
+// The stack starts out with USER_STACK_PAGES mapped and grows on faults to at
+// most USER_STACK_MAX_PAGES, above a guard page.
+pub const USER_STACK_TOP: VirtualAddress = USER_END;
+pub const USER_STACK_PAGES: usize = 16;
+pub const USER_STACK_MAX_PAGES: usize = 2048;
+
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+
+#[derive(Debug)]
+pub enum ExecError {
+    Fs(fs::Error),
+    NotAFile,
+    Elf(elf::Error),
+    NotInUserSpace,
+    OverlappingSegments,
+    ArgumentsTooLarge,
+    OutOfMemory,
+}
+
+impl From<fs::Error> for ExecError {
+    fn from(error: fs::Error) -> ExecError {
+        ExecError::Fs(error)
+    }
+}
+
+impl From<elf::Error> for ExecError {
+    fn from(error: elf::Error) -> ExecError {
+        ExecError::Elf(error)
+    }
+}
+
+pub struct Image {
+    pub entry: VirtualAddress,
+    pub stack_pointer: VirtualAddress,
+    pub address_space: InactivePageTable,
+    // the memory mapped on faults, see `mem::fault`
+    pub regions: Vec<Region>,
+}
+
+pub fn load(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<Image, ExecError> {
+    let elf = Elf::parse(data)?;
+    check_segments(&elf)?;
+
+    let stack_size = initial_stack_size(argv, envp);
+    if stack_size > USER_STACK_PAGES * PAGE_SIZE {
+        return Err(ExecError::ArgumentsTooLarge);
+    }
+
+    // give caches a chance to make room first, mapping panics when out of frames
+    let pages: usize = elf.program_headers()
+        .iter()
+        .filter(|ph| is_loadable(ph) && ph.filesz > 0)
+        .map(|ph| (file_end(ph) - 1) / PAGE_SIZE + 1 - ph.start_address() / PAGE_SIZE)
+        .sum();
+    if !mem::reclaim(pages + USER_STACK_PAGES) {
+        return Err(ExecError::OutOfMemory);
+    }
+
+    mem::with_controller(|mc| {
+        // build the new address space while it is active, so that segments
+        // can be copied to their final virtual addresses
+        let address_space = mc.new_address_space();
+        let kernel_space = mc.switch(address_space);
+
+        for ph in elf.program_headers().iter().filter(|ph| is_loadable(ph)) {
+            load_segment(mc, &elf, ph);
+        }
+        let stack_pointer = setup_stack(mc, &elf, argv, envp);
+
+        let address_space = mc.switch(kernel_space);
+        Ok(Image {
+            entry: elf.entry(),
+            stack_pointer: stack_pointer,
+            address_space: address_space,
+            regions: regions(&elf),
+        })
+    })
+}
+
+pub fn load_file(path: &str, argv: &[&str], envp: &[&str]) -> Result<Image, ExecError> {
+    let inode = fs::resolve(path)?;
+    let metadata = inode.metadata();
+    if metadata.kind != FileType::File {
+        return Err(ExecError::NotAFile);
+    }
+
+    // read into u64s to get the alignment the ELF structures need
+    let size = metadata.size as usize;
+    let mut words: Vec<u64> = vec![0; (size + 7) / 8];
+    let data = unsafe { slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, size) };
+    let mut read = 0;
+    while read < size {
+        match inode.read_at(read as u64, &mut data[read..])? {
+            0 => break,
+            count => read += count,
+        }
+    }
+    load(&data[..read], argv, envp)
+}
+
+fn is_loadable(ph: &ProgramHeader) -> bool {
+    ph.typ == PT_LOAD && ph.memsz > 0
+}
+
+fn check_segments(elf: &Elf) -> Result<(), ExecError> {
+    let user_space = USER_START..(USER_STACK_TOP - (USER_STACK_MAX_PAGES + 1) * PAGE_SIZE);
+    if !user_space.contains(elf.entry()) {
+        return Err(ExecError::NotInUserSpace);
+    }
+
+    let segments = elf.program_headers();
+    for (i, ph) in segments.iter().enumerate().filter(|&(_, ph)| is_loadable(ph)) {
+        if ph.start_address() < user_space.start || ph.end_address() > user_space.end {
+            return Err(ExecError::NotInUserSpace);
+        }
+
+        let first = Page::containing(ph.start_address());
+        let last = Page::containing(ph.end_address() - 1);
+        for other in segments[i + 1..].iter().filter(|ph| is_loadable(ph)) {
+            let other_first = Page::containing(other.start_address());
+            let other_last = Page::containing(other.end_address() - 1);
+            if first <= other_last && other_first <= last {
+                return Err(ExecError::OverlappingSegments);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Maps the pages with data from the file. The rest of the segment, its bss,
+// is mapped on faults.
+fn load_segment(mc: &mut MemoryController, elf: &Elf, ph: &ProgramHeader) {
+    if ph.filesz == 0 {
+        return;
+    }
+    let flags = segment_flags(ph);
+    let start_page = Page::containing(ph.start_address());
+    let end_page = Page::containing(file_end(ph) - 1);
+
+    // map writable first: the kernel has to fill in read-only segments too.
+    // the tables above the pages take their user bit from this first mapping
+    for page in Page::range_inclusive(start_page, end_page) {
+        mc.map(page, USER_ACCESSIBLE | WRITABLE | NO_EXECUTE);
+    }
+
+    let data = elf.segment_data(ph);
+    unsafe {
+        let length = end_page.start() + PAGE_SIZE - start_page.start();
+        ptr::write_bytes(start_page.start() as *mut u8, 0, length);
+        ptr::copy_nonoverlapping(data.as_ptr(), ph.start_address() as *mut u8, data.len());
+    }
+
+    for page in Page::range_inclusive(start_page, end_page) {
+        mc.update_flags(page, flags);
+    }
+}
+
+fn segment_flags(ph: &ProgramHeader) -> EntryFlags {
+    EntryFlags::from_elf_program_flags(ph.flags()) | USER_ACCESSIBLE
+}
+
+fn file_end(ph: &ProgramHeader) -> VirtualAddress {
+    ph.start_address() + ph.filesz as usize
+}
+
+fn regions(elf: &Elf) -> Vec<Region> {
+    let mut regions: Vec<Region> = elf.program_headers()
+        .iter()
+        .filter(|ph| is_loadable(ph))
+        .map(|ph| {
+            Region {
+                start: Page::containing(ph.start_address()).start(),
+                end: Page::containing(ph.end_address() - 1).start() + PAGE_SIZE,
+                flags: segment_flags(ph),
+                kind: RegionKind::Anonymous,
+            }
+        })
+        .collect();
+    regions.push(Region {
+        start: USER_STACK_TOP - USER_STACK_MAX_PAGES * PAGE_SIZE,
+        end: USER_STACK_TOP,
+        flags: USER_ACCESSIBLE | WRITABLE | NO_EXECUTE,
+        kind: RegionKind::Stack,
+    });
+    regions
+}
+
+fn initial_stack_size(argv: &[&str], envp: &[&str]) -> usize {
+    let strings: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+    let words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2 * 6;
+    strings + 16 + words * size_of::<u64>() + 16
+}
+
+// Lays out the stack as the System V ABI expects it at process entry:
+//
+//   argc, argv[0..argc], NULL, envp[..], NULL, auxv pairs, AT_NULL, strings
+//
+// with the stack pointer pointing at argc, 16 byte aligned.
+fn setup_stack(mc: &mut MemoryController, elf: &Elf, argv: &[&str], envp: &[&str]) -> VirtualAddress {
+    let flags = USER_ACCESSIBLE | WRITABLE | NO_EXECUTE;
+    let stack_end = Page::containing(USER_STACK_TOP - 1);
+    let stack_start = Page::containing(USER_STACK_TOP - USER_STACK_PAGES * PAGE_SIZE);
+    for page in Page::range_inclusive(stack_start, stack_end) {
+        mc.map(page, flags);
+    }
+
+    let mut top = USER_STACK_TOP;
+    let (argv_ptrs, envp_ptrs) = {
+        let mut push_str = |s: &str| -> u64 {
+            top -= s.len() + 1;
+            unsafe {
+                ptr::copy_nonoverlapping(s.as_ptr(), top as *mut u8, s.len());
+                *((top + s.len()) as *mut u8) = 0;
+            }
+            top as u64
+        };
+        let argv_ptrs: Vec<u64> = argv.iter().map(|s| push_str(s)).collect();
+        let envp_ptrs: Vec<u64> = envp.iter().map(|s| push_str(s)).collect();
+        (argv_ptrs, envp_ptrs)
+    };
+
+    let header = elf.header();
+    let mut auxv = vec![(AT_PHENT, header.phentsize as u64),
+                        (AT_PHNUM, header.phnum as u64),
+                        (AT_PAGESZ, PAGE_SIZE as u64),
+                        (AT_ENTRY, header.entry)];
+    if let Some(phdr) = program_headers_address(elf) {
+        auxv.push((AT_PHDR, phdr));
+    }
+    auxv.push((AT_NULL, 0));
+
+    let mut words = Vec::new();
+    words.push(argv_ptrs.len() as u64);
+    words.extend(argv_ptrs);
+    words.push(0);
+    words.extend(envp_ptrs);
+    words.push(0);
+    for &(key, value) in &auxv {
+        words.push(key);
+        words.push(value);
+    }
+
+    let stack_pointer = (top - words.len() * size_of::<u64>()) & !0xf;
+    unsafe {
+        ptr::copy_nonoverlapping(words.as_ptr(), stack_pointer as *mut u64, words.len());
+    }
+    stack_pointer
+}
+
+fn program_headers_address(elf: &Elf) -> Option<u64> {
+    let phoff = elf.header().phoff;
+    let segments = elf.program_headers();
+    segments.iter()
+        .find(|ph| ph.typ == PT_PHDR)
+        .map(|ph| ph.vaddr)
+        .or_else(|| {
+            segments.iter()
+                .find(|ph| ph.typ == PT_LOAD && ph.offset <= phoff && phoff < ph.offset + ph.filesz)
+                .map(|ph| ph.vaddr + (phoff - ph.offset))
+        })
+}
@@ -0,0 +1,175 @@
+pub mod exec;
+
+pub use self::exec::{load, load_file, ExecError, Image};
+
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use spin::{Mutex, MutexGuard};
+
+use fs::FileTable;
+use int::Context;
+use int::context::INTERRUPT;
+use int::gdt::{KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR, USER_CODE_SELECTOR, USER_DATA_SELECTOR};
+use mem;
+use mem::fault;
+use mem::paging::{InactivePageTable, VirtualAddress};
+
+pub type Pid = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Running,
+    Exited,
+}
+
+pub struct Process {
+    pid: Pid,
+    name: String,
+    state: Mutex<State>,
+    files: Mutex<FileTable>,
+}
+
+impl Process {
+    fn new(pid: Pid, name: &str, files: FileTable) -> Process {
+        Process {
+            pid: pid,
+            name: name.to_string(),
+            state: Mutex::new(State::Ready),
+            files: Mutex::new(files),
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> State {
+        *self.state.lock()
+    }
+
+    pub fn set_state(&self, state: State) {
+        *self.state.lock() = state;
+    }
+
+    pub fn files(&self) -> MutexGuard<FileTable> {
+        self.files.lock()
+    }
+}
+
+static NEXT_PID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// The kernel's address space while a user program runs in its own, and the
+// stack the kernel was on when it started the program. Both are taken back
+// when the program ends.
+static KERNEL_SPACE: Mutex<Option<InactivePageTable>> = Mutex::new(None);
+static KERNEL_STACK: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref PROCESSES: Mutex<Vec<Arc<Process>>> = {
+        // pid 0 stands for the kernel itself, until it starts a user program
+        let kernel = Process::new(NEXT_PID.fetch_add(1, Ordering::Relaxed), "kernel", FileTable::new());
+        kernel.set_state(State::Running);
+        Mutex::new(vec![Arc::new(kernel)])
+    };
+    static ref CURRENT: Mutex<Arc<Process>> = Mutex::new(PROCESSES.lock()[0].clone());
+}
+
+pub fn current() -> Arc<Process> {
+    CURRENT.lock().clone()
+}
+
+pub fn processes() -> Vec<Arc<Process>> {
+    PROCESSES.lock().clone()
+}
+
+// Creates a process that inherits the open files of the current one.
+pub fn spawn(name: &str) -> Arc<Process> {
+    let files = current().files().clone();
+    let process = Arc::new(Process::new(NEXT_PID.fetch_add(1, Ordering::Relaxed), name, files));
+    PROCESSES.lock().push(process.clone());
+    process
+}
+
+pub fn run(process: Arc<Process>, image: Image) -> ! {
+    current().set_state(State::Ready);
+    process.set_state(State::Running);
+    *CURRENT.lock() = process;
+    // nothing below this frame is used again, as it does not return
+    let stack_pointer: usize;
+    unsafe {
+        asm!("mov $0, rsp" : "=r"(stack_pointer) ::: "intel");
+    }
+    KERNEL_STACK.store(stack_pointer, Ordering::Relaxed);
+    image.start()
+}
+
+// Ends the current process after a fault it cannot go on from: switches back
+// to the kernel's address space, frees the process's memory and makes the
+// exception `context` return to the kernel instead. There is no other process
+// to switch to, so the kernel idles there.
+pub fn kill_current(context: &mut Context) {
+    current().set_state(State::Exited);
+    fault::clear_regions();
+    let kernel_space = KERNEL_SPACE.lock().take().expect("no process to kill");
+    mem::with_controller(|mc| {
+        let user_space = mc.switch(kernel_space);
+        mc.free_address_space(user_space, exec::USER_START, exec::USER_END);
+    });
+    let kernel = PROCESSES.lock()[0].clone();
+    kernel.set_state(State::Running);
+    *CURRENT.lock() = kernel;
+
+    // aligned as if `idle` had been called
+    let stack_pointer = (KERNEL_STACK.load(Ordering::Relaxed) & !0xf) - 8;
+    context.rip = idle as u64;
+    context.cs = KERNEL_CODE_SELECTOR as u64;
+    // bit 1 of the flags is always set
+    context.rflags = INTERRUPT.bits() | 1 << 1;
+    context.rsp = stack_pointer as u64;
+    context.ss = KERNEL_DATA_SELECTOR as u64;
+}
+
+extern "C" fn idle() -> ! {
+    loop {
+        unsafe {
+            asm!("hlt" :::: "volatile");
+        }
+    }
+}
+
+impl Image {
+    pub fn start(self) -> ! {
+        let Image { entry, stack_pointer, address_space, regions } = self;
+        fault::set_regions(regions);
+        let kernel_space = mem::with_controller(|mc| mc.switch(address_space));
+        *KERNEL_SPACE.lock() = Some(kernel_space);
+        unsafe { enter_user_mode(entry, stack_pointer) }
+    }
+}
+
+unsafe fn enter_user_mode(entry: VirtualAddress, stack_pointer: VirtualAddress) -> ! {
+    // interrupts on, as they are taken on the privilege stack in the TSS; bit
+    // 1 is always set
+    let flags: u64 = 1 << 9 | 1 << 1;
+    asm!("
+         mov ds, $0
+         mov es, $0
+         push $1
+         push $2
+         push $3
+         push $4
+         push $5
+         iretq"
+         :: "r"(USER_DATA_SELECTOR), "r"(USER_DATA_SELECTOR as u64), "r"(stack_pointer),
+            "r"(flags), "r"(USER_CODE_SELECTOR as u64), "r"(entry)
+         : "memory" : "intel", "volatile");
+    ::core::intrinsics::unreachable();
+}
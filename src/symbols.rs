@@ -0,0 +1,208 @@
+// The kernel's own symbols, for naming the functions in backtraces. The tables
+// are mapped once at boot and only read afterwards, so the emergency console
+// can look names up without taking a lock.
+
+use core::fmt;
+use core::mem::size_of;
+use core::slice;
+use core::str;
+
+use multiboot2::BootInformation;
+
+use boot;
+use elf::{SectionHeader, Symbol};
+use mem::{self, PAGE_SIZE};
+use mem::paging::{self, Page, VirtualAddress};
+
+// mapped at 448 GiB, above the framebuffer
+const SYMBOLS_AREA_START: VirtualAddress = 0o000_700_000_000_0000;
+
+struct Table {
+    symbols: &'static [Symbol],
+    names: &'static [u8],
+}
+
+// Set once by `init`.
+static mut TABLE: Option<Table> = None;
+
+impl Table {
+    fn name(&self, offset: u32) -> &'static str {
+        let names = self.names;
+        let start = offset as usize;
+        if start >= names.len() {
+            return "?";
+        }
+        let len = names[start..].iter().position(|&byte| byte == 0).unwrap_or(names.len() - start);
+        str::from_utf8(&names[start..start + len]).unwrap_or("?")
+    }
+}
+
+pub fn init(boot_info: &BootInformation) {
+    assert_has_not_been_called!();
+    let (symbols, names) = match boot::kernel_symbols(boot_info) {
+        Some((symbols, names)) if symbols.size != 0 && names.size != 0 => (symbols, names),
+        _ => {
+            warn!("symbols: no symbol table, backtraces show addresses only");
+            return;
+        }
+    };
+    let (symbols_address, names_address) = mem::with_controller(|mc| {
+        let first = Page::containing(SYMBOLS_AREA_START);
+        let symbols_address = mc.map_physical(first,
+                                              symbols.start_address(),
+                                              symbols.size as usize,
+                                              paging::NO_EXECUTE);
+        let names_address = mc.map_physical(first + pages(symbols),
+                                            names.start_address(),
+                                            names.size as usize,
+                                            paging::NO_EXECUTE);
+        (symbols_address, names_address)
+    });
+    unsafe {
+        TABLE = Some(Table {
+            symbols: slice::from_raw_parts(symbols_address as *const Symbol,
+                                           symbols.size as usize / size_of::<Symbol>()),
+            names: slice::from_raw_parts(names_address as *const u8, names.size as usize),
+        });
+    }
+}
+
+// The pages `map_physical` takes for a section.
+fn pages(section: &SectionHeader) -> usize {
+    (section.end_address() - 1) / PAGE_SIZE - section.start_address() / PAGE_SIZE + 1
+}
+
+// The function `address` is in and how far into it that is.
+pub fn resolve(address: usize) -> Option<(Name, usize)> {
+    let table = match unsafe { TABLE.as_ref() } {
+        Some(table) => table,
+        None => return None,
+    };
+    let mut found: Option<&Symbol> = None;
+    for symbol in table.symbols.iter().filter(|symbol| symbol.is_function()) {
+        let start = symbol.value as usize;
+        // symbols from assembly have no size, and cover up to the next one
+        let covers = start <= address &&
+                     (symbol.size == 0 || address < start + symbol.size as usize);
+        if covers && found.map_or(true, |found| found.value < symbol.value) {
+            found = Some(symbol);
+        }
+    }
+    found.map(|symbol| (Name(table.name(symbol.name)), address - symbol.value as usize))
+}
+
+// A symbol name, shown demangled when it is a mangled Rust path such as
+// `_ZN5mezzo4main17h0123456789abcdefE`.
+pub struct Name(&'static str);
+
+impl Name {
+    // The parts of the mangled path, or None if the name is not one.
+    fn parts(&self) -> Option<Parts> {
+        let name = self.0;
+        if name.starts_with("_ZN") && name.ends_with('E') {
+            let parts = Parts { rest: &name[3..name.len() - 1] };
+            // check the whole name first, so a bad one is shown as it is
+            if parts.clone().all(|part| part.is_some()) {
+                return Some(parts);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = match self.parts() {
+            Some(parts) => parts,
+            None => return f.write_str(self.0),
+        };
+        let mut parts = parts.filter_map(|part| part).peekable();
+        let mut first = true;
+        while let Some(part) = parts.next() {
+            // the last part is a hash, which says nothing to a reader
+            if parts.peek().is_none() && is_hash(part) {
+                break;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+            first = false;
+            write_unescaped(f, part)?;
+        }
+        Ok(())
+    }
+}
+
+// The length-prefixed parts of a mangled path, None for one that is not.
+#[derive(Clone)]
+struct Parts {
+    rest: &'static str,
+}
+
+impl Iterator for Parts {
+    type Item = Option<&'static str>;
+
+    fn next(&mut self) -> Option<Option<&'static str>> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let digits = self.rest.bytes().take_while(|&byte| byte >= b'0' && byte <= b'9').count();
+        let len = match self.rest[..digits].parse::<usize>() {
+            Ok(len) if self.rest.is_char_boundary(digits + len) => len,
+            _ => {
+                self.rest = "";
+                return Some(None);
+            }
+        };
+        let part = &self.rest[digits..digits + len];
+        self.rest = &self.rest[digits + len..];
+        Some(Some(part))
+    }
+}
+
+fn is_hash(part: &str) -> bool {
+    part.len() == 17 && part.starts_with('h') &&
+    part[1..].bytes().all(|byte| (byte >= b'0' && byte <= b'9') || (byte >= b'a' && byte <= b'f'))
+}
+
+// Writes a part of a path with the escapes rustc uses for characters that
+// symbols cannot hold put back.
+fn write_unescaped(f: &mut fmt::Formatter, part: &str) -> fmt::Result {
+    // a leading underscore keeps a part from starting with an escape
+    let mut rest = if part.starts_with("_$") { &part[1..] } else { part };
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            f.write_str("::")?;
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('$') {
+            if let Some(end) = rest[1..].find('$') {
+                let escape = &rest[1..end + 1];
+                let unescaped = match escape {
+                    "SP" => Some('@'),
+                    "BP" => Some('*'),
+                    "RF" => Some('&'),
+                    "LT" => Some('<'),
+                    "GT" => Some('>'),
+                    "LP" => Some('('),
+                    "RP" => Some(')'),
+                    "C" => Some(','),
+                    _ if escape.starts_with('u') => {
+                        u8::from_str_radix(&escape[1..], 16).ok().map(|byte| byte as char)
+                    }
+                    _ => None,
+                };
+                if let Some(char) = unescaped {
+                    write!(f, "{}", char)?;
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+            }
+        }
+        let char = rest.chars().next().unwrap();
+        write!(f, "{}", char)?;
+        rest = &rest[char.len_utf8()..];
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// Time since boot, counted with the TSC and calibrated against the PIT. The
+// PIT also raises a periodic tick, so that code halting until some condition
+// holds wakes up regularly to check it.
+
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+use x86::shared::io::{inb, outb};
+
+use int;
+
+const PIT_FREQUENCY: usize = 1_193_182;
+const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const SPEAKER_CONTROL: u16 = 0x61;
+
+const CALIBRATION_MS: usize = 10;
+
+pub const TICK_HZ: usize = 100;
+pub const TIMER_IRQ: u8 = 0;
+
+static TSC_PER_MS: AtomicUsize = ATOMIC_USIZE_INIT;
+static BOOT_TSC: AtomicUsize = ATOMIC_USIZE_INIT;
+static TICKS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdtsc" : "={eax}"(low), "={edx}"(high) ::: "volatile");
+    }
+    (high as u64) << 32 | low as u64
+}
+
+pub fn init() {
+    assert_has_not_been_called!();
+    BOOT_TSC.store(rdtsc() as usize, Ordering::Relaxed);
+
+    // run PIT channel 2 as a one-shot with the speaker off, and count TSC
+    // ticks until its output goes high
+    let count = PIT_FREQUENCY * CALIBRATION_MS / 1000;
+    let ticks = unsafe {
+        let control = inb(SPEAKER_CONTROL) & !0x02;
+        outb(SPEAKER_CONTROL, control & !0x01);
+        outb(PIT_COMMAND, 0b1011_0000); // channel 2, lobyte/hibyte, mode 0
+        outb(PIT_CHANNEL_2, count as u8);
+        outb(PIT_CHANNEL_2, (count >> 8) as u8);
+        outb(SPEAKER_CONTROL, control | 0x01);
+
+        let start = rdtsc();
+        while inb(SPEAKER_CONTROL) & 0x20 == 0 {}
+        rdtsc() - start
+    };
+    TSC_PER_MS.store(ticks as usize / CALIBRATION_MS, Ordering::Relaxed);
+
+    let divisor = PIT_FREQUENCY / TICK_HZ;
+    unsafe {
+        outb(PIT_COMMAND, 0b0011_0100); // channel 0, lobyte/hibyte, rate generator
+        outb(PIT_CHANNEL_0, divisor as u8);
+        outb(PIT_CHANNEL_0, (divisor >> 8) as u8);
+    }
+    int::register_irq(TIMER_IRQ, Arc::new(|| {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }));
+}
+
+pub fn ticks() -> usize {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn tsc_frequency_khz() -> usize {
+    TSC_PER_MS.load(Ordering::Relaxed)
+}
+
+pub fn uptime_ms() -> u64 {
+    let per_ms = TSC_PER_MS.load(Ordering::Relaxed) as u64;
+    if per_ms == 0 {
+        return 0;
+    }
+    (rdtsc() - BOOT_TSC.load(Ordering::Relaxed) as u64) / per_ms
+}
+
+// Microseconds since boot, for timestamps finer than uptime_ms.
+pub fn uptime_us() -> u64 {
+    let per_ms = TSC_PER_MS.load(Ordering::Relaxed) as u64;
+    if per_ms == 0 {
+        return 0;
+    }
+    let ticks = rdtsc() - BOOT_TSC.load(Ordering::Relaxed) as u64;
+    ticks / per_ms * 1000 + ticks % per_ms * 1000 / per_ms
+}
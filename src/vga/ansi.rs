@@ -0,0 +1,139 @@
+// A parser for the VT100/ANSI escape sequences the console understands. It
+// only splits the byte stream up; the Writer decides what each piece does.
+
+const ESCAPE: u8 = 0x1b;
+const MAX_PARAMS: usize = 8;
+
+pub enum Action {
+    // nothing to do yet, the byte was part of a sequence
+    None,
+    Print(u8),
+    // a C0 control character such as `\n` or backspace
+    Execute(u8),
+    Csi(Csi),
+}
+
+// A control sequence: ESC [, optionally `?`, numeric parameters separated
+// by `;`, and a final command byte.
+#[derive(Clone, Copy)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    count: usize,
+    pub private: bool,
+    pub command: u8,
+}
+
+impl Csi {
+    const fn new() -> Csi {
+        Csi {
+            params: [0; MAX_PARAMS],
+            count: 0,
+            private: false,
+            command: 0,
+        }
+    }
+
+    // Parameter `index`, with `default` for one that is missing or zero.
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&param) if param != 0 => param,
+            _ => default,
+        }
+    }
+
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.count]
+    }
+}
+
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+pub struct Parser {
+    state: State,
+    csi: Csi,
+}
+
+impl Parser {
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            csi: Csi::new(),
+        }
+    }
+
+    pub fn advance(&mut self, byte: u8) -> Action {
+        match self.state {
+            State::Ground => {
+                match byte {
+                    ESCAPE => {
+                        self.state = State::Escape;
+                        Action::None
+                    }
+                    0x00...0x1f | 0x7f => Action::Execute(byte),
+                    _ => Action::Print(byte),
+                }
+            }
+            State::Escape => {
+                match byte {
+                    b'[' => {
+                        self.csi = Csi::new();
+                        self.state = State::Csi;
+                    }
+                    ESCAPE => {}
+                    // other escapes are not supported and dropped
+                    _ => self.state = State::Ground,
+                }
+                Action::None
+            }
+            State::Csi => self.advance_csi(byte),
+        }
+    }
+
+    fn advance_csi(&mut self, byte: u8) -> Action {
+        match byte {
+            b'0'...b'9' => {
+                if self.csi.count == 0 {
+                    self.csi.count = 1;
+                }
+                let param = &mut self.csi.params[self.csi.count - 1];
+                *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                Action::None
+            }
+            b';' => {
+                // an empty first parameter still counts as one
+                if self.csi.count == 0 {
+                    self.csi.count = 1;
+                }
+                if self.csi.count < MAX_PARAMS {
+                    self.csi.count += 1;
+                }
+                Action::None
+            }
+            b'?' => {
+                self.csi.private = true;
+                Action::None
+            }
+            // intermediate bytes are accepted and ignored
+            0x20...0x2f => Action::None,
+            0x40...0x7e => {
+                self.state = State::Ground;
+                self.csi.command = byte;
+                Action::Csi(self.csi)
+            }
+            ESCAPE => {
+                self.state = State::Escape;
+                Action::None
+            }
+            // control characters take effect in the middle of a sequence
+            0x00...0x1f => Action::Execute(byte),
+            _ => {
+                self.state = State::Ground;
+                Action::None
+            }
+        }
+    }
+}
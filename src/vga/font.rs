@@ -0,0 +1,253 @@
+// The built-in console font: 8x8 glyphs for printable ASCII and the upper
+// half of code page 437, drawn with each row doubled to fill an 8x16 cell.
+// Bit 0 of a row is its leftmost pixel.
+
+pub const WIDTH: usize = 8;
+pub const HEIGHT: usize = 16;
+
+const FIRST: u8 = 0x20;
+const LAST: u8 = 0x7e;
+const UPPER_FIRST: u8 = 0x80;
+
+// shown for bytes the font has no glyph for
+const MISSING: [u8; 8] = [0x7e, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7e, 0x00];
+
+const GLYPHS: [[u8; 8]; (LAST - FIRST + 1) as usize] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7f, 0x36, 0x7f, 0x36, 0x36, 0x00], // '#'
+    [0x0c, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x0c, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0c, 0x66, 0x63, 0x00], // '%'
+    [0x1c, 0x36, 0x1c, 0x6e, 0x3b, 0x33, 0x6e, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x18, 0x0c, 0x06, 0x06, 0x06, 0x0c, 0x18, 0x00], // '('
+    [0x06, 0x0c, 0x18, 0x18, 0x18, 0x0c, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0c, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3e, 0x63, 0x73, 0x7b, 0x6f, 0x67, 0x3e, 0x00], // '0'
+    [0x0c, 0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x3f, 0x00], // '1'
+    [0x1e, 0x33, 0x30, 0x1c, 0x06, 0x33, 0x3f, 0x00], // '2'
+    [0x1e, 0x33, 0x30, 0x1c, 0x30, 0x33, 0x1e, 0x00], // '3'
+    [0x38, 0x3c, 0x36, 0x33, 0x7f, 0x30, 0x78, 0x00], // '4'
+    [0x3f, 0x03, 0x1f, 0x30, 0x30, 0x33, 0x1e, 0x00], // '5'
+    [0x1c, 0x06, 0x03, 0x1f, 0x33, 0x33, 0x1e, 0x00], // '6'
+    [0x3f, 0x33, 0x30, 0x18, 0x0c, 0x0c, 0x0c, 0x00], // '7'
+    [0x1e, 0x33, 0x33, 0x1e, 0x33, 0x33, 0x1e, 0x00], // '8'
+    [0x1e, 0x33, 0x33, 0x3e, 0x30, 0x18, 0x0e, 0x00], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ';'
+    [0x18, 0x0c, 0x06, 0x03, 0x06, 0x0c, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3f, 0x00, 0x00, 0x3f, 0x00, 0x00], // '='
+    [0x06, 0x0c, 0x18, 0x30, 0x18, 0x0c, 0x06, 0x00], // '>'
+    [0x1e, 0x33, 0x30, 0x18, 0x0c, 0x00, 0x0c, 0x00], // '?'
+    [0x3e, 0x63, 0x7b, 0x7b, 0x7b, 0x03, 0x1e, 0x00], // '@'
+    [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00], // 'A'
+    [0x3f, 0x66, 0x66, 0x3e, 0x66, 0x66, 0x3f, 0x00], // 'B'
+    [0x3c, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3c, 0x00], // 'C'
+    [0x1f, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1f, 0x00], // 'D'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x46, 0x7f, 0x00], // 'E'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x06, 0x0f, 0x00], // 'F'
+    [0x3c, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7c, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1e, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0f, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7f, 0x00], // 'L'
+    [0x63, 0x77, 0x7f, 0x7f, 0x6b, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1c, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1c, 0x00], // 'O'
+    [0x3f, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x0f, 0x00], // 'P'
+    [0x1e, 0x33, 0x33, 0x33, 0x3b, 0x1e, 0x38, 0x00], // 'Q'
+    [0x3f, 0x66, 0x66, 0x3e, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1e, 0x33, 0x07, 0x0e, 0x38, 0x33, 0x1e, 0x00], // 'S'
+    [0x3f, 0x2d, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6b, 0x7f, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1c, 0x1c, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1e, 0x0c, 0x0c, 0x1e, 0x00], // 'Y'
+    [0x7f, 0x63, 0x31, 0x18, 0x4c, 0x66, 0x7f, 0x00], // 'Z'
+    [0x1e, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1e, 0x00], // '['
+    [0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x40, 0x00], // '\\'
+    [0x1e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1e, 0x00], // ']'
+    [0x08, 0x1c, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff], // '_'
+    [0x0c, 0x0c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x3b, 0x00], // 'b'
+    [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6e, 0x00], // 'd'
+    [0x00, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // 'e'
+    [0x1c, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0f, 0x00], // 'f'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'g'
+    [0x07, 0x06, 0x36, 0x6e, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0c, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1e, 0x36, 0x67, 0x00], // 'k'
+    [0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7f, 0x7f, 0x6b, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // 'o'
+    [0x00, 0x00, 0x3b, 0x66, 0x66, 0x3e, 0x06, 0x0f], // 'p'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3b, 0x6e, 0x66, 0x06, 0x0f, 0x00], // 'r'
+    [0x00, 0x00, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x00], // 's'
+    [0x08, 0x0c, 0x3e, 0x0c, 0x0c, 0x2c, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6b, 0x7f, 0x7f, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1c, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'y'
+    [0x00, 0x00, 0x3f, 0x19, 0x0c, 0x26, 0x3f, 0x00], // 'z'
+    [0x38, 0x0c, 0x0c, 0x07, 0x0c, 0x0c, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0c, 0x0c, 0x38, 0x0c, 0x0c, 0x07, 0x00], // '}'
+    [0x6e, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+// 0x80 to 0xff, as in code page 437
+const UPPER: [[u8; 8]; 128] = [
+    [0x3c, 0x66, 0x03, 0x03, 0x66, 0x3c, 0x18, 0x06], // Ç
+    [0x33, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // ü
+    [0x38, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // é
+    [0x0c, 0x33, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // â
+    [0x33, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // ä
+    [0x07, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // à
+    [0x0c, 0x0c, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // å
+    [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x0c], // ç
+    [0x0c, 0x33, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // ê
+    [0x33, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // ë
+    [0x07, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // è
+    [0x33, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // ï
+    [0x0c, 0x33, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // î
+    [0x07, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // ì
+    [0x33, 0x00, 0x0c, 0x1e, 0x33, 0x3f, 0x33, 0x33], // Ä
+    [0x0c, 0x00, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33], // Å
+    [0x38, 0x00, 0x7f, 0x46, 0x16, 0x16, 0x46, 0x7f], // É
+    [0x00, 0x00, 0x6e, 0x98, 0xfe, 0x1b, 0xee, 0x00], // æ
+    [0x7c, 0x36, 0x33, 0x7f, 0x33, 0x33, 0x73, 0x00], // Æ
+    [0x0c, 0x33, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // ô
+    [0x33, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // ö
+    [0x07, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // ò
+    [0x0c, 0x33, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // û
+    [0x07, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // ù
+    [0x33, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f], // ÿ
+    [0x33, 0x00, 0x1c, 0x36, 0x63, 0x63, 0x36, 0x1c], // Ö
+    [0x33, 0x00, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f], // Ü
+    [0x18, 0x18, 0x7e, 0x03, 0x03, 0x7e, 0x18, 0x18], // ¢
+    [0x1c, 0x36, 0x26, 0x0f, 0x06, 0x63, 0x3f, 0x00], // £
+    [0x33, 0x33, 0x1e, 0x3f, 0x0c, 0x3f, 0x0c, 0x00], // ¥
+    [0x0f, 0x1b, 0x5b, 0xef, 0x23, 0x23, 0xc3, 0x00], // ₧
+    [0x70, 0xd8, 0x18, 0x7e, 0x18, 0x18, 0x1b, 0x0e], // ƒ
+    [0x38, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // á
+    [0x38, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // í
+    [0x38, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // ó
+    [0x38, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // ú
+    [0x6e, 0x3b, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00], // ñ
+    [0x6e, 0x3b, 0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63], // Ñ
+    [0x3c, 0x36, 0x36, 0x7c, 0x00, 0x7e, 0x00, 0x00], // ª
+    [0x1c, 0x36, 0x36, 0x1c, 0x00, 0x3e, 0x00, 0x00], // º
+    [0x18, 0x00, 0x18, 0x30, 0x60, 0x66, 0x3c, 0x00], // ¿
+    [0x00, 0x00, 0x00, 0x3f, 0x03, 0x03, 0x00, 0x00], // ⌐
+    [0x00, 0x00, 0x00, 0x3f, 0x30, 0x30, 0x00, 0x00], // ¬
+    [0x63, 0x33, 0x1b, 0xf6, 0x9b, 0x58, 0x4c, 0xe6], // ½
+    [0x63, 0x33, 0x1b, 0xb6, 0xdb, 0xd8, 0xec, 0xc6], // ¼
+    [0x18, 0x00, 0x18, 0x18, 0x3c, 0x3c, 0x18, 0x00], // ¡
+    [0x00, 0xcc, 0x66, 0x33, 0x66, 0xcc, 0x00, 0x00], // «
+    [0x00, 0x33, 0x66, 0xcc, 0x66, 0x33, 0x00, 0x00], // »
+    [0x22, 0x88, 0x22, 0x88, 0x22, 0x88, 0x22, 0x88], // ░
+    [0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa], // ▒
+    [0xdd, 0x77, 0xdd, 0x77, 0xdd, 0x77, 0xdd, 0x77], // ▓
+    [0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08], // │
+    [0x08, 0x08, 0x08, 0x0f, 0x08, 0x08, 0x08, 0x08], // ┤
+    [0x08, 0x08, 0x0f, 0x08, 0x0f, 0x08, 0x08, 0x08], // ╡
+    [0x14, 0x14, 0x14, 0x17, 0x14, 0x14, 0x14, 0x14], // ╢
+    [0x00, 0x00, 0x00, 0x1f, 0x14, 0x14, 0x14, 0x14], // ╖
+    [0x00, 0x00, 0x0f, 0x08, 0x0f, 0x08, 0x08, 0x08], // ╕
+    [0x14, 0x14, 0x17, 0x10, 0x17, 0x14, 0x14, 0x14], // ╣
+    [0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14], // ║
+    [0x00, 0x00, 0x1f, 0x10, 0x17, 0x14, 0x14, 0x14], // ╗
+    [0x14, 0x14, 0x17, 0x10, 0x1f, 0x00, 0x00, 0x00], // ╝
+    [0x14, 0x14, 0x14, 0x1f, 0x00, 0x00, 0x00, 0x00], // ╜
+    [0x08, 0x08, 0x0f, 0x08, 0x0f, 0x00, 0x00, 0x00], // ╛
+    [0x00, 0x00, 0x00, 0x0f, 0x08, 0x08, 0x08, 0x08], // ┐
+    [0x08, 0x08, 0x08, 0xf8, 0x00, 0x00, 0x00, 0x00], // └
+    [0x08, 0x08, 0x08, 0xff, 0x00, 0x00, 0x00, 0x00], // ┴
+    [0x00, 0x00, 0x00, 0xff, 0x08, 0x08, 0x08, 0x08], // ┬
+    [0x08, 0x08, 0x08, 0xf8, 0x08, 0x08, 0x08, 0x08], // ├
+    [0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00], // ─
+    [0x08, 0x08, 0x08, 0xff, 0x08, 0x08, 0x08, 0x08], // ┼
+    [0x08, 0x08, 0xf8, 0x08, 0xf8, 0x08, 0x08, 0x08], // ╞
+    [0x14, 0x14, 0x14, 0xf4, 0x14, 0x14, 0x14, 0x14], // ╟
+    [0x14, 0x14, 0xf4, 0x04, 0xfc, 0x00, 0x00, 0x00], // ╚
+    [0x00, 0x00, 0xfc, 0x04, 0xf4, 0x14, 0x14, 0x14], // ╔
+    [0x14, 0x14, 0xf7, 0x00, 0xff, 0x00, 0x00, 0x00], // ╩
+    [0x00, 0x00, 0xff, 0x00, 0xf7, 0x14, 0x14, 0x14], // ╦
+    [0x14, 0x14, 0xf4, 0x04, 0xf4, 0x14, 0x14, 0x14], // ╠
+    [0x00, 0x00, 0xff, 0x00, 0xff, 0x00, 0x00, 0x00], // ═
+    [0x14, 0x14, 0xf7, 0x00, 0xf7, 0x14, 0x14, 0x14], // ╬
+    [0x08, 0x08, 0xff, 0x00, 0xff, 0x00, 0x00, 0x00], // ╧
+    [0x14, 0x14, 0x14, 0xff, 0x00, 0x00, 0x00, 0x00], // ╨
+    [0x00, 0x00, 0xff, 0x00, 0xff, 0x08, 0x08, 0x08], // ╤
+    [0x00, 0x00, 0x00, 0xff, 0x14, 0x14, 0x14, 0x14], // ╥
+    [0x14, 0x14, 0x14, 0xfc, 0x00, 0x00, 0x00, 0x00], // ╙
+    [0x08, 0x08, 0xf8, 0x08, 0xf8, 0x00, 0x00, 0x00], // ╘
+    [0x00, 0x00, 0xf8, 0x08, 0xf8, 0x08, 0x08, 0x08], // ╒
+    [0x00, 0x00, 0x00, 0xfc, 0x14, 0x14, 0x14, 0x14], // ╓
+    [0x14, 0x14, 0x14, 0xff, 0x14, 0x14, 0x14, 0x14], // ╫
+    [0x08, 0x08, 0xff, 0x08, 0xff, 0x08, 0x08, 0x08], // ╪
+    [0x08, 0x08, 0x08, 0x0f, 0x00, 0x00, 0x00, 0x00], // ┘
+    [0x00, 0x00, 0x00, 0xf8, 0x08, 0x08, 0x08, 0x08], // ┌
+    [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], // █
+    [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff], // ▄
+    [0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f], // ▌
+    [0xf0, 0xf0, 0xf0, 0xf0, 0xf0, 0xf0, 0xf0, 0xf0], // ▐
+    [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], // ▀
+    [0x00, 0x00, 0x6e, 0x3b, 0x33, 0x3b, 0x6e, 0x00], // α
+    [0x1e, 0x33, 0x33, 0x1f, 0x33, 0x1f, 0x03, 0x03], // ß
+    [0x3f, 0x33, 0x03, 0x03, 0x03, 0x03, 0x03, 0x00], // Γ
+    [0x00, 0x7f, 0x36, 0x36, 0x36, 0x36, 0x36, 0x00], // π
+    [0x3f, 0x33, 0x06, 0x0c, 0x06, 0x33, 0x3f, 0x00], // Σ
+    [0x00, 0x00, 0x7e, 0x1b, 0x1b, 0x1b, 0x0e, 0x00], // σ
+    [0x00, 0x66, 0x66, 0x66, 0x66, 0x3e, 0x06, 0x03], // µ
+    [0x00, 0x6e, 0x3b, 0x18, 0x18, 0x18, 0x18, 0x00], // τ
+    [0x3f, 0x0c, 0x1e, 0x33, 0x33, 0x1e, 0x0c, 0x3f], // Φ
+    [0x1c, 0x36, 0x63, 0x7f, 0x63, 0x36, 0x1c, 0x00], // Θ
+    [0x1c, 0x36, 0x63, 0x63, 0x36, 0x36, 0x77, 0x00], // Ω
+    [0x38, 0x0c, 0x18, 0x3e, 0x33, 0x33, 0x1e, 0x00], // δ
+    [0x00, 0x00, 0x7e, 0xdb, 0xdb, 0x7e, 0x00, 0x00], // ∞
+    [0x60, 0x30, 0x7e, 0xdb, 0xdb, 0x7e, 0x06, 0x03], // φ
+    [0x1c, 0x06, 0x03, 0x1f, 0x03, 0x06, 0x1c, 0x00], // ε
+    [0x1e, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x00], // ∩
+    [0x00, 0x3f, 0x00, 0x3f, 0x00, 0x3f, 0x00, 0x00], // ≡
+    [0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x3f, 0x00], // ±
+    [0x06, 0x0c, 0x18, 0x0c, 0x06, 0x00, 0x3f, 0x00], // ≥
+    [0x18, 0x0c, 0x06, 0x0c, 0x18, 0x00, 0x3f, 0x00], // ≤
+    [0x70, 0xd8, 0xd8, 0x18, 0x18, 0x18, 0x18, 0x18], // ⌠
+    [0x18, 0x18, 0x18, 0x18, 0x1b, 0x1b, 0x0e, 0x00], // ⌡
+    [0x00, 0x0c, 0x00, 0x3f, 0x00, 0x0c, 0x00, 0x00], // ÷
+    [0x00, 0x6e, 0x3b, 0x00, 0x6e, 0x3b, 0x00, 0x00], // ≈
+    [0x1c, 0x36, 0x36, 0x1c, 0x00, 0x00, 0x00, 0x00], // °
+    [0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00], // ∙
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00], // ·
+    [0xf0, 0x30, 0x30, 0x30, 0x37, 0x36, 0x3c, 0x38], // √
+    [0x1e, 0x36, 0x36, 0x36, 0x36, 0x00, 0x00, 0x00], // ⁿ
+    [0x0e, 0x18, 0x0c, 0x06, 0x1e, 0x00, 0x00, 0x00], // ²
+    [0x00, 0x00, 0x3c, 0x3c, 0x3c, 0x3c, 0x00, 0x00], // ■
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // no-break space
+];
+
+// The pixels of row `y` (0 to HEIGHT - 1) of the glyph for `byte`.
+pub fn row(byte: u8, y: usize) -> u8 {
+    let glyph = match byte {
+        FIRST...LAST => &GLYPHS[(byte - FIRST) as usize],
+        UPPER_FIRST...0xff => &UPPER[(byte - UPPER_FIRST) as usize],
+        _ => &MISSING,
+    };
+    glyph[y * 8 / HEIGHT]
+}
@@ -0,0 +1,134 @@
+// A linear framebuffer set up by the boot loader. The console draws its
+// characters on it with the built-in font when there is no text mode.
+
+use core::ops::Range;
+use core::ptr;
+
+use boot::FramebufferInfo;
+use mem;
+use mem::paging::{self, Page, VirtualAddress};
+use super::{font, ColorSpec};
+
+// mapped at 384 GiB, above the block cache
+const FRAMEBUFFER_AREA_START: VirtualAddress = 0o000_600_000_000_0000;
+
+// the sixteen text mode colors as 0xRRGGBB
+const PALETTE: [u32; 16] = [0x000000, 0x0000aa, 0x00aa00, 0x00aaaa,
+                            0xaa0000, 0xaa00aa, 0xaa5500, 0xaaaaaa,
+                            0x555555, 0x5555ff, 0x55ff55, 0x55ffff,
+                            0xff5555, 0xff55ff, 0xffff55, 0xffffff];
+
+#[derive(Clone, Copy)]
+pub struct Framebuffer {
+    address: VirtualAddress,
+    pitch: usize,
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    // the palette in the framebuffer's own pixel format
+    palette: [u32; 16],
+}
+
+// Converts 0xRRGGBB to a pixel with the given (position, size) fields.
+fn pack(rgb: u32, fields: [(u8, u8); 3]) -> u32 {
+    let mut pixel = 0;
+    for (index, &(position, size)) in fields.iter().enumerate() {
+        let channel = rgb >> (16 - 8 * index) & 0xff;
+        let value = if size >= 8 {
+            channel << (size - 8)
+        } else {
+            channel >> (8 - size)
+        };
+        pixel |= value << position;
+    }
+    pixel
+}
+
+impl Framebuffer {
+    // Maps the framebuffer write-combining. Only direct RGB modes of 16, 24
+    // or 32 bits per pixel can be drawn on.
+    pub fn map(info: FramebufferInfo) -> Option<Framebuffer> {
+        let fields = match (info.fields, info.bpp) {
+            (Some(fields), 16) | (Some(fields), 24) | (Some(fields), 32) => fields,
+            _ => return None,
+        };
+        if info.width < font::WIDTH || info.height < font::HEIGHT {
+            return None;
+        }
+
+        let flags = paging::WRITABLE | paging::NO_EXECUTE | paging::WRITE_COMBINING;
+        let address = mem::with_controller(|mc| {
+            mc.map_physical(Page::containing(FRAMEBUFFER_AREA_START),
+                            info.address,
+                            info.pitch * info.height,
+                            flags)
+        });
+
+        let mut palette = [0; 16];
+        for (packed, &rgb) in palette.iter_mut().zip(PALETTE.iter()) {
+            *packed = pack(rgb, fields);
+        }
+
+        Some(Framebuffer {
+            address: address,
+            pitch: info.pitch,
+            width: info.width,
+            height: info.height,
+            bytes_per_pixel: info.bpp / 8,
+            palette: palette,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.height / font::HEIGHT
+    }
+
+    pub fn cols(&self) -> usize {
+        self.width / font::WIDTH
+    }
+
+    pub fn draw(&self, row: usize, col: usize, byte: u8, spec: ColorSpec) {
+        self.paint(row, col, byte, spec, 0..0);
+    }
+
+    // Draws a cell with the cursor on it: the pixel rows in `lines` have their
+    // colors swapped.
+    pub fn draw_cursor(&self,
+                       row: usize,
+                       col: usize,
+                       byte: u8,
+                       spec: ColorSpec,
+                       lines: Range<usize>) {
+        self.paint(row, col, byte, spec, lines);
+    }
+
+    fn paint(&self, row: usize, col: usize, byte: u8, spec: ColorSpec, inverted: Range<usize>) {
+        let foreground = self.palette[(spec.0 & 0xf) as usize];
+        let background = self.palette[(spec.0 >> 4) as usize];
+        for y in 0..font::HEIGHT {
+            let mut bits = font::row(byte, y);
+            if inverted.contains(y) {
+                bits = !bits;
+            }
+            let line = self.address + (row * font::HEIGHT + y) * self.pitch +
+                       col * font::WIDTH * self.bytes_per_pixel;
+            for x in 0..font::WIDTH {
+                let pixel = if bits & 1 << x != 0 { foreground } else { background };
+                self.put_pixel(line + x * self.bytes_per_pixel, pixel);
+            }
+        }
+    }
+
+    fn put_pixel(&self, address: VirtualAddress, pixel: u32) {
+        unsafe {
+            match self.bytes_per_pixel {
+                4 => ptr::write_volatile(address as *mut u32, pixel),
+                3 => {
+                    ptr::write_volatile(address as *mut u16, pixel as u16);
+                    ptr::write_volatile((address + 2) as *mut u8, (pixel >> 16) as u8);
+                }
+                _ => ptr::write_volatile(address as *mut u16, pixel as u16),
+            }
+        }
+    }
+}
@@ -0,0 +1,939 @@
+// use core::default::Default;    // not useful without const trait fns
+
+mod ansi;
+mod cp437;
+mod font;
+mod framebuffer;
+mod scrollback;
+pub mod status;
+mod utf8;
+pub mod vt;
+
+use core::cmp::{min, max};
+use core::fmt;
+use core::ops::Range;
+use core::option::Option;
+use core::ptr::{self, Unique};
+use core::slice;
+
+use multiboot2::BootInformation;
+use spin::Mutex;
+use x86::shared::io::{inb, outb};
+
+use boot;
+use int;
+use kmsg::KMSG;
+
+use self::ansi::{Action, Csi, Parser};
+use self::framebuffer::Framebuffer;
+use self::scrollback::Scrollback;
+use self::utf8::{Decoded, Decoder};
+
+const BUFFER_ROWS: usize = 25;
+const BUFFER_COLS: usize = 80;
+
+// the largest character grid drawn on a framebuffer, 1920x1080 with 8x16 cells
+const MAX_ROWS: usize = 68;
+const MAX_COLS: usize = 240;
+
+const TAB_WIDTH: usize = 8;
+
+// lines of scrollback kept unless `scrollback=` on the command line says
+// otherwise; the heap is small
+const SCROLLBACK_LINES: usize = 100;
+
+// the most `scrollback=` may ask for: about 60 KiB of the 100 KiB heap with
+// lines as wide as MAX_COLS
+const MAX_SCROLLBACK_LINES: usize = 128;
+
+#[allow(dead_code)]
+#[repr(u8)]
+pub enum Color {
+    Black      = 0x0,    DarkGray   = 0x8,
+    Blue       = 0x1,    LightBlue  = 0x9,
+    Green      = 0x2,    LightGreen = 0xa,
+    Cyan       = 0x3,    LightCyan  = 0xb,
+    Red        = 0x4,    LightRed   = 0xc,
+    Magenta    = 0x5,    Pink       = 0xd,
+    Brown      = 0x6,    Yellow     = 0xe,
+    LightGray  = 0x7,    White      = 0xf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ColorSpec(u8);
+
+impl ColorSpec {
+    pub const fn new(foreground: Color, background: Color) -> ColorSpec {
+        ColorSpec((background as u8) << 4 | (foreground as u8))
+    }
+
+    pub const fn default() -> ColorSpec {
+        ColorSpec::new(Color::LightGray, Color::Black)
+    }
+
+    fn foreground(&self) -> u8 {
+        self.0 & 0xf
+    }
+
+    fn background(&self) -> u8 {
+        self.0 >> 4
+    }
+
+    fn with_foreground(&self, color: u8) -> ColorSpec {
+        ColorSpec(self.0 & 0xf0 | color)
+    }
+
+    fn with_background(&self, color: u8) -> ColorSpec {
+        ColorSpec(self.0 & 0x0f | color << 4)
+    }
+}
+
+// SGR colors 0 to 7 in ANSI order; adding 8 gives the bright variant
+const ANSI_COLORS: [u8; 8] = [Color::Black as u8,
+                              Color::Red as u8,
+                              Color::Green as u8,
+                              Color::Brown as u8,
+                              Color::Blue as u8,
+                              Color::Magenta as u8,
+                              Color::Cyan as u8,
+                              Color::LightGray as u8];
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct VgaChar {
+    char: u8,
+    spec: ColorSpec,
+}
+
+impl VgaChar {
+    const fn default() -> VgaChar {
+        VgaChar {
+            char: b' ',
+            spec: ColorSpec::default(),
+        }
+    }
+}
+
+const TEXT_BUFFER: usize = 0xb8000;
+
+// CRT controller registers that place and shape the text mode cursor
+const CRTC_INDEX: u16 = 0x3d4;
+const CRTC_DATA: u16 = 0x3d5;
+const CRTC_CURSOR_START: u8 = 0x0a;
+const CRTC_CURSOR_END: u8 = 0x0b;
+const CRTC_CURSOR_HIGH: u8 = 0x0e;
+const CRTC_CURSOR_LOW: u8 = 0x0f;
+const CURSOR_DISABLE: u8 = 1 << 5;
+
+// the attribute controller, whose mode control register decides whether bit
+// 3 of the background makes text blink or selects a bright background
+const INPUT_STATUS: u16 = 0x3da;
+const ATTRIBUTE_INDEX: u16 = 0x3c0;
+const ATTRIBUTE_READ: u16 = 0x3c1;
+const ATTRIBUTE_MODE: u8 = 0x10;
+// keeps the screen on while the index is written
+const ATTRIBUTE_PALETTE_SOURCE: u8 = 1 << 5;
+const ATTRIBUTE_BLINK: u8 = 1 << 3;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CursorShape {
+    Underline,
+    HalfBlock,
+    Block,
+}
+
+impl CursorShape {
+    // The scan lines of a 16 line character cell that the cursor covers.
+    fn lines(&self) -> Range<usize> {
+        match *self {
+            CursorShape::Underline => 14..16,
+            CursorShape::HalfBlock => 8..16,
+            CursorShape::Block => 0..16,
+        }
+    }
+}
+
+// What is on a framebuffer console, so that it can be scrolled and redrawn
+// without reading the framebuffer back. Only ever used through a Writer.
+static mut SHADOW: [VgaChar; MAX_ROWS * MAX_COLS] = [VgaChar::default(); MAX_ROWS * MAX_COLS];
+
+// Set once by `init`; read by `emergency_writer`, which cannot take locks.
+static mut FRAMEBUFFER: Option<Framebuffer> = None;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Top,
+    Center,
+    Right,
+    Bottom,
+    Left,
+}
+
+pub trait AlignRow {
+    fn rowalign(&self, align: Align) -> usize;
+}
+
+pub trait AlignCol {
+    fn colalign(&self, align: Align, str: &str) -> usize;
+}
+
+pub struct Writer {
+    row: usize,
+    col: usize,
+    rows: usize,
+    cols: usize,
+    // lines at the top and bottom that text does not scroll through, for
+    // fixed regions drawn with `draw_line`
+    reserved_top: usize,
+    reserved_bottom: usize,
+    color_spec: ColorSpec,
+    // `rows` by `cols` cells, row after row: the text buffer itself, the
+    // shadow of a framebuffer, or the buffer of a terminal not shown
+    cells: Unique<VgaChar>,
+    framebuffer: Option<Framebuffer>,
+    // whether this is the terminal on the screen; the others write to a
+    // buffer of their own, see `vt`
+    shown: bool,
+    cursor_shape: CursorShape,
+    cursor_visible: bool,
+    // where the cursor was last drawn on a framebuffer, which has no
+    // hardware cursor
+    cursor_drawn: Option<(usize, usize)>,
+    parser: Parser,
+    decoder: Decoder,
+    // SGR attributes applied on top of `color_spec`
+    bold: bool,
+    reverse: bool,
+    saved_cursor: (usize, usize),
+    scrollback: Option<Scrollback>,
+}
+
+// The kernel's log terminal, the first of the virtual terminals. Like all of
+// them it is locked with interrupts disabled, as the keyboard interrupt
+// switches and scrolls terminals.
+pub static WRITER: Mutex<Writer> = Mutex::new(Writer::new(true));
+
+impl Writer {
+    const fn new(shown: bool) -> Writer {
+        Writer {
+            row: 0,
+            col: 0,
+            rows: BUFFER_ROWS,
+            cols: BUFFER_COLS,
+            reserved_top: 0,
+            reserved_bottom: 1,
+            color_spec: ColorSpec::default(),
+            cells: unsafe { Unique::new(TEXT_BUFFER as *mut _) },
+            framebuffer: None,
+            shown: shown,
+            cursor_shape: CursorShape::Underline,
+            cursor_visible: true,
+            cursor_drawn: None,
+            parser: Parser::new(),
+            decoder: Decoder::new(),
+            bold: false,
+            reverse: false,
+            saved_cursor: (0, 0),
+            scrollback: None,
+        }
+    }
+
+    // Draws on `framebuffer` from now on, keeping its contents in SHADOW
+    // while shown.
+    fn attach(&mut self, framebuffer: Framebuffer) {
+        self.rows = min(framebuffer.rows(), MAX_ROWS);
+        self.cols = min(framebuffer.cols(), MAX_COLS);
+        self.framebuffer = Some(framebuffer);
+        if self.shown {
+            self.cells = unsafe { Unique::new(self.screen()) };
+        }
+        let (row, col) = (self.row, self.col);
+        self.move_cursor(row, col);
+    }
+
+    // The cells of the screen itself.
+    fn screen(&self) -> *mut VgaChar {
+        match self.framebuffer {
+            Some(_) => unsafe { SHADOW.as_mut_ptr() },
+            None => TEXT_BUFFER as *mut VgaChar,
+        }
+    }
+
+    // Takes the terminal off the screen, copying what it shows to `backing`
+    // where it goes on being written.
+    fn hide(&mut self, backing: *mut VgaChar) {
+        self.erase_cursor();
+        unsafe {
+            let cells = self.cells.as_mut() as *mut VgaChar;
+            ptr::copy_nonoverlapping(cells, backing, self.rows * self.cols);
+            self.cells = Unique::new(backing);
+        }
+        self.shown = false;
+    }
+
+    // Puts the terminal back on the screen, drawing the cells that differ
+    // from what the terminal shown before left there.
+    fn show(&mut self) {
+        let backing = unsafe { self.cells.as_mut() as *mut VgaChar };
+        self.cells = unsafe { Unique::new(self.screen()) };
+        self.shown = true;
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let char = unsafe { *backing.offset((row * self.cols + col) as isize) };
+                self.put(row, col, char);
+            }
+        }
+        self.program_cursor_shape();
+        self.update_cursor();
+    }
+
+    // Moves the cursor within the scrolling area.
+    pub fn move_cursor(&mut self, row: usize, col: usize) {
+        let area = self.area();
+        self.row = max(area.start, min(row, area.end - 1));
+        self.col = min(col, self.cols - 1);
+        self.update_cursor();
+    }
+
+    // The rows that text scrolls through.
+    fn area(&self) -> Range<usize> {
+        self.reserved_top..self.rows - self.reserved_bottom
+    }
+
+    // Sets aside `top` and `bottom` lines for fixed regions, leaving the rest
+    // of the screen, at least one line, to scroll. One line at the bottom is
+    // reserved to begin with, for the status line.
+    pub fn reserve(&mut self, top: usize, bottom: usize) {
+        if top + bottom >= self.rows {
+            return;
+        }
+        self.follow();
+        self.reserved_top = top;
+        self.reserved_bottom = bottom;
+        let (row, col) = (self.row, self.col);
+        self.move_cursor(row, col);
+    }
+
+    // Draws `text` on `row`, aligned as asked and padded with blanks to the
+    // width of the screen. Meant for rows outside the scrolling area, which
+    // text written to the terminal leaves alone.
+    pub fn draw_line(&mut self, row: usize, text: &str, align: Align, spec: ColorSpec) {
+        if row >= self.rows {
+            return;
+        }
+        let start = self.colalign(align, text);
+        let mut chars = text.chars();
+        for col in 0..self.cols {
+            let byte = if col >= start {
+                chars.next().map(cp437::encode).unwrap_or(b' ')
+            } else {
+                b' '
+            };
+            self.put(row, col, VgaChar {
+                char: byte,
+                spec: spec,
+            });
+        }
+        if row == self.row {
+            self.update_cursor();
+        }
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+        self.program_cursor_shape();
+        self.update_cursor();
+    }
+
+    pub fn show_cursor(&mut self) {
+        self.cursor_visible = true;
+        self.program_cursor_shape();
+        self.update_cursor();
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor_visible = false;
+        self.program_cursor_shape();
+        self.update_cursor();
+    }
+
+    pub fn clear(&mut self) {
+        for row in self.area() {
+            self.row = row;
+            self.clear_row();
+        }
+        let top = self.area().start;
+        self.move_cursor(top, 0);
+    }
+
+    pub fn set_color(&mut self, spec: ColorSpec) {
+        self.color_spec = spec;
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.follow();
+        for &byte in bytes {
+            self.write_byte(byte, None)
+        }
+        self.update_cursor();
+    }
+
+    fn program_cursor_shape(&self) {
+        if !self.shown || self.framebuffer.is_some() {
+            return;
+        }
+        let lines = self.cursor_shape.lines();
+        let disable = if self.cursor_visible { 0 } else { CURSOR_DISABLE };
+        unsafe {
+            outb(CRTC_INDEX, CRTC_CURSOR_START);
+            let start = inb(CRTC_DATA) & 0xc0 | disable | lines.start as u8;
+            outb(CRTC_DATA, start);
+            outb(CRTC_INDEX, CRTC_CURSOR_END);
+            let end = inb(CRTC_DATA) & 0xe0 | (lines.end - 1) as u8;
+            outb(CRTC_DATA, end);
+        }
+    }
+
+    // Puts the cursor where the next character goes: the hardware cursor in
+    // text mode, inverted scan lines of the cell on a framebuffer.
+    fn update_cursor(&mut self) {
+        if !self.shown {
+            return;
+        }
+        let (row, col) = (self.row, self.col);
+        // the cursor is not shown over scrollback
+        let visible = self.cursor_visible && self.scrollback_offset() == 0;
+        match self.framebuffer {
+            None => {
+                // a position past the end of the screen hides the cursor
+                let position = if visible {
+                    row * self.cols + col
+                } else {
+                    self.rows * self.cols
+                };
+                unsafe {
+                    outb(CRTC_INDEX, CRTC_CURSOR_LOW);
+                    outb(CRTC_DATA, position as u8);
+                    outb(CRTC_INDEX, CRTC_CURSOR_HIGH);
+                    outb(CRTC_DATA, (position >> 8) as u8);
+                }
+            }
+            Some(framebuffer) => {
+                self.erase_cursor();
+                if visible {
+                    let char = *self.cell(row, col);
+                    let lines = self.cursor_shape.lines();
+                    framebuffer.draw_cursor(row, col, char.char, char.spec, lines);
+                    self.cursor_drawn = Some((row, col));
+                }
+            }
+        }
+    }
+
+    // Draws the cell under a framebuffer cursor as it is.
+    fn erase_cursor(&mut self) {
+        if let Some((row, col)) = self.cursor_drawn.take() {
+            let char = *self.cell(row, col);
+            if let Some(ref framebuffer) = self.framebuffer {
+                framebuffer.draw(row, col, char.char, char.spec);
+            }
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8, spec: Option<ColorSpec>) {
+        match self.parser.advance(byte) {
+            Action::None => {}
+            Action::Print(byte) => self.decode(byte, spec),
+            Action::Execute(byte) => {
+                self.break_char(spec);
+                self.execute(byte)
+            }
+            Action::Csi(csi) => {
+                self.break_char(spec);
+                self.control_sequence(&csi)
+            }
+        }
+    }
+
+    // Prints the character `byte` completes, if any, in its CP437 glyph.
+    fn decode(&mut self, byte: u8, spec: Option<ColorSpec>) {
+        match self.decoder.advance(byte) {
+            Decoded::Pending => {}
+            Decoded::Char(char) => self.print_byte(cp437::encode(char), spec),
+            Decoded::Broken => {
+                self.print_byte(cp437::REPLACEMENT, spec);
+                self.decode(byte, spec)
+            }
+        }
+    }
+
+    // Ends a character that control characters or a control sequence cut
+    // short.
+    fn break_char(&mut self, spec: Option<ColorSpec>) {
+        if self.decoder.reset() {
+            self.print_byte(cp437::REPLACEMENT, spec);
+        }
+    }
+
+    // Keeps up to `depth` of the lines that scroll off the top. Only the log
+    // terminal does, once, in `init`.
+    fn enable_scrollback(&mut self, depth: usize) {
+        self.scrollback = Some(unsafe { Scrollback::new(depth) });
+    }
+
+    pub fn scroll_back(&mut self, lines: usize) {
+        let offset = match self.scrollback {
+            Some(ref scrollback) => min(scrollback.offset + lines, scrollback.len()),
+            None => return,
+        };
+        self.show_scrollback(offset);
+    }
+
+    pub fn scroll_forward(&mut self, lines: usize) {
+        let offset = self.scrollback_offset().saturating_sub(lines);
+        self.show_scrollback(offset);
+    }
+
+    // The number of lines in the scrolling part of the screen, which is what
+    // scrolling back and forward moves by a page.
+    pub fn page_lines(&self) -> usize {
+        self.area().len()
+    }
+
+    // Draws the visible window again, all of it, in case the screen was
+    // changed behind the writer's back.
+    pub fn render(&mut self) {
+        let offset = self.scrollback_offset();
+        self.show_scrollback(offset);
+        if self.framebuffer.is_some() {
+            self.redraw();
+        }
+        self.update_cursor();
+    }
+
+    fn scrollback_offset(&self) -> usize {
+        self.scrollback.as_ref().map_or(0, |scrollback| scrollback.offset)
+    }
+
+    // Returns to the live screen before anything is written.
+    fn follow(&mut self) {
+        if self.scrollback_offset() != 0 {
+            self.show_scrollback(0);
+        }
+    }
+
+    // Shows the screen as it was `offset` lines back, saving the live screen
+    // on the way into the scrollback and putting it back on the way out.
+    fn show_scrollback(&mut self, offset: usize) {
+        let mut scrollback = match self.scrollback.take() {
+            Some(scrollback) => scrollback,
+            None => return,
+        };
+        if scrollback.offset == 0 && offset == 0 {
+            // already live, and what `live` holds is stale
+            self.scrollback = Some(scrollback);
+            return;
+        }
+        let (area, cols) = (self.area(), self.cols);
+
+        if scrollback.offset == 0 {
+            for (index, row) in area.clone().enumerate() {
+                for col in 0..cols {
+                    scrollback.live[index * cols + col] = *self.cell(row, col);
+                }
+            }
+        }
+        scrollback.offset = offset;
+
+        // line `len` is the first line of the live screen
+        let len = scrollback.len();
+        for (index, row) in area.enumerate() {
+            let line = len + index - offset;
+            for col in 0..cols {
+                let char = if line < len {
+                    scrollback.get(line, col)
+                } else {
+                    scrollback.live[(line - len) * cols + col]
+                };
+                self.put(row, col, char);
+            }
+        }
+        self.scrollback = Some(scrollback);
+        self.update_cursor();
+    }
+
+    fn print_byte(&mut self, byte: u8, spec: Option<ColorSpec>) {
+        let (row, col) = (self.row, self.col);
+        let char = VgaChar {
+            char: byte,
+            spec: spec.unwrap_or(self.current_spec()),
+        };
+        self.put(row, col, char);
+        self.col += 1;
+        if self.col >= self.cols {
+            self.new_line();
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            b'\t' => self.col = min((self.col / TAB_WIDTH + 1) * TAB_WIDTH, self.cols - 1),
+            0x08 => self.col = self.col.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn control_sequence(&mut self, csi: &Csi) {
+        let (row, col) = (self.row, self.col);
+        let count = csi.param(0, 1) as usize;
+        match (csi.private, csi.command) {
+            (false, b'A') => self.move_cursor(row.saturating_sub(count), col),
+            (false, b'B') => self.move_cursor(row + count, col),
+            (false, b'C') => self.move_cursor(row, col + count),
+            (false, b'D') => self.move_cursor(row, col.saturating_sub(count)),
+            (false, b'G') => self.move_cursor(row, count - 1),
+            // rows count from the top of the scrolling area
+            (false, b'H') | (false, b'f') => {
+                let top = self.area().start;
+                self.move_cursor(top + csi.param(0, 1) as usize - 1,
+                                 csi.param(1, 1) as usize - 1)
+            }
+            (false, b'J') => self.erase_display(csi.param(0, 0)),
+            (false, b'K') => self.erase_line(csi.param(0, 0)),
+            (false, b'm') => self.select_graphic_rendition(csi),
+            (false, b's') => self.saved_cursor = (row, col),
+            (false, b'u') => {
+                let (row, col) = self.saved_cursor;
+                self.move_cursor(row, col)
+            }
+            (true, b'h') if csi.param(0, 0) == 25 => self.show_cursor(),
+            (true, b'l') if csi.param(0, 0) == 25 => self.hide_cursor(),
+            _ => {}
+        }
+    }
+
+    // The color of printed characters, with bold and reverse applied.
+    fn current_spec(&self) -> ColorSpec {
+        let mut spec = self.color_spec;
+        if self.bold {
+            spec = spec.with_foreground(spec.foreground() | 0x8);
+        }
+        if self.reverse {
+            spec = ColorSpec(spec.foreground() << 4 | spec.background());
+        }
+        spec
+    }
+
+    fn select_graphic_rendition(&mut self, csi: &Csi) {
+        if csi.params().is_empty() {
+            self.reset_attributes();
+        }
+        for &param in csi.params() {
+            let spec = self.color_spec;
+            match param {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                30...37 => self.color_spec = spec.with_foreground(ANSI_COLORS[param as usize - 30]),
+                39 => self.color_spec = spec.with_foreground(ColorSpec::default().foreground()),
+                40...47 => self.color_spec = spec.with_background(ANSI_COLORS[param as usize - 40]),
+                49 => self.color_spec = spec.with_background(ColorSpec::default().background()),
+                90...97 => {
+                    self.color_spec = spec.with_foreground(ANSI_COLORS[param as usize - 90] | 0x8)
+                }
+                100...107 => {
+                    self.color_spec = spec.with_background(ANSI_COLORS[param as usize - 100] | 0x8)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.color_spec = ColorSpec::default();
+        self.bold = false;
+        self.reverse = false;
+    }
+
+    // Erases from the cursor to the end (0), from the start to the cursor (1)
+    // or all (2) of the line, in the current background color.
+    fn erase_line(&mut self, mode: u16) {
+        let (row, col) = (self.row, self.col);
+        let cols = match mode {
+            0 => col..self.cols,
+            1 => 0..col + 1,
+            2 => 0..self.cols,
+            _ => return,
+        };
+        self.erase(row, cols);
+    }
+
+    // Like `erase_line`, for the whole scrolling area.
+    fn erase_display(&mut self, mode: u16) {
+        let (row, area) = (self.row, self.area());
+        let rows = match mode {
+            0 => row + 1..area.end,
+            1 => area.start..row,
+            2 => area,
+            _ => return,
+        };
+        if mode != 2 {
+            self.erase_line(mode);
+        }
+        let cols = self.cols;
+        for row in rows {
+            self.erase(row, 0..cols);
+        }
+    }
+
+    fn erase(&mut self, row: usize, cols: Range<usize>) {
+        let blank = VgaChar {
+            char: b' ',
+            spec: self.current_spec(),
+        };
+        for col in cols {
+            self.put(row, col, blank);
+        }
+    }
+
+    fn cell(&mut self, row: usize, col: usize) -> &mut VgaChar {
+        unsafe {
+            let cells = self.cells.as_mut() as *mut VgaChar;
+            &mut *cells.offset((row * self.cols + col) as isize)
+        }
+    }
+
+    // Sets a cell, drawing it on the framebuffer if it changed.
+    fn put(&mut self, row: usize, col: usize, char: VgaChar) {
+        if *self.cell(row, col) == char {
+            return;
+        }
+        *self.cell(row, col) = char;
+        if !self.shown {
+            return;
+        }
+        if let Some(ref framebuffer) = self.framebuffer {
+            framebuffer.draw(row, col, char.char, char.spec);
+        }
+    }
+
+    // Draws every cell, for a framebuffer whose contents are unknown.
+    fn redraw(&mut self) {
+        if let (true, Some(framebuffer)) = (self.shown, self.framebuffer) {
+            for row in 0..self.rows {
+                for col in 0..self.cols {
+                    let char = *self.cell(row, col);
+                    framebuffer.draw(row, col, char.char, char.spec);
+                }
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        let end = self.area().end;
+        self.col = 0;
+        self.row += 1;
+        if self.row >= end {
+            self.scroll_up();
+            self.row = end - 1;
+            self.clear_row();
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        let area = self.area();
+        if let Some(mut scrollback) = self.scrollback.take() {
+            let first = unsafe {
+                let cells = self.cells.as_mut() as *mut VgaChar;
+                slice::from_raw_parts(cells.offset((area.start * self.cols) as isize), self.cols)
+            };
+            scrollback.push(first);
+            self.scrollback = Some(scrollback);
+        }
+        for row in area.start..area.end - 1 {
+            for col in 0..self.cols {
+                let below = *self.cell(row + 1, col);
+                self.put(row, col, below);
+            }
+        }
+    }
+
+    fn clear_row(&mut self) {
+        let row = self.row;
+        for col in 0..self.cols {
+            self.put(row, col, VgaChar::default());
+        }
+    }
+}
+
+// Rows of the whole screen. Left and Right make no sense for a row and mean
+// the first and the last one, like Top and Bottom.
+impl AlignRow for Writer {
+    fn rowalign(&self, align: Align) -> usize {
+        match align {
+            Align::Top | Align::Left => 0,
+            Align::Center => (self.rows - 1) / 2,
+            Align::Bottom | Align::Right => self.rows - 1,
+        }
+    }
+}
+
+// The column `str` starts at; text wider than the screen starts at the left
+// edge. Top and Bottom mean Left and Right.
+impl AlignCol for Writer {
+    fn colalign(&self, align: Align, str: &str) -> usize {
+        let free = self.cols.saturating_sub(str.chars().count());
+        match align {
+            Align::Left | Align::Top => 0,
+            Align::Center => free / 2,
+            Align::Right | Align::Bottom => free,
+        }
+    }
+}
+
+impl ::core::fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
+        self.follow();
+        for byte in s.bytes() {
+            self.write_byte(byte, None)
+        }
+        self.update_cursor();
+        Ok(())
+    }
+}
+
+macro_rules! println {
+    ($fmt:expr) => (print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+macro_rules! print {
+    ($($arg:tt)*) => ({
+        $crate::vga::print(format_args!($($arg)*));
+    });
+}
+
+pub fn print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    int::without_interrupts(|| {
+        WRITER.lock().write_fmt(args).unwrap();
+        KMSG.lock().write_fmt(args).unwrap();
+    });
+}
+
+// Switches the console to the boot loader's framebuffer, if there is one it
+// can draw on, and shows again what was printed before. Sets up the other
+// virtual terminals to match.
+pub fn init(boot_info: &BootInformation) {
+    assert_has_not_been_called!();
+
+    let framebuffer = boot::framebuffer(boot_info).and_then(Framebuffer::map);
+    let lines = scrollback_lines();
+    int::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.enable_scrollback(lines);
+        match framebuffer {
+            Some(framebuffer) => {
+                unsafe {
+                    FRAMEBUFFER = Some(framebuffer);
+                }
+                writer.row = 0;
+                writer.col = 0;
+                writer.attach(framebuffer);
+                writer.redraw();
+                replay(&mut writer);
+            }
+            None => {
+                disable_blink();
+                writer.program_cursor_shape();
+            }
+        }
+    });
+    vt::init(framebuffer);
+}
+
+// Makes bit 3 of the background select bright colors in text mode, as it does
+// on a framebuffer, rather than blinking.
+fn disable_blink() {
+    unsafe {
+        // reading the input status resets the index/data flip-flop to index
+        inb(INPUT_STATUS);
+        outb(ATTRIBUTE_INDEX, ATTRIBUTE_MODE | ATTRIBUTE_PALETTE_SOURCE);
+        let mode = inb(ATTRIBUTE_READ);
+        outb(ATTRIBUTE_INDEX, mode & !ATTRIBUTE_BLINK);
+    }
+}
+
+// Writes out the kernel message buffer, which holds what was printed so far.
+fn replay(writer: &mut Writer) {
+    let kmsg = KMSG.lock();
+    let mut offset = kmsg.first();
+    let mut buf = [0; 256];
+    loop {
+        let (_, count) = kmsg.read_at(offset, &mut buf);
+        if count == 0 {
+            break;
+        }
+        writer.write_bytes(&buf[..count]);
+        offset += count;
+    }
+}
+
+// Prints `text` on a line of its own, centered on the log terminal.
+pub fn banner(text: &str) {
+    let col = int::without_interrupts(|| WRITER.lock().colalign(Align::Center, text));
+    println!("{0:1$}{2}", "", col, text);
+}
+
+// Warns rather than runs out of heap when asked for too much. The log macros
+// are not defined yet here, so this logs through `log::log` itself.
+fn scrollback_lines() -> usize {
+    let lines = boot::option("scrollback")
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(SCROLLBACK_LINES);
+    if lines > MAX_SCROLLBACK_LINES {
+        ::log::log(::log::Level::Warn,
+                   module_path!(),
+                   format_args!("vga: scrollback={} is too many lines, keeping {}",
+                                lines,
+                                MAX_SCROLLBACK_LINES));
+        return MAX_SCROLLBACK_LINES;
+    }
+    lines
+}
+
+// A writer on whatever the screen shows, for the emergency console. It takes
+// no lock, as the error may have struck while the terminal was locked, and
+// starts below the last line with anything on it so that what led up to the
+// error stays in view.
+pub unsafe fn emergency_writer() -> Writer {
+    let mut writer = Writer::new(true);
+    if let Some(framebuffer) = FRAMEBUFFER {
+        writer.attach(framebuffer);
+    }
+    let area = writer.area();
+    let mut last = None;
+    for row in area.clone() {
+        for col in 0..writer.cols {
+            let char = writer.cell(row, col).char;
+            if char != b' ' && char != 0 {
+                last = Some(row);
+                break;
+            }
+        }
+    }
+    match last {
+        Some(row) => {
+            writer.move_cursor(row, 0);
+            writer.new_line();
+        }
+        None => writer.move_cursor(area.start, 0),
+    }
+    writer
+}
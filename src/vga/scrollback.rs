@@ -0,0 +1,58 @@
+// Lines that scrolled off the top of the console, kept in a ring on the heap
+// so that they can be brought back into view.
+
+use collections::vec::Vec;
+use collections::vec_deque::VecDeque;
+
+use super::{VgaChar, MAX_ROWS, MAX_COLS};
+
+// The live screen while the view is scrolled back. Static rather than on the
+// heap, as scrolling back happens in the keyboard interrupt handler; there is
+// only the one, as only the log terminal keeps scrollback.
+static mut LIVE: [VgaChar; MAX_ROWS * MAX_COLS] = [VgaChar::default(); MAX_ROWS * MAX_COLS];
+
+pub struct Scrollback {
+    depth: usize,
+    lines: VecDeque<Vec<VgaChar>>,
+    // how many lines back the view is, 0 while following the output
+    pub offset: usize,
+    // the screen as it was when scrolling back started, row after row, put
+    // back when the view returns
+    pub live: &'static mut [VgaChar],
+}
+
+impl Scrollback {
+    // Must only be called once, as all scrollbacks would share LIVE.
+    pub unsafe fn new(depth: usize) -> Scrollback {
+        Scrollback {
+            depth: depth,
+            lines: VecDeque::with_capacity(depth),
+            offset: 0,
+            live: &mut LIVE,
+        }
+    }
+
+    // Keeps a copy of `line`, dropping the oldest line once full.
+    pub fn push(&mut self, line: &[VgaChar]) {
+        if self.depth == 0 {
+            return;
+        }
+        let mut kept = if self.lines.len() == self.depth {
+            self.lines.pop_front().unwrap()
+        } else {
+            Vec::with_capacity(line.len())
+        };
+        kept.clear();
+        kept.extend_from_slice(line);
+        self.lines.push_back(kept);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    // The character at `col` of line `index`, oldest first.
+    pub fn get(&self, index: usize, col: usize) -> VgaChar {
+        self.lines[index].get(col).cloned().unwrap_or(VgaChar::default())
+    }
+}
@@ -0,0 +1,101 @@
+// The status line at the bottom of every terminal: which terminal it is, the
+// uptime, free memory and the number of CPUs. It is redrawn once a second from
+// the timer interrupt, so it is formatted on the stack and never waits for the
+// memory controller.
+
+use core::fmt::{self, Write};
+use core::str;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use alloc::arc::Arc;
+
+use acpi;
+use int;
+use mem;
+use time;
+use super::{Align, AlignCol, AlignRow, Color, ColorSpec, MAX_COLS};
+use super::vt::{self, VT_COUNT};
+
+// free frames as last seen, for when the memory controller is busy
+static FREE_FRAMES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn spec() -> ColorSpec {
+    ColorSpec::new(Color::Black, Color::LightGray)
+}
+
+// A line of text in a fixed buffer; what does not fit is dropped.
+struct Line {
+    bytes: [u8; MAX_COLS],
+    len: usize,
+}
+
+impl Line {
+    fn new() -> Line {
+        Line {
+            bytes: [0; MAX_COLS],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    fn pad(&mut self, len: usize) {
+        while self.len < len && self.len < MAX_COLS {
+            self.bytes[self.len] = b' ';
+            self.len += 1;
+        }
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == MAX_COLS {
+                break;
+            }
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+pub fn init() {
+    int::without_interrupts(update);
+    int::register_irq(time::TIMER_IRQ,
+                      Arc::new(|| if time::ticks() % time::TICK_HZ == 0 {
+                          update();
+                      }));
+}
+
+// Redraws the status line of every terminal. Must run with interrupts off.
+pub fn update() {
+    if let Some(free) = mem::try_free_frames() {
+        FREE_FRAMES.store(free, Ordering::Relaxed);
+    }
+    let seconds = time::uptime_ms() / 1000;
+    let cpus = acpi::cpu_count();
+
+    let mut right = Line::new();
+    let _ = write!(right,
+                   "up {}:{:02}:{:02} | {} KiB free | {} cpu{} ",
+                   seconds / 3600,
+                   seconds / 60 % 60,
+                   seconds % 60,
+                   FREE_FRAMES.load(Ordering::Relaxed) * mem::PAGE_SIZE / 1024,
+                   cpus,
+                   if cpus == 1 { "" } else { "s" });
+
+    for index in 0..VT_COUNT {
+        let mut writer = vt::terminal(index).lock();
+        let mut line = Line::new();
+        let _ = write!(line, " mezzo tty{}", index + 1);
+        let col = writer.colalign(Align::Right, right.as_str());
+        line.pad(col);
+        let _ = line.write_str(right.as_str());
+        let row = writer.rowalign(Align::Bottom);
+        writer.draw_line(row, line.as_str(), Align::Left, spec());
+    }
+}
@@ -0,0 +1,74 @@
+// A UTF-8 decoder fed one byte at a time, as a character may be split between
+// writes to the console. Malformed input decodes to U+FFFD, once for each
+// byte that cannot start a character and once for a sequence broken off.
+
+use core::char;
+
+pub const REPLACEMENT: char = '\u{fffd}';
+
+pub enum Decoded {
+    // the byte was part of a character that is not complete yet
+    Pending,
+    Char(char),
+    // the byte did not continue the character being decoded, which is
+    // dropped; the byte has to be fed again
+    Broken,
+}
+
+pub struct Decoder {
+    code: u32,
+    // continuation bytes still expected
+    remaining: u8,
+    // the smallest code point that needs this many bytes, to catch overlong
+    // encodings
+    min: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Decoder {
+        Decoder {
+            code: 0,
+            remaining: 0,
+            min: 0,
+        }
+    }
+
+    pub fn advance(&mut self, byte: u8) -> Decoded {
+        if self.remaining > 0 {
+            if byte & 0xc0 != 0x80 {
+                self.remaining = 0;
+                return Decoded::Broken;
+            }
+            self.code = self.code << 6 | (byte & 0x3f) as u32;
+            self.remaining -= 1;
+            if self.remaining > 0 {
+                return Decoded::Pending;
+            }
+            if self.code < self.min {
+                return Decoded::Char(REPLACEMENT);
+            }
+            // surrogates and code points past U+10FFFF are not characters
+            return Decoded::Char(char::from_u32(self.code).unwrap_or(REPLACEMENT));
+        }
+
+        let (code, remaining, min) = match byte {
+            0x00...0x7f => return Decoded::Char(byte as char),
+            0xc0...0xdf => (byte & 0x1f, 1, 0x80),
+            0xe0...0xef => (byte & 0x0f, 2, 0x800),
+            0xf0...0xf7 => (byte & 0x07, 3, 0x10000),
+            // continuation bytes on their own, and bytes never used
+            _ => return Decoded::Char(REPLACEMENT),
+        };
+        self.code = code as u32;
+        self.remaining = remaining;
+        self.min = min;
+        Decoded::Pending
+    }
+
+    // Drops a character left incomplete. Returns whether there was one.
+    pub fn reset(&mut self) -> bool {
+        let pending = self.remaining > 0;
+        self.remaining = 0;
+        pending
+    }
+}
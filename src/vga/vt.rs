@@ -0,0 +1,151 @@
+// Virtual terminals: independent consoles of which one is on the screen at a
+// time, switched between with Alt+F1..F6. The first is the kernel's log
+// terminal that `print!` writes to, the others start out blank. Only the log
+// terminal keeps scrollback, as the heap is small.
+
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use core::ptr::Unique;
+
+use spin::Mutex;
+
+use int;
+use super::{Writer, VgaChar, WRITER, MAX_ROWS, MAX_COLS};
+use super::framebuffer::Framebuffer;
+
+pub const VT_COUNT: usize = 6;
+
+const INPUT_SIZE: usize = 256;
+
+static OTHERS: [Mutex<Writer>; VT_COUNT - 1] = [Mutex::new(Writer::new(false)),
+                                                Mutex::new(Writer::new(false)),
+                                                Mutex::new(Writer::new(false)),
+                                                Mutex::new(Writer::new(false)),
+                                                Mutex::new(Writer::new(false))];
+
+// What each terminal holds while it is not on the screen. These are static
+// rather than on the heap, as terminals are switched in the keyboard
+// interrupt handler.
+static mut BACKING: [[VgaChar; MAX_ROWS * MAX_COLS]; VT_COUNT] =
+    [[VgaChar::default(); MAX_ROWS * MAX_COLS]; VT_COUNT];
+
+static ACTIVE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Bytes typed on a terminal that were not read yet. Once full, further input
+// is dropped.
+struct Input {
+    data: [u8; INPUT_SIZE],
+    start: usize,
+    len: usize,
+}
+
+impl Input {
+    const fn new() -> Input {
+        Input {
+            data: [0; INPUT_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < INPUT_SIZE {
+            self.data[(self.start + self.len) % INPUT_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.start];
+        self.start = (self.start + 1) % INPUT_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+static INPUT: [Mutex<Input>; VT_COUNT] = [Mutex::new(Input::new()),
+                                          Mutex::new(Input::new()),
+                                          Mutex::new(Input::new()),
+                                          Mutex::new(Input::new()),
+                                          Mutex::new(Input::new()),
+                                          Mutex::new(Input::new())];
+
+fn backing(index: usize) -> *mut VgaChar {
+    unsafe { BACKING[index].as_mut_ptr() }
+}
+
+// Points the terminals that are not shown at their buffers, with the same
+// size of screen as the log terminal.
+pub fn init(framebuffer: Option<Framebuffer>) {
+    for index in 1..VT_COUNT {
+        let mut writer = terminal(index).lock();
+        if let Some(framebuffer) = framebuffer {
+            writer.attach(framebuffer);
+        }
+        writer.cells = unsafe { Unique::new(backing(index)) };
+    }
+}
+
+pub fn terminal(index: usize) -> &'static Mutex<Writer> {
+    match index {
+        0 => &WRITER,
+        index => &OTHERS[index - 1],
+    }
+}
+
+// The index of the terminal on the screen.
+pub fn active() -> usize {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+pub fn switch(index: usize) {
+    if index >= VT_COUNT {
+        return;
+    }
+    int::without_interrupts(|| {
+        let current = active();
+        if index != current {
+            terminal(current).lock().hide(backing(current));
+            terminal(index).lock().show();
+            ACTIVE.store(index, Ordering::Relaxed);
+        }
+    })
+}
+
+pub fn write(index: usize, bytes: &[u8]) {
+    int::without_interrupts(|| terminal(index).lock().write_bytes(bytes))
+}
+
+pub fn with_active<F, T>(f: F) -> T
+    where F: FnOnce(&mut Writer) -> T
+{
+    int::without_interrupts(|| f(&mut terminal(active()).lock()))
+}
+
+// Queues what was typed for the terminal on the screen.
+pub fn input(bytes: &[u8]) {
+    int::without_interrupts(|| {
+        let mut input = INPUT[active()].lock();
+        for &byte in bytes {
+            input.push(byte);
+        }
+    })
+}
+
+// Takes what was typed on terminal `index`, without waiting for more.
+pub fn read_input(index: usize, buf: &mut [u8]) -> usize {
+    int::without_interrupts(|| {
+        let mut input = INPUT[index].lock();
+        let mut count = 0;
+        while count < buf.len() {
+            match input.pop() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    })
+}